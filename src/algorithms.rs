@@ -1,5 +1,22 @@
+use druid::Data;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/*
+Come confrontare il testo OCR con una finestra del capitolo
+*/
+#[derive(Clone, Copy, Data, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MatchStrategy {
+    Linear,    // Parola per parola nella stessa posizione, solo parole lunghe
+    Alignment, // Allineamento tra sequenze di parole, tollera parole aggiunte, perse o storpiate
+}
+
+impl Default for MatchStrategy {
+    fn default() -> Self {
+        MatchStrategy::Linear
+    }
+}
+
 pub struct OcrAlgorithms {}
 
 impl OcrAlgorithms {
@@ -29,35 +46,32 @@ impl OcrAlgorithms {
         (common_words as f64 / total_words as f64) >= tol
     }
 
-    pub fn fuzzy_match(
-        chapter: String,
-        page: String,
-        algorithm: fn(&[String], &[String], f64) -> bool,
-    ) -> Option<usize> {
-        let chapter: Vec<String> = chapter
-            .split(" ")
-            .map(|el| el.chars().filter(|c| c.is_alphabetic()).collect::<String>())
-            .filter(|w| w.len() >= 5)
-            .collect();
-        let page: Vec<String> = page
-            .split(" ")
-            .map(|el| el.chars().filter(|c| c.is_alphabetic()).collect::<String>())
-            .filter(|w| w.len() >= 5)
-            .collect();
-        let mut offset = 0;
-        if page.len() == 0 {
-            return None;
-        };
-        for w in chapter.windows(page.len()) {
-            if algorithm(w, &page, 0.5) {
-                return Some(offset);
-            }
-            offset += 1
-        }
-        None
+    /* Parole di almeno 5 lettere: sono quelle usate per confrontare testo OCR e capitoli */
+    pub fn long_words(text: &str) -> Vec<String> {
+        Self::long_words_at(text)
+            .into_iter()
+            .map(|(_, w)| w)
+            .collect()
     }
 
-    pub fn fuzzy_linear_compare(a: &[String], b: &[String], tol: f64) -> bool {
+    /* Come long_words, ma con la posizione (in byte) di ogni parola nel testo */
+    pub fn long_words_at(text: &str) -> Vec<(usize, String)> {
+        let mut start = 0;
+        text.split(" ")
+            .map(|el| {
+                let pos = start;
+                start += el.len() + 1;
+                (
+                    pos,
+                    el.chars().filter(|c| c.is_alphabetic()).collect::<String>(),
+                )
+            })
+            .filter(|(_, w)| w.len() >= 5)
+            .collect()
+    }
+
+    /* Frazione di parole uguali nella stessa posizione */
+    pub fn fuzzy_linear_score(a: &[String], b: &[String]) -> f64 {
         let mut eq: usize = 0;
 
         for (i, e) in a.iter().enumerate() {
@@ -65,6 +79,73 @@ impl OcrAlgorithms {
                 eq += 1;
             }
         }
-        (eq as f64 / a.len() as f64) >= tol
+        eq as f64 / a.len() as f64
+    }
+
+    /* Tutte le parole, anche brevi, in minuscolo e senza punteggiatura */
+    pub fn all_words(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|el| {
+                el.chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(|c| c.to_lowercase())
+                    .collect::<String>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect()
+    }
+
+    /* Distanza di Levenshtein tra due sequenze con costo di sostituzione a scelta */
+    fn edit_distance<T>(
+        a: &[T],
+        b: &[T],
+        free_text_ends: bool,
+        sub: impl Fn(&T, &T) -> f64,
+    ) -> f64 {
+        let mut prev: Vec<f64> = (0..=a.len())
+            .map(|i| if free_text_ends { 0. } else { i as f64 })
+            .collect();
+        for (j, bj) in b.iter().enumerate() {
+            let mut cur = vec![(j + 1) as f64; a.len() + 1];
+            for (i, ai) in a.iter().enumerate() {
+                cur[i + 1] = (prev[i] + sub(ai, bj))
+                    .min(prev[i + 1] + 1.)
+                    .min(cur[i] + 1.);
+            }
+            prev = cur;
+        }
+        if free_text_ends {
+            prev.into_iter().fold(f64::INFINITY, f64::min)
+        } else {
+            prev[a.len()]
+        }
+    }
+
+    /* Somiglianza tra due parole in base ai caratteri: 1 se uguali, 0 se non hanno niente in comune */
+    pub fn char_similarity(a: &str, b: &str) -> f64 {
+        if a == b {
+            return 1.;
+        }
+        let a = a.chars().collect::<Vec<char>>();
+        let b = b.chars().collect::<Vec<char>>();
+        let max = a.len().max(b.len());
+        if max == 0 {
+            return 1.;
+        }
+        1. - Self::edit_distance(&a, &b, false, |x, y| if x == y { 0. } else { 1. }) / max as f64
+    }
+
+    /*
+    Allineamento semi-globale: la pagina deve essere allineata tutta, il testo del capitolo può avere
+    parole in più all'inizio e alla fine. Sostituire due parole costa quanto sono diverse i loro caratteri,
+    inserire o perdere una parola costa 1
+    */
+    pub fn fuzzy_alignment_score(text: &[String], page: &[String]) -> f64 {
+        if page.len() == 0 {
+            return 0.;
+        }
+        let distance =
+            Self::edit_distance(text, page, true, |a, b| 1. - Self::char_similarity(a, b));
+        (1. - distance / page.len() as f64).max(0.)
     }
 }
@@ -1,20 +1,47 @@
 use crate::book::chapter::Chapter;
-use crate::bookcase::{BookCase, BookInfo};
-use crate::ocr::find_ch;
-use crate::utilities::is_part;
+use crate::bookcase::{BookCase, BookInfo, Duplicate, DuplicateChoice};
+use crate::errors::{log, BookError, Notifications, OcrError, Report, Severity};
+use crate::image_source::{clipboard_image, inbox_images, unread_images, ImageSource};
+use crate::jobs::{JobHandle, JobId, JobKind, JobManager};
+use crate::layout::{LayoutModel, LayoutSettings};
+use crate::ocr::{
+    find_matches, fit_chapter_pages, is_ambiguous, parse_chapter_pages, ChapterPages, OcrData,
+    OcrMatch, PageAnchor, WordIndex,
+};
+use crate::ocr_backend::OcrPage;
+use crate::settings::Settings;
+use crate::utilities::{
+    installed_languages, open_image, open_images, th_lepto_load, th_lepto_load_many,
+};
 use crate::view::view::View;
+use crate::wizard::{OcrWizard, WizardStep};
 use crate::Book;
-use druid::{im::HashSet, im::Vector, Data, ExtEventSink, ImageBuf, Lens, Selector, Target};
-use std::thread;
+use druid::{
+    im::HashMap, im::HashSet, im::Vector, Data, EventCtx, ExtEventSink, ImageBuf, Lens, Selector,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 pub const TRIGGER_ON: Selector<()> = Selector::new("wrapper.focus_on");
 pub const TRIGGER_OFF: Selector<()> = Selector::new("wrapper.focus_off");
 pub const SCROLL_REQUEST: Selector<()> = Selector::new("wrapper.scroll");
-pub const FINISH_SLOW_FUNCTION: Selector<Option<(usize, usize, String)>> =
+pub const FINISH_SLOW_FUNCTION: Selector<(Vec<OcrMatch>, OcrPage)> =
     Selector::new("finish_slow_function");
-pub const FINISH_LEPTO_LOAD: Selector<Option<String>> = Selector::new("leptonica.finish_load");
-pub const FINISH_BOOK_LOAD: Selector<Option<Book>> = Selector::new("book.finish_load");
+pub const FINISH_LEPTO_LOAD: Selector<Result<OcrPage, OcrError>> =
+    Selector::new("leptonica.finish_load");
+pub const FINISH_LEPTO_LOAD_MANY: Selector<Vec<Result<(OcrPage, Option<ImageBuf>), String>>> =
+    Selector::new("leptonica.finish_load_many");
+pub const FINISH_PREPROCESS: Selector<ImageBuf> = Selector::new("leptonica.finish_preprocess");
+pub const FINISH_BOOK_LOAD: Selector<Result<Book, BookError>> = Selector::new("book.finish_load");
 pub const FINISH_IMAGE_LOAD: Selector<(ImageBuf, String)> = Selector::new("image.finish_load");
+pub const FINISH_INDEX: Selector<(String, Arc<WordIndex>)> = Selector::new("book.finish_index");
+pub const FINISH_PAGE_MAPPING: Selector<(String, Result<Vector<usize>, BookError>)> =
+    Selector::new("book.finish_page_mapping");
+pub const OPEN_READER_WINDOW: Selector<BookInfo> = Selector::new("reader.open_window");
+pub const JOB_PROGRESS: Selector<(JobId, f64)> = Selector::new("jobs.progress");
+pub const JOB_DONE: Selector<JobId> = Selector::new("jobs.done");
+pub const CANCEL_JOB: Selector<JobId> = Selector::new("jobs.cancel");
+pub const DISMISS_NOTIFICATION: Selector<usize> = Selector::new("notifications.dismiss");
 
 #[derive(Clone, Data, PartialEq, Copy)]
 pub enum InputMode {
@@ -22,6 +49,8 @@ pub enum InputMode {
     EbookAdd,
     OcrSyn0,
     OcrSyn1,
+    OcrSyn2, // Altre pagine dopo l'allineamento
+    CoverAdd,
     None,
 }
 
@@ -33,7 +62,8 @@ impl Default for InputMode {
 
 #[derive(Default, Clone, Data, Lens)]
 pub struct ApplicationState {
-    pub error_message: Option<String>,
+    pub notifications: Notifications,
+    // errori e avvisi da mostrare all'utente, scritti anche nel file di log
     pub book_to_view: Book,
     pub edit: bool,
     // Serve a switchare da view mode a edit mode
@@ -46,42 +76,512 @@ pub struct ApplicationState {
     pub is_loading: bool,
     pub i_mode: InputMode,
     pub book_to_align: Book,
+    pub meta_edit: Option<usize>,
+    // index of the BookInfo whose metadata are being edited
+    pub meta_backup: BookInfo,
+    pub duplicate: Option<Duplicate>,
+    // book being imported that is already in the library, waiting for the user choice
+    pub readers: HashMap<usize, ReaderWindow>,
+    // books opened in secondary windows, the bookcase is shared
+    pub next_reader: usize,
+    pub settings: Settings,
+    pub settings_open: bool,
+    pub installed_langs: Vector<String>,
+    // lingue di Tesseract trovate nella cartella tessdata langs_tessdata, lette all'apertura delle impostazioni
+    pub langs_tessdata: String,
+    pub ocr_choices: Vector<OcrMatch>,
+    // posizioni OCR troppo simili tra loro, l'utente sceglie quella giusta
+    pub ocr_page: OcrPage,
+    pub page_input: String,
+    pub page_jump: Option<(usize, usize)>,
+    // dialog per saltare a una pagina cartacea aperto, con la prima e l'ultima pagina del libro
+    pub page_jump_error: Option<String>,
+    pub page_anchor: Option<usize>,
+    // dialog per indicare la pagina cartacea in cui inizia l'elemento dato, usa page_input e page_jump_error
+    pub pages_edit: Option<usize>,
+    // indice del BookInfo di cui si stanno correggendo a mano le pagine dei capitoli
+    pub pages_input: Vector<String>,
+    pub pages_edit_error: Option<String>,
+    pub layout_backup: LayoutSettings,
+    pub wizard: OcrWizard,
+    // passo del wizard di allineamento di book_to_align, con le foto caricate
+    pub editions_open: bool,
+    // dialog per scegliere l'edizione cartacea di cui mostrare le pagine
+    pub edition_input: String,
+    pub edition_error: Option<String>,
+    pub inbox_read: HashSet<String>,
+    // foto della cartella in arrivo già passate all'OCR
+    pub jump_opened_book: bool,
+    // il libro è stato aperto dalla libreria solo per saltare alla foto
+    pub jobs: JobManager,
+}
+
+#[derive(Default, Clone, Data, Lens)]
+pub struct ReaderWindow {
+    pub book: Book,
+    pub view: View,
+    pub ocr: OcrData,
+}
+
+impl ReaderWindow {
+    pub fn update_view(&mut self, sink: ExtEventSink) {
+        refresh_view(&mut self.view, &mut self.book, &self.ocr, sink);
+    }
+}
+
+/*
+Lente dallo stato dell'applicazione alla finestra secondaria con l'id dato
+*/
+pub struct ReaderLens(pub usize);
+
+impl Lens<ApplicationState, ReaderWindow> for ReaderLens {
+    fn with<V, F: FnOnce(&ReaderWindow) -> V>(&self, data: &ApplicationState, f: F) -> V {
+        match data.readers.get(&self.0) {
+            Some(reader) => f(reader),
+            // La finestra è in chiusura e il suo stato è già stato rimosso
+            None => f(&ReaderWindow::default()),
+        }
+    }
+
+    fn with_mut<V, F: FnOnce(&mut ReaderWindow) -> V>(
+        &self,
+        data: &mut ApplicationState,
+        f: F,
+    ) -> V {
+        match data.readers.get_mut(&self.0) {
+            Some(reader) => f(reader),
+            None => f(&mut ReaderWindow::default()),
+        }
+    }
+}
+
+/* Stima delle pagine di un capitolo, formattato come durante la lettura */
+pub fn chapter_pages(ch: &Chapter, path: &str, ocr: &OcrData) -> ChapterPages {
+    let mut view = View::new();
+    view.update_view(ch.format(None, None, path));
+    let model = LayoutModel::new(ocr);
+    if model.is_part(&view.current_view) {
+        return ChapterPages {
+            pages: 2,
+            element_pages: vec![(0, 0); view.current_view.len()],
+            part: true,
+        };
+    }
+    let pages = view.guess_lines(&model, 1).unwrap_or(1);
+    ChapterPages {
+        pages,
+        element_pages: view
+            .current_view
+            .iter()
+            .map(|el| match el.pg_offset.0 {
+                0 => (0, 0),
+                _ => {
+                    let (start, end) = View::element_pages(el);
+                    (start.saturating_sub(1), end - 1)
+                }
+            })
+            .collect(),
+        part: false,
+    }
+}
+
+/* Prima pagina cartacea di ogni capitolo del libro, 0 per quelli prima del primo allineato */
+pub fn book_pages(chapters: &Vector<Chapter>, path: &str, ocr: &OcrData) -> Vector<usize> {
+    let (first_chap, first) = match (ocr.first_chap, ocr.first) {
+        (Some(first_chap), Some(first)) => (first_chap, first),
+        _ => return Vector::new(),
+    };
+    let start = ocr.get_mapping(first).map_or(1, |m| m.page);
+    let layouts = chapters
+        .iter()
+        .skip(first_chap)
+        .map(|ch| chapter_pages(ch, path, ocr))
+        .collect::<Vec<ChapterPages>>();
+    let anchors = ocr.anchors.iter().copied().collect::<Vec<PageAnchor>>();
+    (0..first_chap)
+        .map(|_| 0)
+        .chain(fit_chapter_pages(&layouts, first_chap, start, &anchors))
+        .collect()
+}
+
+/*
+Formatta il capitolo corrente e, se il libro è allineato, stima le pagine cartacee
+*/
+pub fn refresh_view(view: &mut View, book: &mut Book, ocr: &OcrData, sink: ExtEventSink) {
+    view.update_view(book.format_current_chapter(sink));
+
+    /* Le pagine dell'editore sono già nei pg_offset messi da Chapter::format */
+    if ocr.publisher_pages {
+        return;
+    }
+    if ocr.is_aligned() {
+        let _ = view.guess_lines(
+            &LayoutModel::new(ocr),
+            book.chapters
+                .get(book.get_ch())
+                .unwrap_or(&Chapter::default())
+                .initial_page,
+        );
+    } else {
+        /* Un'edizione non allineata non mostra le pagine dei segnaposto dell'editore */
+        for el in view.current_view.iter_mut() {
+            el.pg_offset = (0, false);
+        }
+    }
 }
 
 impl ApplicationState {
     pub fn new() -> ApplicationState {
+        /* I problemi trovati all'avvio nella libreria diventano le prime notifiche */
+        let (bookcase, problems) = BookCase::load();
+        let mut notifications = Notifications::new();
+        for problem in problems.iter() {
+            notifications.report(problem);
+        }
         let app = ApplicationState {
-            error_message: None,
+            notifications,
             book_to_view: Book::empty_book(),
             edit: false,
             xml_backup: "".to_string(),
             modified: HashSet::new(),
             view: View::new(),
-            bookcase: BookCase::new(),
+            bookcase,
             is_loading: false,
             i_mode: InputMode::None,
             book_to_align: Book::empty_book(),
+            meta_edit: None,
+            meta_backup: BookInfo::default(),
+            duplicate: None,
+            readers: HashMap::new(),
+            next_reader: 0,
+            settings: Settings::load(),
+            settings_open: false,
+            installed_langs: Vector::new(),
+            langs_tessdata: String::new(),
+            inbox_read: HashSet::new(),
+            jump_opened_book: false,
+            jobs: JobManager::new(),
+            ocr_choices: Vector::new(),
+            ocr_page: OcrPage::default(),
+            page_input: String::new(),
+            page_jump: None,
+            page_jump_error: None,
+            page_anchor: None,
+            pages_edit: None,
+            pages_input: Vector::new(),
+            pages_edit_error: None,
+            layout_backup: LayoutSettings::default(),
+            wizard: OcrWizard::new(),
+            editions_open: false,
+            edition_input: String::new(),
+            edition_error: None,
         };
         //app.update_view();
         app
     }
 
+    pub fn notify(&mut self, severity: Severity, message: &str) {
+        self.notifications.push(severity, message);
+    }
+
+    pub fn report(&mut self, error: &impl Report) {
+        self.notifications.report(error);
+    }
+
+    pub fn open_settings(&mut self) {
+        self.settings_open = true;
+        self.refresh_languages(true);
+    }
+
+    /* Scorrere le cartelle tessdata è lento: si rifà solo se il percorso è cambiato */
+    pub fn refresh_languages(&mut self, force: bool) {
+        if force || self.langs_tessdata != self.settings.tessdata_path {
+            self.installed_langs = installed_languages(&self.settings).into_iter().collect();
+            self.langs_tessdata = self.settings.tessdata_path.clone();
+        }
+    }
+
+    /* Salva meta.json, se non ci si riesce l'utente lo deve sapere: le modifiche andrebbero perse */
+    pub fn save_library(&mut self) {
+        if let Err(e) = self.bookcase.update_meta() {
+            self.report(&e);
+        }
+    }
+
     pub fn update_view(&mut self, sink: ExtEventSink) {
-        self.view
-            .update_view(self.book_to_view.format_current_chapter(sink));
+        let ocr = self.get_current_book_info().ocr;
+        refresh_view(&mut self.view, &mut self.book_to_view, &ocr, sink);
+    }
+
+    /*
+    Salta alla pagina cartacea page: il capitolo è l'ultimo che comincia prima di page,
+    l'elemento il primo che ci sta sopra secondo la stima di guess_lines
+    */
+    pub fn go_to_paper_page(&mut self, page: usize, sink: ExtEventSink) -> Result<(), String> {
+        if !self.get_current_book_info().ocr.has_pages() {
+            return Err("The book is not aligned with a paper book".to_string());
+        }
+        let ch = self
+            .book_to_view
+            .chapters
+            .iter()
+            .enumerate()
+            .filter(|(_, ch)| ch.initial_page != 0 && ch.initial_page <= page)
+            .last()
+            .map(|(id, _)| id)
+            .ok_or(format!(
+                "Page {} comes before the first aligned chapter",
+                page
+            ))?;
 
+        self.book_to_view.get_mut_nav().set_ch(ch);
+        self.update_view(sink);
+        /* Se la stima del capitolo finisce prima di page restiamo sull'ultimo elemento */
+        let element = self
+            .view
+            .element_of_page(page)
+            .unwrap_or(self.view.current_view.len().saturating_sub(1));
+        self.book_to_view.get_mut_nav().set_element_number(element);
+        self.view.selected = Some(element);
+        Ok(())
+    }
+
+    /*
+    Prima e ultima pagina cartacea del libro, l'ultima stimata con guess_lines sull'ultimo capitolo
+    allineato oppure letta dai segnaposto dell'editore
+    */
+    pub fn paper_page_range(&self) -> Option<(usize, usize)> {
         let ocr = self.get_current_book_info().ocr;
-        if ocr.is_aligned() {
-            let _ = self.view.guess_lines(
-                ocr.get_avg_ch(),
-                ocr.get_first_page_lines(),
-                ocr.get_other_page_lines(),
-                self.book_to_view
-                    .chapters
-                    .get(self.book_to_view.get_ch())
-                    .unwrap_or(&Chapter::default())
-                    .initial_page,
-            );
+        if !ocr.has_pages() {
+            return None;
+        }
+        let chapters = &self.book_to_view.chapters;
+        let first = chapters
+            .iter()
+            .map(|ch| ch.initial_page)
+            .filter(|page| *page != 0)
+            .min()?;
+        let last_ch = chapters.iter().filter(|ch| ch.initial_page != 0).last()?;
+
+        let mut view = View::new();
+        view.update_view(last_ch.format(None, None, &self.book_to_view.path));
+        if ocr.publisher_pages {
+            return Some((first, view.last_page().unwrap_or(last_ch.initial_page)));
+        }
+        let model = LayoutModel::new(&ocr);
+        let pages = if model.is_part(&view.current_view) {
+            1
+        } else {
+            view.guess_lines(&model, last_ch.initial_page).unwrap_or(1)
+        };
+        Some((first, last_ch.initial_page + pages - 1))
+    }
+
+    pub fn open_page_jump(&mut self) {
+        match self.paper_page_range() {
+            Some(range) => {
+                self.page_input = String::new();
+                self.page_jump_error = None;
+                self.page_jump = Some(range);
+            }
+            None => self.notify(Severity::Info, "The book is not aligned with a paper book"),
+        }
+    }
+
+    pub fn confirm_page_jump(&mut self, sink: ExtEventSink) {
+        let (first, last) = match self.page_jump {
+            Some(range) => range,
+            None => return,
+        };
+        let result = match self.page_input.trim().parse::<usize>() {
+            Ok(page) if page < first || page > last => Err(format!(
+                "The paper book goes from page {} to page {}",
+                first, last
+            )),
+            Ok(page) => self.go_to_paper_page(page, sink),
+            Err(_) => Err(format!("'{}' is not a page number", self.page_input.trim())),
+        };
+        match result {
+            Ok(()) => self.page_jump = None,
+            Err(e) => self.page_jump_error = Some(e),
+        }
+    }
+
+    pub fn open_page_anchor(&mut self) {
+        let element = match self.view.selected {
+            Some(element) if self.get_current_book_info().ocr.is_aligned() => element,
+            _ => return,
+        };
+        self.page_input = match self.view.page_range(element, element) {
+            Some((start, _)) => start.to_string(),
+            None => String::new(),
+        };
+        self.page_jump_error = None;
+        self.page_anchor = Some(element);
+    }
+
+    pub fn confirm_page_anchor(&mut self, sink: ExtEventSink) {
+        let element = match self.page_anchor {
+            Some(element) => element,
+            None => return,
+        };
+        let result = match self.page_input.trim().parse::<usize>() {
+            Ok(page) if page > 0 => {
+                let anchor = PageAnchor {
+                    ch: self.book_to_view.get_nav().get_ch(),
+                    element,
+                    page,
+                    manual: true,
+                };
+                self.add_page_anchor(anchor, sink)
+            }
+            _ => Err(format!("'{}' is not a page number", self.page_input.trim())),
+        };
+        match result {
+            Ok(()) => self.page_anchor = None,
+            Err(e) => self.page_jump_error = Some(e),
+        }
+    }
+
+    pub fn edit_page_mapping(&mut self, id: usize) {
+        self.pages_input = self.bookcase.library[id]
+            .mapped_pages
+            .iter()
+            .map(|page| page.to_string())
+            .collect();
+        self.pages_edit_error = None;
+        self.layout_backup = self.bookcase.library[id].ocr.layout;
+        self.pages_edit = Some(id);
+    }
+
+    pub fn discard_page_mapping(&mut self) {
+        if let Some(id) = self.pages_edit.take() {
+            self.bookcase.library[id].ocr.layout = self.layout_backup;
+        }
+    }
+
+    /*
+    Salva le prime pagine corrette a mano: ogni capitolo cambiato diventa un'ancora manuale
+    sul suo primo elemento, così i ricalcoli successivi la rispettano. Se è cambiata
+    l'impaginazione si ricalcolano tutte le pagine, in background perché va formattato ogni capitolo
+    */
+    pub fn save_page_mapping(&mut self, sink: ExtEventSink) {
+        let id = match self.pages_edit {
+            Some(id) => id,
+            None => return,
+        };
+        let inputs = self.pages_input.iter().cloned().collect::<Vec<String>>();
+        let pages = match parse_chapter_pages(&inputs) {
+            Ok(pages) => pages,
+            Err(e) => {
+                self.pages_edit_error = Some(e);
+                return;
+            }
+        };
+        let book_info = &mut self.bookcase.library[id];
+        for (ch, page) in pages.iter().enumerate() {
+            if *page != 0 && book_info.mapped_pages.get(ch) != Some(page) {
+                book_info.ocr.add_anchor(PageAnchor {
+                    ch,
+                    element: 0,
+                    page: *page,
+                    manual: true,
+                });
+            }
+        }
+        book_info.mapped_pages = pages.into_iter().collect();
+        if book_info.ocr.is_aligned() && book_info.ocr.layout != self.layout_backup {
+            th_book_pages(&mut self.jobs, sink.clone(), book_info.clone());
+        }
+        self.save_library();
+        self.show_book_pages(id, sink);
+        self.pages_edit = None;
+    }
+
+    /* Pagine ricalcolate in background, il libro potrebbe essere stato rimosso nel frattempo */
+    pub fn finish_page_mapping(&mut self, path: &str, pages: Vector<usize>, sink: ExtEventSink) {
+        if let Some(id) = self.bookcase.library.iter().position(|b| b.path == path) {
+            self.bookcase.library[id].mapped_pages = pages;
+            self.save_library();
+            self.show_book_pages(id, sink);
+        }
+    }
+
+    /*
+    Il libro id, nel lettore e nelle finestre secondarie in cui è aperto, prende le prime pagine
+    dei capitoli e i dati OCR salvati nella libreria
+    */
+    fn show_book_pages(&mut self, id: usize, sink: ExtEventSink) {
+        let book_info = self.bookcase.library[id].clone();
+        for reader in self.readers.values_mut() {
+            if reader.book.get_path() == book_info.path {
+                reader.book.set_initial_pages(&book_info.mapped_pages);
+                reader.ocr = book_info.ocr.clone();
+                reader.update_view(sink.clone());
+            }
+        }
+        if self.book_to_view.get_path() == book_info.path {
+            self.book_to_view.set_initial_pages(&book_info.mapped_pages);
+            self.update_view(sink);
+        }
+    }
+
+    pub fn open_editions(&mut self) {
+        self.edition_input = String::new();
+        self.edition_error = None;
+        self.editions_open = true;
+    }
+
+    /* Il libro aperto, anche nelle altre finestre, prende le prime pagine dell'edizione selezionata */
+    fn apply_edition(&mut self, sink: ExtEventSink) {
+        self.save_library();
+        let path = self.get_current_book_info().path;
+        if let Some(id) = self.bookcase.library.iter().position(|b| b.path == path) {
+            self.show_book_pages(id, sink);
+        }
+    }
+
+    pub fn select_edition(&mut self, name: &str, sink: ExtEventSink) {
+        let result = match self.get_mut_current_book_info() {
+            Some(book_info) => book_info.select_edition(name),
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                self.edition_error = None;
+                self.apply_edition(sink);
+            }
+            Err(e) => self.edition_error = Some(e),
+        }
+    }
+
+    pub fn add_edition(&mut self, sink: ExtEventSink) {
+        let name = self.edition_input.clone();
+        let result = match self.get_mut_current_book_info() {
+            Some(book_info) => book_info.add_edition(&name),
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                self.edition_input = String::new();
+                self.edition_error = None;
+                self.apply_edition(sink);
+            }
+            Err(e) => self.edition_error = Some(e),
+        }
+    }
+
+    pub fn remove_edition(&mut self, name: &str) {
+        let result = match self.get_mut_current_book_info() {
+            Some(book_info) => book_info.remove_edition(name),
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                self.edition_error = None;
+                self.save_library();
+            }
+            Err(e) => self.edition_error = Some(e),
         }
     }
 
@@ -90,24 +590,175 @@ impl ApplicationState {
     }
 
     pub fn close_current_book(&mut self) {
-        for book_info in self.bookcase.library.iter_mut() {
-            if book_info.get_path().to_str().unwrap() == self.book_to_view.get_path() {
-                book_info.start_chapter = self.book_to_view.get_nav().get_ch();
-                book_info.start_element_number = self.book_to_view.get_nav().get_element_numer();
-                break;
-            }
+        if let Err(e) = self
+            .bookcase
+            .save_position(&self.book_to_view.get_path(), self.book_to_view.get_nav())
+        {
+            self.report(&e);
         }
-        self.bookcase.update_meta();
         self.book_to_view = Book::empty_book();
     }
 
+    pub fn open_reader(&mut self, book: Book, ocr: OcrData, sink: ExtEventSink) -> usize {
+        let id = self.next_reader;
+        self.next_reader += 1;
+        let mut reader = ReaderWindow {
+            book,
+            view: View::new(),
+            ocr,
+        };
+        reader.update_view(sink);
+        self.readers.insert(id, reader);
+        id
+    }
+
+    pub fn close_reader(&mut self, id: usize) {
+        if let Some(reader) = self.readers.remove(&id) {
+            if let Err(e) = self
+                .bookcase
+                .save_position(&reader.book.get_path(), reader.book.get_nav())
+            {
+                self.report(&e);
+            }
+        }
+    }
+
     pub fn set_book_to_read(&mut self, book: Book) {
         self.book_to_view = book;
+        self.import_print_pages();
         //self.is_loading = false;
     }
 
+    /*
+    Un libro mai allineato prende le pagine cartacee indicate dall'editore nell'epub, a meno che
+    l'utente non abbia già creato più edizioni
+    */
+    fn import_print_pages(&mut self) {
+        let pages = self.book_to_view.print_pages.clone();
+        if pages.is_empty() {
+            return;
+        }
+        match self.get_mut_current_book_info() {
+            Some(info) if info.mapped_pages.is_empty() && info.editions.is_empty() => {
+                info.mapped_pages = pages;
+                info.ocr.publisher_pages = true;
+            }
+            _ => return,
+        }
+        self.save_library();
+    }
+
     pub fn set_book_to_align(&mut self, book: Book) {
         self.book_to_align = book;
+        self.wizard = OcrWizard::new();
+    }
+
+    /*
+    Conferma il passo di controllo: numero di pagina e righe corretti dall'utente vanno nel
+    Mapping della foto. Con la pagina piena il libro è allineato
+    */
+    pub fn wizard_confirm(&mut self) {
+        let (page, lines) = match self.wizard.check_inputs() {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                self.wizard.error = Some(e);
+                return;
+            }
+        };
+        let mapping = match self.wizard.current_page() {
+            Some(current) => current.mapping,
+            None => return,
+        };
+        let step = self.wizard.step;
+        let book_info = match self.get_mut_current_book_info() {
+            Some(book_info) => book_info,
+            None => return,
+        };
+        if let Some(m) = book_info.ocr.mappings.get_mut(mapping) {
+            m.page_lines = lines;
+            if step == WizardStep::CheckFirst {
+                m.page = page;
+            }
+        }
+        if step == WizardStep::CheckOther {
+            let _ = self.map_pages(true);
+            self.save_library();
+        }
+        self.wizard.next();
+    }
+
+    /* Torna al passo precedente, togliendo il Mapping della foto scartata */
+    pub fn wizard_back(&mut self) {
+        let discarded = self.wizard.back();
+        let step = self.wizard.step;
+        let ocr = match self.get_mut_current_book_info() {
+            Some(book_info) => &mut book_info.ocr,
+            None => return,
+        };
+        if let Some(id) = discarded {
+            if id < ocr.mappings.len() {
+                ocr.mappings.remove(id);
+            }
+            if ocr.first == Some(id) {
+                ocr.first = None;
+                ocr.first_chap = None;
+            }
+            if ocr.other == Some(id) {
+                ocr.other = None;
+            }
+        }
+        /* Tornando al controllo della prima pagina si ritrovano i valori confermati */
+        if step == WizardStep::CheckFirst {
+            if let Some(first) = ocr.first.and_then(|id| ocr.mappings.get(id).copied()) {
+                self.wizard.page_input = first.page.to_string();
+                self.wizard.lines_input = first.page_lines.to_string();
+            }
+        }
+    }
+
+    /* Chiude il wizard: se il libro non è ancora allineato le foto caricate vengono scartate */
+    pub fn close_wizard(&mut self) {
+        let done = matches!(self.wizard.step, WizardStep::Samples | WizardStep::Done);
+        if !done {
+            if let Some(book_info) = self.get_mut_current_book_info() {
+                book_info.ocr = OcrData::new();
+            }
+        }
+        self.jobs.cancel_kind(JobKind::Ocr);
+        self.book_to_align = Book::empty_book();
+        self.wizard = OcrWizard::new();
+    }
+
+    pub fn edit_metadata(&mut self, id: usize) {
+        self.meta_backup = self.bookcase.library[id].clone();
+        self.meta_edit = Some(id);
+    }
+
+    pub fn discard_metadata(&mut self) {
+        if let Some(id) = self.meta_edit.take() {
+            self.bookcase.library[id] = self.meta_backup.clone();
+        }
+    }
+
+    pub fn save_metadata(&mut self) -> Result<(), String> {
+        let id = match self.meta_edit {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let book_info = &mut self.bookcase.library[id];
+        book_info.save_metadata().map_err(|e| e.to_string())?;
+        book_info.refresh_metadata()?;
+        self.meta_edit = None;
+        self.save_library();
+        Ok(())
+    }
+
+    pub fn resolve_duplicate(&mut self, choice: Option<DuplicateChoice>) {
+        if let (Some(duplicate), Some(choice)) = (self.duplicate.take(), choice) {
+            if let Err(e) = self.bookcase.resolve_duplicate(duplicate, choice) {
+                self.report(&e);
+            }
+        }
     }
 
     pub fn map_pages(&mut self, overwrite: bool) -> Result<(), usize> {
@@ -115,44 +766,66 @@ impl ApplicationState {
 
         let chapters = self.book_to_align.chapters.clone();
         let path = (*self).book_to_align.path.clone();
-        let book_info = self.get_mut_current_book_info().unwrap();
+        let book_info = match self.get_mut_current_book_info() {
+            Some(book_info) => book_info,
+            None => return Ok(()),
+        };
+        if !overwrite
+            && !book_info.ocr.publisher_pages
+            && book_info.mapped_pages.len() == chapters.len()
+        {
+            return Ok(());
+        }
+        /* Le foto sono di un'edizione precisa: sostituiscono le pagine dell'editore */
+        book_info.ocr.publisher_pages = false;
+        book_info.mapped_pages = book_pages(&chapters, &path, &book_info.ocr);
+        return Ok(());
+    }
+
+    /*
+    Una pagina riconosciuta durante la lettura diventa un'ancora: si ricalcolano solo i capitoli
+    tra le ancore vicine e si aggiornano le pagine del libro aperto
+    */
+    pub fn add_page_anchor(
+        &mut self,
+        anchor: PageAnchor,
+        sink: ExtEventSink,
+    ) -> Result<(), String> {
+        let chapters = self.book_to_view.chapters.clone();
+        let path = self.book_to_view.path.clone();
+        let book_info = self
+            .get_mut_current_book_info()
+            .ok_or("The book is not in the library")?;
+        if book_info.mapped_pages.len() != chapters.len() {
+            return Err("The book is not aligned with a paper book".to_string());
+        }
+        let first_chap = book_info.ocr.first_chap;
+        if first_chap.map_or(true, |first| anchor.ch < first) {
+            return Err("This chapter comes before the first aligned chapter".to_string());
+        }
+        let (first, last) = book_info.ocr.add_anchor(anchor);
+        let last = last.unwrap_or(chapters.len() - 1);
         let ocr = book_info.ocr.clone();
-        if overwrite {
-            book_info.mapped_pages = Vector::new();
-        }
-        let mut page = ocr.get_mapping(ocr.first.unwrap()).unwrap().page;
-        let mut temp_view = View::new();
-        for (id, ch) in chapters.iter().enumerate() {
-            if id + 1 <= book_info.mapped_pages.len() {
-                continue;
-            }
-            if ocr.first_chap.unwrap() <= id {
-                temp_view.update_view(ch.format(None, None, &path));
-                if is_part(temp_view.current_view.clone()) {
-                    if page % 2 == 0 {
-                        page += 1;
-                        book_info.mapped_pages.push_back(page);
-                        page += 2;
-                    } else {
-                        book_info.mapped_pages.push_back(page);
-                        page += 2;
-                    }
-                } else {
-                    book_info.mapped_pages.push_back(page);
-                    page += temp_view
-                        .guess_lines(
-                            ocr.get_avg_ch(),
-                            ocr.get_first_page_lines(),
-                            ocr.get_other_page_lines(),
-                            0,
-                        )
-                        .unwrap();
-                }
-            } else {
-                book_info.mapped_pages.push_back(0);
+        let layouts = chapters
+            .iter()
+            .skip(first)
+            .take(last + 1 - first)
+            .map(|ch| chapter_pages(ch, &path, &ocr))
+            .collect::<Vec<ChapterPages>>();
+        let anchors = ocr.anchors.iter().copied().collect::<Vec<PageAnchor>>();
+        let pages = fit_chapter_pages(&layouts, first, book_info.mapped_pages[first], &anchors);
+        for (i, page) in pages.iter().enumerate() {
+            book_info.mapped_pages.set(first + i, *page);
+        }
+        self.save_library();
+
+        for (i, page) in pages.into_iter().enumerate() {
+            if let Some(ch) = self.book_to_view.chapters.get_mut(first + i) {
+                ch.initial_page = page;
             }
         }
-        return Ok(());
+        self.update_view(sink);
+        Ok(())
     }
 
     pub fn get_current_book_info(&self) -> BookInfo {
@@ -191,29 +864,334 @@ impl ApplicationState {
         None
     }
 
-    pub fn ocr_jump(&mut self, sink: ExtEventSink, str: String) {
+    /*
+    Chiede le foto per l'OCR alla sorgente scelta nelle impostazioni. Solo il file dialog risponde
+    più tardi, con OPEN_FILE o OPEN_FILES
+    */
+    pub fn request_ocr_images(&mut self, ctx: &mut EventCtx, mode: InputMode) {
+        self.i_mode = mode;
+        let many = mode == InputMode::OcrSyn2;
+        let images = match self.settings.image_source {
+            ImageSource::Dialog => {
+                let options = if many { open_images() } else { open_image() };
+                ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(options));
+                return;
+            }
+            ImageSource::Inbox => match self.settings.inbox() {
+                Some(folder) => match inbox_images(folder) {
+                    Ok(images) => match unread_images(images, &self.inbox_read, many) {
+                        images if images.is_empty() => {
+                            Err(format!("No new photos in {}", folder.display()))
+                        }
+                        images => Ok(images),
+                    },
+                    Err(e) => Err(format!("Couldn't read {}: {}", folder.display(), e)),
+                },
+                None => Err("Choose the inbox folder in the settings".to_string()),
+            },
+            ImageSource::Clipboard => clipboard_image()
+                .map(|path| vec![path])
+                .map_err(|e| e.to_string()),
+        };
+        match images {
+            Ok(images) => self.load_ocr_images(images, ctx.get_external_handle()),
+            Err(e) => self.ocr_failed(Some(OcrError::Source(e))),
+        }
+    }
+
+    /*
+    Una foto appena arrivata nella cartella viene usata subito se qualcosa la aspetta: il wizard
+    fermo su una foto da caricare oppure il libro aperto, per saltare alla pagina
+    */
+    pub fn inbox_arrived(&mut self, images: Vec<PathBuf>, sink: ExtEventSink) {
+        let busy = self.is_loading || self.wizard.loading || !self.ocr_choices.is_empty();
+        if busy || self.i_mode != InputMode::None {
+            return;
+        }
+        let mode = if !self.book_to_align.is_empty() {
+            match self.wizard.step {
+                WizardStep::FirstPage => InputMode::OcrSyn0,
+                WizardStep::OtherPage => InputMode::OcrSyn1,
+                WizardStep::Samples => InputMode::OcrSyn2,
+                _ => return,
+            }
+        } else if !self.book_to_view.is_empty() && !self.edit {
+            InputMode::OcrJump
+        } else {
+            return;
+        };
+        /* Le foto già prese a mano dalla cartella non vanno rilette */
+        let images = unread_images(images, &self.inbox_read, mode == InputMode::OcrSyn2);
+        if images.is_empty() {
+            return;
+        }
+        if mode == InputMode::OcrJump {
+            self.jump_opened_book = false;
+            self.is_loading = true;
+        }
+        self.i_mode = mode;
+        self.load_ocr_images(images, sink);
+    }
+
+    /* Passa le foto all'OCR secondo i_mode */
+    pub fn load_ocr_images(&mut self, images: Vec<PathBuf>, sink: ExtEventSink) {
+        if self.settings.image_source == ImageSource::Inbox {
+            for image in images.iter() {
+                self.inbox_read.insert(image.to_string_lossy().to_string());
+            }
+        }
+        let lang = self.get_current_book_info().language.clone();
         match self.i_mode {
-            InputMode::OcrJump => th_find(str.clone(), sink, self.book_to_view.chapters.clone()),
-            InputMode::OcrSyn1 | InputMode::OcrSyn0 => {
-                th_find(str.clone(), sink, self.book_to_align.chapters.clone())
+            InputMode::OcrSyn2 => {
+                self.wizard.start_loading();
+                th_lepto_load_many(&mut self.jobs, sink, images, &lang, self.settings.clone());
+            }
+            InputMode::OcrJump | InputMode::OcrSyn0 | InputMode::OcrSyn1 => {
+                if self.i_mode != InputMode::OcrJump {
+                    self.wizard.start_loading();
+                }
+                if let Some(image) = images.last() {
+                    let settings = self.settings.clone();
+                    th_lepto_load(&mut self.jobs, sink, image.clone(), &lang, settings);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /*
+    Foto non letta, non trovata nel libro o scelta annullata. Il libro aperto dalla libreria solo
+    per saltare alla foto si richiude
+    */
+    pub fn ocr_failed(&mut self, error: Option<OcrError>) {
+        if self.i_mode == InputMode::OcrJump {
+            if let Some(e) = error {
+                self.report(&e);
+            }
+            if self.jump_opened_book {
+                self.book_to_view = Book::empty_book();
             }
-            _ => (),
+            self.is_loading = false;
+        } else {
+            self.wizard.loading = false;
+            self.wizard.preview = None;
+            /* Nel wizard l'errore si vede sotto il passo corrente, qui finisce solo nel log */
+            if let Some(e) = error {
+                log(e.severity(), &e.to_string());
+                self.wizard.error = Some(e.to_string());
+            }
+        }
+        self.i_mode = InputMode::None;
+    }
+
+    pub fn ocr_jump(&mut self, sink: ExtEventSink, page: OcrPage) {
+        let settings = self.settings.clone();
+        let book = match self.i_mode {
+            InputMode::OcrJump => &self.book_to_view,
+            InputMode::OcrSyn1 | InputMode::OcrSyn0 => &self.book_to_align,
+            _ => return,
+        };
+        th_find(&mut self.jobs, page, sink, book, settings);
+    }
+
+    /*
+    Annullamento dall'elenco dei lavori: il thread si ferma al prossimo controllo, intanto
+    l'interfaccia torna come prima della richiesta
+    */
+    pub fn cancel_job(&mut self, id: JobId) {
+        match self.jobs.cancel(id) {
+            Some(JobKind::LoadBook) => self.is_loading = false,
+            Some(JobKind::Ocr) => self.ocr_failed(None),
+            Some(JobKind::Pages) | None => {}
+        }
+    }
+
+    /*
+    Risultato della ricerca OCR: si salta direttamente solo se la posizione migliore non è in dubbio
+    */
+    pub fn ocr_matches(&mut self, matches: Vec<OcrMatch>, page: OcrPage, sink: ExtEventSink) {
+        if matches.is_empty() {
+            self.ocr_failed(Some(OcrError::NoMatch));
+        } else if is_ambiguous(&matches) {
+            self.ocr_choices = matches.into_iter().collect();
+            self.ocr_page = page;
+        } else {
+            self.apply_ocr_match(matches[0].ch, matches[0].offset, page, sink);
+        }
+        self.is_loading = false;
+    }
+
+    pub fn choose_ocr_match(&mut self, choice: Option<usize>, sink: ExtEventSink) {
+        let choices = std::mem::take(&mut self.ocr_choices);
+        let page = std::mem::take(&mut self.ocr_page);
+        match choice.and_then(|i| choices.get(i)) {
+            Some(choice) => self.apply_ocr_match(choice.ch, choice.offset, page, sink),
+            None => self.ocr_failed(None),
         }
     }
+
+    /*
+    Altre foto di pagine per un libro già allineato: ogni pagina riconosciuta diventa un Mapping
+    in più, mostrato nel wizard, e la mappatura delle pagine viene ricalcolata una volta sola
+    */
+    pub fn ocr_log_pages(&mut self, pages: Vec<Result<(OcrPage, Option<ImageBuf>), String>>) {
+        let total = pages.len();
+        let mut failure = None;
+        let ocr = match self.get_mut_current_book_info() {
+            Some(book_info) => &mut book_info.ocr,
+            None => return self.ocr_failed(None),
+        };
+        let accepted = pages
+            .into_iter()
+            .filter_map(|page| match page {
+                Ok((page, preview)) => ocr.ocr_log(&page).ok().map(|id| (page.text, id, preview)),
+                Err(e) => {
+                    failure.get_or_insert(e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let added = accepted.len();
+        for (text, id, preview) in accepted {
+            self.wizard.sample_read(&text, id, preview);
+        }
+        if added > 0 {
+            let _ = self.map_pages(true);
+            self.save_library();
+        }
+        self.wizard.loading = false;
+        if added < total {
+            self.wizard.error = Some(match failure {
+                Some(e) => format!("{} of {} pages could not be used: {}", total - added, total, e),
+                None => format!(
+                    "{} of {} pages could not be used, please try again with better quality images.",
+                    total - added,
+                    total
+                ),
+            });
+        }
+        self.i_mode = InputMode::None;
+    }
+
+    fn apply_ocr_match(&mut self, ch: usize, off: usize, page: OcrPage, sink: ExtEventSink) {
+        match self.i_mode {
+            InputMode::OcrJump => {
+                self.book_to_view.get_mut_nav().set_ch(ch);
+                self.update_view(sink.clone());
+
+                let element = self.view.ocr_offset_to_element(off);
+                self.book_to_view.get_mut_nav().set_element_number(element);
+
+                if let Some(book_info) = self
+                    .get_mut_current_book_info()
+                    .filter(|book_info| book_info.ocr.is_aligned())
+                {
+                    let ocr = &mut book_info.ocr;
+                    if let Ok(id) = ocr.ocr_log(&page) {
+                        /* Il numero letto sulla foto corregge la stima delle pagine */
+                        let printed = ocr.mappings[id].page;
+                        if printed != 0 {
+                            let anchor = PageAnchor {
+                                ch,
+                                element,
+                                page: printed,
+                                manual: false,
+                            };
+                            if let Err(e) = self.add_page_anchor(anchor, sink) {
+                                self.notify(Severity::Warning, &e);
+                            }
+                        }
+                    }
+                }
+            }
+            InputMode::OcrSyn0 | InputMode::OcrSyn1 => {
+                let first = self.i_mode == InputMode::OcrSyn0;
+                let ocr = match self.get_mut_current_book_info() {
+                    Some(book_info) => &mut book_info.ocr,
+                    None => return self.ocr_failed(None),
+                };
+                let result = if first {
+                    ocr.ocr_log_first(&page, ch).map(|_| ocr.first)
+                } else {
+                    ocr.ocr_log_other(&page).map(|_| ocr.other)
+                };
+                match result {
+                    Ok(Some(id)) => {
+                        let mapping = ocr.mappings[id];
+                        self.wizard
+                            .page_read(&page.text, id, mapping.page, mapping.page_lines);
+                    }
+                    _ => self.wizard.fail(
+                        "Image not recognized, please try with another photo of the page."
+                            .to_string(),
+                    ),
+                }
+            }
+            _ => {}
+        }
+        self.i_mode = InputMode::None;
+    }
+}
+
+/*
+L'indice viene ricalcolato nel thread solo se il libro è stato modificato dopo il caricamento
+*/
+/*
+Un nuovo ricalcolo sostituisce quello ancora in corso, che lavorava su pagine vecchie
+*/
+fn th_book_pages(jobs: &mut JobManager, sink: ExtEventSink, book_info: BookInfo) {
+    jobs.cancel_kind(JobKind::Pages);
+    jobs.spawn(JobKind::Pages, "Estimating paper pages", sink, move |job| {
+        estimate_pages(job, book_info)
+    });
 }
 
-fn th_find(str: String, sink: ExtEventSink, vec: Vector<Chapter>) {
-    thread::spawn(move || match find_ch(str.clone(), vec) {
-        Some((index, offset)) => sink
-            .submit_command(
-                FINISH_SLOW_FUNCTION,
-                Option::Some((index, offset, str)),
-                Target::Auto,
-            )
-            .expect("command failed to submit"),
+fn estimate_pages(job: &JobHandle, book_info: BookInfo) {
+    let pages = Book::new(book_info.get_path(), 0, 0, &book_info.mapped_pages)
+        .map(|book| book_pages(&book.chapters, &book.path, &book_info.ocr));
+    job.submit(FINISH_PAGE_MAPPING, (book_info.path, pages));
+}
+
+fn th_find(
+    jobs: &mut JobManager,
+    page: OcrPage,
+    sink: ExtEventSink,
+    book: &Book,
+    settings: Settings,
+) {
+    let path = book.get_path();
+    let chapters = book.chapters.clone();
+    let index = book.index.clone();
+    jobs.spawn(JobKind::Ocr, "Looking for the page", sink, move |job| {
+        find(job, page, path, chapters, index, settings)
+    });
+}
+
+fn find(
+    job: &JobHandle,
+    page: OcrPage,
+    path: String,
+    chapters: Vector<Chapter>,
+    index: Option<Arc<WordIndex>>,
+    settings: Settings,
+) {
+    let index = match index {
+        Some(index) => index,
         None => {
-            sink.submit_command(FINISH_SLOW_FUNCTION, Option::None, Target::Auto)
-                .expect("command failed to submit");
+            let index = Arc::new(WordIndex::new(&chapters));
+            job.submit(FINISH_INDEX, (path, index.clone()));
+            job.progress(1, 2);
+            index
         }
-    });
+    };
+    if job.is_cancelled() {
+        return;
+    }
+    let matches = find_matches(
+        page.text.clone(),
+        &index,
+        settings.match_threshold,
+        settings.match_strategy,
+    );
+    job.submit(FINISH_SLOW_FUNCTION, (matches, page));
 }
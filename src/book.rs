@@ -1,20 +1,21 @@
 pub mod chapter;
-mod epub_text;
+pub(crate) mod epub_text;
+pub(crate) mod opf;
 pub(crate) mod page_element;
 
 use crate::book::chapter::Chapter;
+use crate::book::opf::read_page_list;
 use crate::book::page_element::PageElement;
+use crate::errors::BookError;
+use crate::ocr::WordIndex;
+use crate::utilities::rewrite_epub;
 use druid::im::{HashMap, HashSet};
 use druid::{im::Vector, Data, ExtEventSink, ImageBuf, Lens};
 use epub::doc::EpubDoc;
-use std::env::current_dir;
+use rayon::prelude::*;
 use std::error::Error;
-use std::fs::{OpenOptions};
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use std::{fmt, fs};
-use zip::write::FileOptions;
-use zip::ZipWriter;
+use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Default, Debug, Clone, Data, Lens)]
 pub struct Navigation {
@@ -50,6 +51,8 @@ pub struct Book {
     pub path: String, // Nel file system
     pub chapters: Vector<Chapter>,
     pub imgs: HashMap<String, ImageBuf>,
+    pub index: Option<Arc<WordIndex>>, // Indice delle parole per la ricerca OCR, None se da ricalcolare
+    pub print_pages: Vector<usize>, // Prime pagine dei capitoli secondo l'editore, vuoto se l'epub non le indica
 }
 
 impl Book {
@@ -66,47 +69,68 @@ impl Book {
         init_chapter: usize,
         init_element_number: usize,
         page_chapter: &Vector<usize>,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, BookError> {
         // Apriamo come EpubDoc il file passato
-        let book_path = path
-            .as_ref()
-            .to_path_buf()
-            .into_os_string()
-            .into_string()
-            .map_err(|_e| fmt::Error::default())?;
+        let book_path = path.as_ref().to_string_lossy().to_string();
 
-        let mut epub_doc = EpubDoc::new(path)?;
+        let mut epub_doc =
+            EpubDoc::new(path).map_err(|e| BookError::Open(book_path.clone(), e.to_string()))?;
+        let page_list = read_page_list(&book_path).unwrap_or_default();
 
-        let mut ch_vec = Vector::new();
-        let mut id = 0;
+        /* L'archivio si legge in ordine, i capitoli vengono poi preparati in parallelo */
+        let mut spine = Vec::new();
         while {
-            //La libreria che fa il parsing fallisce quando incontra &nbsp; quindi lo sostiusco a priori con uno spazio
-            let ch_xml = epub_doc.get_current_str()?;
             let ch_path = epub_doc
-                .get_current_path()?
-                .into_os_string()
-                .into_string()
-                .map_err(|_e| fmt::Error::default())?;
-
-            //Creiamo un nuovo capitolo
-            let starting_page = match page_chapter.get(id) {
-                Some(page) => *page,
-                None => 0,
-            };
-
-            let ch = Chapter::new(ch_path, ch_xml, starting_page);
-
-            ch_vec.push_back(ch);
-            id += 1;
+                .get_current_path()
+                .map_err(|e| BookError::Open(book_path.clone(), e.to_string()))?
+                .to_string_lossy()
+                .to_string();
+            //La libreria che fa il parsing fallisce quando incontra &nbsp; quindi lo sostiusco a priori con uno spazio
+            let ch_xml = epub_doc
+                .get_current_str()
+                .map_err(|e| BookError::Chapter(ch_path.clone(), e.to_string()))?;
+            spine.push((ch_path, ch_xml));
             epub_doc.go_next().is_ok()
         } {}
 
+        /* Il parsing dei segnaposto dell'editore è la parte costosa e si fa insieme al capitolo */
+        let (chapters, marks): (Vec<Chapter>, Vec<Vec<(bool, usize)>>) = spine
+            .into_par_iter()
+            .enumerate()
+            .map(|(id, (ch_path, ch_xml))| {
+                //Creiamo un nuovo capitolo
+                let starting_page = match page_chapter.get(id) {
+                    Some(page) => *page,
+                    None => 0,
+                };
+
+                let page_targets = page_list.get(&ch_path).cloned().unwrap_or_default();
+                let mut ch = Chapter::new(ch_path, ch_xml, starting_page);
+                ch.page_targets = page_targets;
+                let marks = ch.page_marks();
+                (ch, marks)
+            })
+            .unzip();
+        let mut ch_vec = chapters.into_iter().collect::<Vector<Chapter>>();
+
+        /* Senza un allineamento salvato valgono le pagine dell'editore */
+        let print_pages = pages_from_marks(&marks);
+        if page_chapter.is_empty() {
+            for (ch, page) in ch_vec.iter_mut().zip(print_pages.iter()) {
+                ch.initial_page = *page;
+            }
+        }
+
+        /* La posizione salvata potrebbe riferirsi a un'altra edizione del libro */
+        let init_chapter = if init_chapter < ch_vec.len() { init_chapter } else { 0 };
         let nav_new = Navigation::new(init_chapter, init_element_number);
         Ok(Self {
             path: book_path,
             nav: nav_new,
             chapters: ch_vec,
             imgs: HashMap::new(),
+            index: None,
+            print_pages,
         })
     }
 
@@ -149,8 +173,20 @@ impl Book {
         })
     }
 
+    /* Prime pagine cartacee dei capitoli, 0 per quelli senza */
+    pub fn set_initial_pages(&mut self, pages: &Vector<usize>) {
+        for (id, ch) in self.chapters.iter_mut().enumerate() {
+            ch.initial_page = pages.get(id).copied().unwrap_or(0);
+        }
+    }
+
     pub fn update_xml(&mut self, xml: String) {
         (*self).chapters[self.nav.get_ch()].xml = xml;
+        self.index = None;
+    }
+
+    pub fn build_index(&mut self) {
+        self.index = Some(Arc::new(WordIndex::new(&self.chapters)));
     }
 
     /*
@@ -158,66 +194,55 @@ impl Book {
     */
 
     pub fn save(&mut self, set: HashSet<usize>, target_path: String) -> Result<(), Box<dyn Error>> {
+        let replaced: HashMap<String, Vec<u8>> = set
+            .iter()
+            .map(|el| {
+                (
+                    self.chapters[*el].get_path().clone(),
+                    self.chapters[*el].xml.clone().into_bytes(),
+                )
+            })
+            .collect();
 
-        let file_path = (&self).path.clone();
-
-        let new_target_path = if target_path == file_path {
-            let mut new = current_dir()?.to_str().ok_or("No string conversion")?.to_string();
-            new.push_str("/tmp.epub");
-            new
-        } else {
-            let _ = fs::remove_file(&target_path); //Non mettere '?' -> se non c'è non è un problema: con questa istruzione mi assicuro soltanto che non esista già
-            target_path.clone()
-        };
-
-        let file = fs::File::open(file_path.clone())?;
-        let writer = OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .open(PathBuf::from(new_target_path.clone()))?;
-
-
-
-        let mut archive = zip::ZipArchive::new(file)?;
-
-        // Set the options for creating a new file entry in the zip file
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-
-        let path_map:HashMap<String, usize> = set.iter().map(|el| (self.chapters[*el].get_path().clone(), *el)).collect();
-
-        let mut zip_writer = ZipWriter::new(writer);
+        rewrite_epub(&self.path, &target_path, &replaced)
+    }
+}
 
-        // Iterate through the entries in the ZipArchive and add them to the ZipWriter, except for the entry to be deleted
-        for i in 0..archive.len() {
-            let zip_entry = archive.by_index(i)?;
-            let file_name = zip_entry.name();
+/*
+Prima pagina cartacea di ogni capitolo secondo i segnaposto dell'editore: quella del primo
+segnaposto se viene prima del testo, altrimenti il capitolo continua l'ultima pagina del
+precedente. Vuoto se il libro non ha segnaposto
+*/
+pub fn print_pages(chapters: &Vector<Chapter>) -> Vector<usize> {
+    /* Cercare i segnaposto richiede il parsing di ogni capitolo, che si fa in parallelo */
+    let marks = chapters
+        .iter()
+        .collect::<Vec<&Chapter>>()
+        .par_iter()
+        .map(|ch| ch.page_marks())
+        .collect::<Vec<_>>();
+    pages_from_marks(&marks)
+}
 
-            if !path_map.contains_key(file_name) {
-                if file_name.ends_with('/') {
-                    // Add a directory to the zip file
-                    zip_writer.add_directory(file_name, options)?;
-                } else {
-                    // Add a file to the zip file
-                    zip_writer.start_file(file_name, options)?;
-                    zip_writer.raw_copy_file(zip_entry)?;
-                }
-            } else {
-                zip_writer.start_file(file_name, options)?;
-                zip_writer.write_all(&self.chapters[*path_map.get(file_name).ok_or("No match")?].xml.clone().as_bytes())?;
+/* Come print_pages, partendo dai segnaposto già letti da ogni capitolo */
+fn pages_from_marks(marks: &[Vec<(bool, usize)>]) -> Vector<usize> {
+    let mut last = 0;
+    let pages = marks
+        .iter()
+        .map(|marks| {
+            let start = match marks.first() {
+                Some((false, page)) => *page,
+                Some((true, page)) if last == 0 => page - 1,
+                _ => last,
+            };
+            if let Some((_, page)) = marks.last() {
+                last = *page;
             }
-
-        }
-
-        zip_writer.finish()?;
-
-        if target_path != new_target_path {
-            fs::remove_file(target_path.clone())?;
-            fs::copy(&new_target_path, &target_path)?;
-            fs::remove_file(&new_target_path)?;
-        }
-
-        Ok(())
+            start
+        })
+        .collect::<Vector<usize>>();
+    if last == 0 {
+        return Vector::new();
     }
-
-
+    pages
 }
@@ -1,14 +1,16 @@
 use crate::book::epub_text::{AttributeCase, EpubText};
+use crate::book::opf::{page_label, OPS_NAMESPACE};
 use crate::book::page_element::ImageState::{Present, Waiting};
 use crate::book::page_element::PageElement;
-use crate::utilities::{convert_path_separators, get_image_buf, unify_paths};
+use crate::errors::{log, BookError, Report};
+use crate::utilities::{convert_path_separators, default_image, get_image_buf, unify_paths};
 use druid::im::HashMap;
 use druid::text::Attribute;
 use druid::{im::Vector, Data, ExtEventSink, FontFamily, FontStyle, FontWeight, ImageBuf, Lens};
 use roxmltree::{Document, Node, ParsingOptions};
 use std::path::PathBuf;
 
-const MAX_SIZE: f64 = 35.0;
+pub(crate) const MAX_SIZE: f64 = 35.0;
 
 #[derive(Default, Clone, Data, Lens, Debug)]
 pub struct Chapter {
@@ -16,6 +18,18 @@ pub struct Chapter {
     pub xml: String,
     // imgs: HashMap<PathBuf, ImageBuf>,
     pub initial_page: usize,
+    pub page_targets: HashMap<String, usize>, // Id degli elementi nella page-list del nav, con la loro pagina
+}
+
+/*
+Segnaposto di una pagina cartacea nel capitolo: la pagina inizia con l'elemento element,
+oppure nel mezzo del suo testo se inside
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageBreak {
+    pub element: usize,
+    pub inside: bool,
+    pub page: usize,
 }
 
 impl Chapter {
@@ -26,6 +40,7 @@ impl Chapter {
             path,
             xml,
             initial_page,
+            page_targets: HashMap::new(),
         }
     }
 
@@ -44,44 +59,106 @@ impl Chapter {
                 return v;
             }
         };
-        let node = doc.root_element().last_element_child().unwrap();
+        let node = match doc.root_element().last_element_child() {
+            Some(node) => node,
+            None => {
+                let e = BookError::Chapter(self.path.clone(), "no body".to_string());
+                log(e.severity(), &e.to_string());
+                let mut v = Vector::new();
+                v.push_back(PageElement::from_error(EpubText::from(e.to_string()), true));
+                return v;
+            }
+        };
         let mut elements: Vector<PageElement> = Vector::new();
         let mut cur_text = EpubText::new();
-        Self::xml_to_elements(
+        let breaks = self.xml_to_elements(
             node,
             &mut elements,
             &mut cur_text,
             images_cache,
             sink,
             ebook_path,
-            &(*self).path,
         );
+        apply_page_breaks(&mut elements, &breaks, self.initial_page);
 
         elements
     }
 
+    /*
+    Pagine dei segnaposto dell'editore nel capitolo, ognuna con l'indicazione se prima del
+    segnaposto c'è del testo
+    */
+    pub fn page_marks(&self) -> Vec<(bool, usize)> {
+        let opt = ParsingOptions { allow_dtd: true };
+        let doc = match Document::parse_with_options(&self.xml, opt) {
+            Ok(doc) => doc,
+            Err(_) => return Vec::new(),
+        };
+        let body = match doc.root_element().last_element_child() {
+            Some(body) => body,
+            None => return Vec::new(),
+        };
+        let mut text = false;
+        let mut marks = Vec::new();
+        for node in body.descendants() {
+            if node.is_text() {
+                text |= !node.text().unwrap_or("").trim().is_empty()
+                    && !node.ancestors().any(is_page_break);
+            } else if let Some(page) = self.page_number(node) {
+                marks.push((text, page));
+            }
+        }
+        marks
+    }
+
+    /*
+    Pagina indicata da un nodo: il suo id è nella page-list oppure è un segnaposto
+    epub:type="pagebreak" con il numero nel title, nell'aria-label o nel testo
+    */
+    fn page_number(&self, node: Node) -> Option<usize> {
+        if let Some(page) = node
+            .attribute("id")
+            .and_then(|id| self.page_targets.get(id))
+        {
+            return Some(*page);
+        }
+        if !is_page_break(node) {
+            return None;
+        }
+        match node.attribute("title").or(node.attribute("aria-label")) {
+            Some(label) => page_label(label),
+            None => page_label(
+                &node
+                    .descendants()
+                    .filter_map(|n| if n.is_text() { n.text() } else { None })
+                    .collect::<String>(),
+            ),
+        }
+    }
+
     fn xml_to_elements(
+        &self,
         node: Node,
         elements: &mut Vector<PageElement>,
         current_text: &mut EpubText,
         images_cache: Option<&HashMap<String, ImageBuf>>,
         sink: Option<ExtEventSink>,
         ebook_path: &str,
-        chapter_path: &str,
-    ) {
+    ) -> Vec<PageBreak> {
+        let mut breaks = Vec::new();
+
         /* Def Macros */
         macro_rules! recur_on_children {
             () => {
                 for child in node.children() {
-                    Self::xml_to_elements(
+                    breaks.append(&mut self.xml_to_elements(
                         child,
                         elements,
                         current_text,
                         images_cache,
                         sink.clone(),
                         ebook_path,
-                        chapter_path,
-                    );
+                    ));
                 }
             };
         }
@@ -96,10 +173,23 @@ impl Chapter {
             };
         }
 
+        /* Il segnaposto non viene mostrato, segna solo dove inizia la pagina */
+        if node.is_element() {
+            if let Some(page) = self.page_number(node) {
+                breaks.push(PageBreak {
+                    element: elements.len(),
+                    inside: !current_text.text.trim().is_empty(),
+                    page,
+                });
+            }
+            if is_page_break(node) {
+                return breaks;
+            }
+        }
+
         /*  Actual Transformation */
 
-        if node.is_text() {
-            let text = node.text().unwrap();
+        if let Some(text) = node.text().filter(|_| node.is_text()) {
             let content: Vec<_> = text.split_ascii_whitespace().collect();
             if current_text
                 .get_attributes()
@@ -136,14 +226,13 @@ impl Chapter {
             }
             "img" => {
                 new_line!("NO_HTML");
-                let image_path = String::from(node.attribute("src").unwrap());
-                let mut p1 = PathBuf::from(chapter_path);
+                /* Senza src il percorso non esiste nell'archivio e si mostra l'immagine di default */
+                let image_path = String::from(node.attribute("src").unwrap_or_default());
+                let mut p1 = PathBuf::from(&self.path);
                 p1.pop(); //RIMUOVO IL FILE XML DAL PATH
-                let mut complete_img_path =
-                    unify_paths(p1, PathBuf::from(&image_path))
-                        .into_os_string()
-                        .into_string()
-                        .unwrap();
+                let mut complete_img_path = unify_paths(p1, PathBuf::from(&image_path))
+                    .to_string_lossy()
+                    .to_string();
                 complete_img_path = convert_path_separators(complete_img_path);
                 /*LANCIO funzione su altro thread che mi carica il pathbuf*/
                 match (images_cache, sink) {
@@ -158,10 +247,11 @@ impl Chapter {
                     )),
                     _ => elements.push_back(PageElement::from_img_sync(
                         Present(
-                            match get_image_buf(PathBuf::from(ebook_path), complete_img_path) {
-                                None => ImageBuf::from_file("./images/default.jpg").unwrap(),
-                                Some(buff) => buff,
-                            },
+                            get_image_buf(PathBuf::from(ebook_path), complete_img_path)
+                                .unwrap_or_else(|e| {
+                                    log(e.severity(), &e.to_string());
+                                    default_image()
+                                }),
                         ),
                         false,
                     )),
@@ -280,9 +370,45 @@ impl Chapter {
             }*/
             _ => recur_on_children!(),
         }
+        breaks
     }
 
     pub fn get_path(&self) -> String {
         (&self).path.clone()
     }
 }
+
+/* Segnaposto di pagina: epub:type="pagebreak" oppure role="doc-pagebreak" */
+pub fn is_page_break(node: Node) -> bool {
+    let has = |value: Option<&str>, token: &str| {
+        value.map_or(false, |v| v.split_ascii_whitespace().any(|t| t == token))
+    };
+    has(node.attribute((OPS_NAMESPACE, "type")), "pagebreak")
+        || has(node.attribute("role"), "doc-pagebreak")
+}
+
+/*
+Pagina cartacea di ogni elemento secondo i segnaposto: quelli prima del primo segnaposto
+restano sulla pagina iniziale del capitolo, senza segnaposto i pg_offset non cambiano
+*/
+pub(crate) fn apply_page_breaks(
+    elements: &mut Vector<PageElement>,
+    breaks: &[PageBreak],
+    initial_page: usize,
+) {
+    if breaks.is_empty() {
+        return;
+    }
+    let mut page = initial_page;
+    let mut next = breaks.iter().peekable();
+    for (i, el) in elements.iter_mut().enumerate() {
+        while let Some(mark) = next.next_if(|mark| mark.element <= i && !mark.inside) {
+            page = mark.page;
+        }
+        let start = page;
+        while let Some(mark) = next.next_if(|mark| mark.element <= i) {
+            page = mark.page;
+        }
+        el.pg_offset = (page, start != 0 && start != page);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::utilities::{convert_path_separators, unify_paths};
+use druid::im::HashMap;
+use regex::{Captures, Regex};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use zip::ZipArchive;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+pub const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+
+/*
+Utility per leggere e riscrivere il package document (OPF) di un epub
+*/
+
+pub fn read_zip_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, Box<dyn Error>> {
+    let mut file = archive.by_name(name)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+pub fn opf_path(archive: &mut ZipArchive<File>) -> Result<String, Box<dyn Error>> {
+    let container = read_zip_entry(archive, CONTAINER_PATH)?;
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = Document::parse_with_options(&container, opt)?;
+    let path = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "rootfile")
+        .and_then(|n| n.attribute("full-path"))
+        .ok_or("No rootfile in container.xml")?;
+    Ok(path.to_string())
+}
+
+pub fn read_opf(book_path: &str) -> Result<(String, String), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(book_path)?)?;
+    let path = opf_path(&mut archive)?;
+    let opf = read_zip_entry(&mut archive, &path)?;
+    Ok((path, opf))
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/*
+Sostituisce il contenuto del primo <dc:tag> del blocco metadata, se non esiste lo aggiunge
+in fondo al blocco. Un valore vuoto rimuove l'elemento.
+*/
+pub fn set_metadata(opf: &str, tag: &str, value: &str) -> String {
+    let element = Regex::new(&format!(
+        r"(?s)<dc:{tag}(\s[^>]*?)?(/>|>.*?</dc:{tag}>)",
+        tag = regex::escape(tag)
+    ))
+    .unwrap();
+    let value = escape_xml(value.trim());
+
+    if element.is_match(opf) {
+        return element
+            .replace(opf, |caps: &Captures| {
+                if value.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "<dc:{tag}{attrs}>{value}</dc:{tag}>",
+                        tag = tag,
+                        attrs = caps.get(1).map_or("", |m| m.as_str()),
+                        value = value
+                    )
+                }
+            })
+            .to_string();
+    }
+    if value.is_empty() {
+        return opf.to_string();
+    }
+
+    let end = Regex::new(r"</(\w+:)?metadata>").unwrap();
+    end.replace(opf, |caps: &Captures| {
+        format!("<dc:{tag}>{value}</dc:{tag}>\n{end}", tag = tag, value = value, end = &caps[0])
+    })
+    .to_string()
+}
+
+/*
+Identificatore univoco del libro: il dc:identifier puntato da package@unique-identifier
+*/
+pub fn unique_identifier(opf: &str) -> Option<String> {
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = Document::parse_with_options(opf, opt).ok()?;
+    let mut identifiers = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "identifier");
+    let node = match doc.root_element().attribute("unique-identifier") {
+        Some(id) => identifiers.clone().find(|n| n.attribute("id") == Some(id)),
+        None => None,
+    }
+    .or(identifiers.next())?;
+    node.text()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/*
+Href e media-type della copertina: prima properties="cover-image" (EPUB3), poi <meta name="cover"> (EPUB2)
+*/
+pub fn cover_item(opf: &str) -> Option<(String, String)> {
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = Document::parse_with_options(opf, opt).ok()?;
+    let mut items = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "item");
+
+    let item = match items.clone().find(|n| {
+        n.attribute("properties")
+            .map_or(false, |p| p.split_whitespace().any(|p| p == "cover-image"))
+    }) {
+        Some(item) => item,
+        None => {
+            let cover_id = doc
+                .descendants()
+                .find(|n| n.tag_name().name() == "meta" && n.attribute("name") == Some("cover"))
+                .and_then(|n| n.attribute("content"))?;
+            items.find(|n| n.attribute("id") == Some(cover_id))?
+        }
+    };
+    Some((
+        item.attribute("href")?.to_string(),
+        item.attribute("media-type").unwrap_or("image/jpeg").to_string(),
+    ))
+}
+
+pub fn image_extension(media_type: &str) -> &str {
+    match media_type {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "image/gif" => "gif",
+        _ => "jpeg",
+    }
+}
+
+pub fn image_media_type(extension: &str) -> &str {
+    match extension.to_lowercase().as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "gif" => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/*
+Dichiara href come unica copertina del libro, sia per EPUB3 che per EPUB2
+*/
+pub fn set_cover(opf: &str, href: &str, media_type: &str) -> String {
+    let item = Regex::new(r"<item\s[^>]*>").unwrap();
+    let property = Regex::new(r#"\s+properties="([^"]*)""#).unwrap();
+    let opf = item.replace_all(opf, |caps: &Captures| {
+        let tag = &caps[0];
+        if tag.contains(r#"id="custom-cover""#) {
+            return String::new();
+        }
+        property
+            .replace(tag, |p: &Captures| {
+                let props = p[1]
+                    .split_whitespace()
+                    .filter(|p| *p != "cover-image")
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                if props.is_empty() {
+                    String::new()
+                } else {
+                    format!(r#" properties="{}""#, props)
+                }
+            })
+            .to_string()
+    });
+    let meta = Regex::new(r#"<meta\s[^>]*name="cover"[^>]*>"#).unwrap();
+    let opf = meta.replace_all(&opf, "");
+
+    let manifest_end = Regex::new(r"</(\w+:)?manifest>").unwrap();
+    let opf = manifest_end.replace(&opf, |caps: &Captures| {
+        format!(
+            "<item id=\"custom-cover\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\"/>\n{}",
+            escape_xml(href),
+            media_type,
+            &caps[0]
+        )
+    });
+    let metadata_end = Regex::new(r"</(\w+:)?metadata>").unwrap();
+    metadata_end
+        .replace(&opf, |caps: &Captures| {
+            format!("<meta name=\"cover\" content=\"custom-cover\"/>\n{}", &caps[0])
+        })
+        .to_string()
+}
+
+/*
+Href del documento con la page-list: il nav dell'EPUB3 (properties="nav"), altrimenti l'NCX dell'EPUB2
+*/
+pub fn nav_item(opf: &str) -> Option<String> {
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = Document::parse_with_options(opf, opt).ok()?;
+    let items = doc.descendants().filter(|n| n.tag_name().name() == "item");
+    items
+        .clone()
+        .find(|n| {
+            n.attribute("properties")
+                .map_or(false, |p| p.split_whitespace().any(|p| p == "nav"))
+        })
+        .or(items
+            .clone()
+            .find(|n| n.attribute("media-type") == Some("application/x-dtbncx+xml")))
+        .and_then(|n| n.attribute("href"))
+        .map(String::from)
+}
+
+/* Percorso nell'archivio di un href relativo al documento in doc_path */
+pub fn resolve_href(doc_path: &str, href: &str) -> String {
+    let mut dir = PathBuf::from(doc_path);
+    dir.pop();
+    convert_path_separators(
+        unify_paths(dir, PathBuf::from(href))
+            .to_string_lossy()
+            .to_string(),
+    )
+}
+
+/* Numero di pagina di un'etichetta come "12" o "Page 12", None per quelle romane */
+pub fn page_label(label: &str) -> Option<usize> {
+    label
+        .trim()
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .parse()
+        .ok()
+        .filter(|page| *page != 0)
+}
+
+/*
+Voci della page-list, dal nav EPUB3 (<nav epub:type="page-list">) o dall'NCX (<pageList>):
+documento nell'archivio, id dell'elemento e pagina
+*/
+pub fn page_list(nav: &str, nav_path: &str) -> Vec<(String, String, usize)> {
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = match Document::parse_with_options(nav, opt) {
+        Ok(doc) => doc,
+        Err(_) => return Vec::new(),
+    };
+    let is_page_list = |n: Node| {
+        n.tag_name().name() == "nav"
+            && n.attribute((OPS_NAMESPACE, "type"))
+                .map_or(false, |t| t.split_whitespace().any(|t| t == "page-list"))
+    };
+    let text = |n: Node| {
+        n.descendants()
+            .filter_map(|t| if t.is_text() { t.text() } else { None })
+            .collect::<String>()
+    };
+    doc.descendants()
+        .filter_map(|n| match n.tag_name().name() {
+            "pageTarget" => {
+                let label = n.descendants().find(|c| c.tag_name().name() == "text")?;
+                let content = n.descendants().find(|c| c.tag_name().name() == "content")?;
+                Some((content.attribute("src")?, text(label)))
+            }
+            "a" if n.ancestors().any(is_page_list) => Some((n.attribute("href")?, text(n))),
+            _ => None,
+        })
+        .filter_map(|(href, label)| {
+            let (file, id) = href.split_once('#')?;
+            Some((
+                resolve_href(nav_path, file),
+                id.to_string(),
+                page_label(&label)?,
+            ))
+        })
+        .collect()
+}
+
+/* Page-list del libro raggruppata per capitolo: per ogni documento gli id con la loro pagina */
+pub fn read_page_list(
+    book_path: &str,
+) -> Result<HashMap<String, HashMap<String, usize>>, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(book_path)?)?;
+    let path = opf_path(&mut archive)?;
+    let opf = read_zip_entry(&mut archive, &path)?;
+    let nav_path = match nav_item(&opf) {
+        Some(href) => resolve_href(&path, &href),
+        None => return Ok(HashMap::new()),
+    };
+    let nav = read_zip_entry(&mut archive, &nav_path)?;
+    let mut pages: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    for (file, id, page) in page_list(&nav, &nav_path) {
+        pages.entry(file).or_default().insert(id, page);
+    }
+    Ok(pages)
+}
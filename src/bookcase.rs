@@ -1,62 +1,144 @@
+use crate::book::opf::{
+    cover_item, image_extension, image_media_type, read_opf, set_cover,
+    set_metadata, unique_identifier,
+};
+use crate::book::Navigation;
+use crate::errors::{log, LibraryError, Report};
 use crate::ocr::{OcrData, SerializableOcrData};
-use druid::{im::Vector, Data, ImageBuf, Lens};
+use crate::utilities::{convert_path_separators, rewrite_epub, stable_hash, unify_paths};
+use druid::{im, im::Vector, Data, ImageBuf, Lens};
 use epub::doc::EpubDoc;
 use isolang::Language;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, Read, Write};
-use std::path::PathBuf;
-use std::{env, fs};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
+use zip::ZipArchive;
 
 const FILE_NAME: &str = "meta.json";
+const COVERS_DIR: &str = "./images/covers";
+const DEFAULT_COVER: &str = "./images/default.jpg";
+pub const DEFAULT_EDITION: &str = "Default";
 //const FILE_NAME: &str = "meta.bin";
 
 #[derive(Default, Clone, Data, Lens, Debug)]
 pub struct BookInfo {
+    pub id: String,
+    pub content_hash: String, // Hash del file, calcolato all'import per riconoscere le copie
     pub name: String,
     pub path: String,
     pub start_chapter: usize,
     pub start_element_number: usize,
     pub cover_path: String,
     pub cover_buf: ImageBuf,
+    pub custom_cover: bool,
+    pub embed_cover: bool,
     pub ocr: OcrData,
     pub mapped_pages: Vector<usize>,
+    pub edition: String, // Edizione cartacea a cui si riferiscono ocr e mapped_pages
+    pub editions: Vector<Edition>, // Le altre edizioni cartacee del libro
     pub title: String,
     pub description: String,
     pub language: String,
     pub creator: String,
+    pub alt_paths: Vector<String>,
+}
+
+/*
+Edizione cartacea non selezionata (per esempio rilegata ed economica hanno pagine diverse):
+i suoi campioni OCR e le prime pagine dei capitoli
+*/
+#[derive(Default, Clone, Data, Lens, Debug, PartialEq)]
+pub struct Edition {
+    pub name: String,
+    pub ocr: OcrData,
+    pub mapped_pages: Vector<usize>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SerializableEdition {
+    pub name: String,
+    pub ocr: SerializableOcrData,
+    pub mapped_pages: Vec<usize>,
+}
+
+impl From<Edition> for SerializableEdition {
+    fn from(e: Edition) -> Self {
+        SerializableEdition {
+            name: e.name,
+            ocr: e.ocr.into(),
+            mapped_pages: e.mapped_pages.iter().copied().collect(),
+        }
+    }
+}
+
+impl From<SerializableEdition> for Edition {
+    fn from(e: SerializableEdition) -> Self {
+        Edition {
+            name: e.name,
+            ocr: e.ocr.into(),
+            mapped_pages: e.mapped_pages.iter().copied().collect(),
+        }
+    }
+}
+
+fn default_edition() -> String {
+    DEFAULT_EDITION.to_string()
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct SerializableBookInfo {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub content_hash: String,
     pub name: String,
     pub path: String,
     pub start_chapter: usize,
     pub start_element_number: usize,
     pub cover_path: String,
+    #[serde(default)]
+    pub custom_cover: bool,
+    #[serde(default)]
+    pub embed_cover: bool,
     pub ocr: SerializableOcrData,
     pub mapped_pages: Vec<usize>,
+    #[serde(default = "default_edition")]
+    pub edition: String,
+    #[serde(default)]
+    pub editions: Vec<SerializableEdition>,
     pub title: String,
     pub description: String,
     pub language: String,
     pub creator: String,
+    #[serde(default)]
+    pub alt_paths: Vec<String>,
 }
 
 impl From<BookInfo> for SerializableBookInfo {
     fn from(b: BookInfo) -> Self {
         SerializableBookInfo {
+            id: b.id,
+            content_hash: b.content_hash,
             name: b.name,
             path: b.path,
             start_chapter: b.start_chapter,
             start_element_number: b.start_element_number,
             cover_path: b.cover_path,
+            custom_cover: b.custom_cover,
+            embed_cover: b.embed_cover,
             ocr: b.ocr.into(),
             mapped_pages: b.mapped_pages.iter().map(|m| *m).collect(),
+            edition: b.edition,
+            editions: b.editions.iter().map(|e| e.clone().into()).collect(),
             title: b.title,
             description: b.description,
             language: b.language,
             creator: b.creator,
+            alt_paths: b.alt_paths.iter().cloned().collect(),
         }
     }
 }
@@ -64,30 +146,37 @@ impl From<BookInfo> for SerializableBookInfo {
 impl From<SerializableBookInfo> for BookInfo {
     fn from(b: SerializableBookInfo) -> Self {
         BookInfo {
+            id: b.id,
+            content_hash: b.content_hash,
             name: b.name,
             path: b.path,
             start_chapter: b.start_chapter,
             start_element_number: b.start_element_number,
             cover_path: b.cover_path.clone(),
-            cover_buf: ImageBuf::from_file(b.cover_path)
-                .unwrap_or(ImageBuf::from_file("./images/default.jpg").unwrap()),
+            cover_buf: load_cover(&b.cover_path),
+            custom_cover: b.custom_cover,
+            embed_cover: b.embed_cover,
             ocr: b.ocr.into(),
             mapped_pages: b.mapped_pages.iter().map(|m| *m).collect(),
+            edition: b.edition,
+            editions: b.editions.iter().map(|e| e.clone().into()).collect(),
             title: b.title,
             description: b.description,
             language: b.language,
             creator: b.creator,
+            alt_paths: b.alt_paths.iter().cloned().collect(),
         }
     }
 }
 
 impl BookInfo {
-    pub fn new(path: String) -> Result<Self, String> {
+    pub fn new(path: String) -> Result<Self, LibraryError> {
         let mut doc = match EpubDoc::new(&path) {
             Ok(d) => d,
-            Err(_) => return Err(String::new()),
+            Err(e) => return Err(LibraryError::Import(path, e.to_string())),
         };
-        let cover_path = Self::get_image(&mut doc);
+        let id = Self::book_id(&path);
+        let cover_path = Self::get_image(&mut doc, &path, &id);
         let title = doc
             .mdata("title")
             .unwrap_or("unknown".to_string())
@@ -111,25 +200,29 @@ impl BookInfo {
 
         let name = PathBuf::from(path.clone())
             .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
 
         Ok(Self {
+            id,
+            content_hash: file_hash(&path).unwrap_or_default(),
             name,
             path,
             start_chapter: 0,
             start_element_number: 0,
             cover_path: cover_path.clone(),
-            cover_buf: ImageBuf::from_file(cover_path)
-                .unwrap_or(ImageBuf::from_file("./images/default.jpg").unwrap()),
+            cover_buf: load_cover(&cover_path),
+            custom_cover: false,
+            embed_cover: false,
             ocr: OcrData::new(),
             mapped_pages: Vector::new(),
+            edition: default_edition(),
+            editions: Vector::new(),
             title,
             description,
             language,
             creator,
+            alt_paths: Vector::new(),
         })
     }
 
@@ -137,20 +230,225 @@ impl BookInfo {
         PathBuf::from(&self.path)
     }
 
-    fn get_image(doc: &mut EpubDoc<BufReader<File>>) -> String {
-        let title = doc.mdata("title").unwrap().replace("|", "_");
+    /* Nomi delle edizioni cartacee, prima quella selezionata */
+    pub fn edition_names(&self) -> Vec<String> {
+        std::iter::once(self.edition.clone())
+            .chain(self.editions.iter().map(|e| e.name.clone()))
+            .collect()
+    }
+
+    /* Rende name l'edizione selezionata, scambiandola con quella corrente */
+    pub fn select_edition(&mut self, name: &str) -> Result<(), String> {
+        if self.edition == name {
+            return Ok(());
+        }
+        let other = self
+            .editions
+            .iter_mut()
+            .find(|e| e.name == name)
+            .ok_or(format!("There is no edition called '{}'", name))?;
+        std::mem::swap(&mut self.edition, &mut other.name);
+        std::mem::swap(&mut self.ocr, &mut other.ocr);
+        std::mem::swap(&mut self.mapped_pages, &mut other.mapped_pages);
+        Ok(())
+    }
+
+    /* Aggiunge una nuova edizione, ancora da allineare, e la seleziona */
+    pub fn add_edition(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Type a name for the edition".to_string());
+        }
+        if self.edition_names().iter().any(|e| e == name) {
+            return Err(format!("There is already an edition called '{}'", name));
+        }
+        self.editions.push_back(Edition {
+            name: name.to_string(),
+            ocr: OcrData::new(),
+            mapped_pages: Vector::new(),
+        });
+        self.select_edition(name)
+    }
+
+    /* Elimina un'edizione non selezionata con i suoi campioni e le sue pagine */
+    pub fn remove_edition(&mut self, name: &str) -> Result<(), String> {
+        if self.edition == name {
+            return Err("The edition in use can't be removed".to_string());
+        }
+        let id = self
+            .editions
+            .iter()
+            .position(|e| e.name == name)
+            .ok_or(format!("There is no edition called '{}'", name))?;
+        self.editions.remove(id);
+        Ok(())
+    }
+
+    /*
+    Riscrive il blocco metadata dell'OPF dentro l'epub con title, creator, language e description correnti
+    */
+    pub fn save_metadata(&self) -> Result<(), Box<dyn Error>> {
+        let (opf_path, mut opf) = read_opf(&self.path)?;
+        // Nel JSON la lingua è ISO 639-3, nell'OPF ci va il codice ISO 639-1 quando esiste
+        let language = Language::from_639_3(&self.language)
+            .or(Language::from_639_1(&self.language))
+            .map(|l| l.to_639_1().unwrap_or(l.to_639_3()).to_string())
+            .unwrap_or(self.language.clone());
+
+        opf = set_metadata(&opf, "title", &self.title);
+        opf = set_metadata(&opf, "creator", &self.creator);
+        opf = set_metadata(&opf, "language", &language);
+        opf = set_metadata(&opf, "description", &self.description);
+
+        let mut replaced = im::HashMap::new();
+        if self.custom_cover && self.embed_cover {
+            let extension = PathBuf::from(&self.cover_path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("jpeg")
+                .to_string();
+            let href = format!("custom-cover.{}", extension);
+            let base = PathBuf::from(&opf_path)
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default();
+            let entry = convert_path_separators(
+                unify_paths(base, PathBuf::from(&href))
+                    .to_str()
+                    .ok_or("No string conversion")?
+                    .to_string(),
+            );
+            opf = set_cover(&opf, &href, image_media_type(&extension));
+            replaced.insert(entry, fs::read(&self.cover_path)?);
+        }
+        replaced.insert(opf_path, opf.into_bytes());
+
+        rewrite_epub(&self.path, &self.path, &replaced)
+    }
+
+    /*
+    Rilegge dall'epub i metadati e la copertina
+    */
+    pub fn refresh_metadata(&mut self) -> Result<(), String> {
+        let updated = BookInfo::new(self.path.clone()).map_err(|e| e.to_string())?;
+        self.title = updated.title;
+        self.creator = updated.creator;
+        self.language = updated.language;
+        self.description = updated.description;
+        // Una copertina personalizzata incorporata diventa quella del libro
+        if self.custom_cover && self.embed_cover {
+            self.custom_cover = false;
+            self.embed_cover = false;
+        }
+        // Il file è stato riscritto
+        self.content_hash = updated.content_hash;
+        if !self.custom_cover {
+            self.id = updated.id;
+            self.cover_path = updated.cover_path;
+            self.cover_buf = updated.cover_buf;
+        }
+        Ok(())
+    }
 
-        let cover_data = match doc.get_cover() {
-            Ok(data) => data,
-            Err(_) => return String::from("./images/default.jpeg"),
+    /*
+    Identità stabile del libro: hash del dc:identifier se presente, altrimenti del contenuto del file
+    */
+    pub fn book_id(path: &str) -> String {
+        if let Some(identifier) = read_opf(path)
+            .ok()
+            .and_then(|(_, opf)| unique_identifier(&opf))
+        {
+            return stable_hash(identifier.as_bytes());
+        }
+        match fs::read(path) {
+            Ok(bytes) => stable_hash(&bytes),
+            Err(_) => stable_hash(path.as_bytes()),
+        }
+    }
+
+    /*
+    Estrae la copertina in ./images/covers/<id>.<ext>, così libri con lo stesso titolo non si sovrascrivono
+    */
+    fn get_image(doc: &mut EpubDoc<BufReader<File>>, path: &str, id: &str) -> String {
+        let cover = Self::read_cover(path).or_else(|| {
+            doc.get_cover()
+                .ok()
+                .map(|data| (data, "jpeg".to_string()))
+        });
+        let (cover_data, extension) = match cover {
+            Some(cover) => cover,
+            None => return String::from(DEFAULT_COVER),
         };
-        let path = String::from("./images/") + title.as_str() + ".jpeg";
-        File::create(path.clone())
-            .unwrap()
-            .write_all(&cover_data)
-            .expect("Couldn't create a cover image");
-        path
+        /* Le copertine SVG (o in formati sconosciuti) non si possono mostrare */
+        if let Err(e) = ImageBuf::from_data(&cover_data) {
+            let error = LibraryError::Cover(format!("{} ({})", path, extension), e.to_string());
+            log(error.severity(), &error.to_string());
+            return String::from(DEFAULT_COVER);
+        }
+        let cover_path = format!("{}/{}.{}", COVERS_DIR, id, extension);
+        let written = fs::create_dir_all(COVERS_DIR)
+            .and_then(|_| File::create(&cover_path))
+            .and_then(|mut file| file.write_all(&cover_data));
+        match written {
+            Ok(_) => cover_path,
+            Err(e) => {
+                let error = LibraryError::Cover(cover_path, e.to_string());
+                log(error.severity(), &error.to_string());
+                String::from(DEFAULT_COVER)
+            }
+        }
     }
+
+    fn read_cover(path: &str) -> Option<(Vec<u8>, String)> {
+        let mut archive = ZipArchive::new(File::open(path).ok()?).ok()?;
+        let (opf_path, opf) = read_opf(path).ok()?;
+        let (href, media_type) = cover_item(&opf)?;
+        let base = PathBuf::from(opf_path).parent()?.to_path_buf();
+        let entry = convert_path_separators(unify_paths(base, PathBuf::from(href)).to_str()?.to_string());
+        let mut file = archive.by_name(&entry).ok()?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).ok()?;
+        Some((data, image_extension(&media_type).to_string()))
+    }
+
+    /*
+    Imposta come copertina un'immagine scelta dall'utente, copiandola nella cache delle copertine
+    */
+    pub fn set_custom_cover(&mut self, src: &Path) -> Result<(), LibraryError> {
+        let error = |e: &dyn fmt::Display| LibraryError::Cover(src.display().to_string(), e.to_string());
+        let extension = src
+            .extension()
+            .and_then(|e| e.to_str())
+            .ok_or_else(|| error(&"unknown image format"))?
+            .to_lowercase()
+            .replace("jpg", "jpeg");
+        if extension == "svg" {
+            let unsupported = "SVG covers are not supported, choose a JPEG, PNG or WebP image";
+            return Err(error(&unsupported));
+        }
+        let cover_path = format!(
+            "{}/{}_{}.{}",
+            COVERS_DIR,
+            self.id,
+            stable_hash(self.path.as_bytes()),
+            extension
+        );
+        fs::create_dir_all(COVERS_DIR).map_err(|e| error(&e))?;
+        fs::copy(src, &cover_path).map_err(|e| error(&e))?;
+        self.cover_buf = load_cover(&cover_path);
+        self.cover_path = cover_path;
+        self.custom_cover = true;
+        Ok(())
+    }
+}
+
+pub fn file_hash(path: &str) -> Option<String> {
+    fs::read(path).ok().map(|bytes| stable_hash(&bytes))
+}
+
+pub fn load_cover(path: &str) -> ImageBuf {
+    ImageBuf::from_file(path)
+        .unwrap_or_else(|_| ImageBuf::from_file(DEFAULT_COVER).unwrap_or_default())
 }
 
 #[derive(Default, Clone, Data, Lens)]
@@ -158,6 +456,42 @@ pub struct BookCase {
     pub(crate) library: Vector<BookInfo>,
 }
 
+#[derive(Clone, Copy, Data, PartialEq, Debug)]
+pub enum DuplicateKind {
+    SameFile,
+    SameIdentifier,
+    SameTitle,
+}
+
+#[derive(Clone, Copy, Data, PartialEq, Debug)]
+pub enum DuplicateChoice {
+    Merge,
+    Replace,
+    KeepBoth,
+}
+
+#[derive(Clone, Data, Debug)]
+pub struct Duplicate {
+    pub existing: usize,
+    pub candidate: BookInfo,
+    pub kind: DuplicateKind,
+}
+
+impl Duplicate {
+    pub fn describe(&self, library: &Vector<BookInfo>) -> String {
+        let existing = &library[self.existing];
+        let reason = match self.kind {
+            DuplicateKind::SameFile => "is the same file as",
+            DuplicateKind::SameIdentifier => "has the same identifier of",
+            DuplicateKind::SameTitle => "looks like another edition of",
+        };
+        format!(
+            "'{}' {} '{}' already in your library at {}",
+            self.candidate.path, reason, existing.title, existing.path
+        )
+    }
+}
+
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub struct SerializableBookCase {
     pub(crate) library: Vec<SerializableBookInfo>,
@@ -180,11 +514,38 @@ impl From<SerializableBookCase> for BookCase {
 }
 
 impl BookCase {
+    /* Per la riga di comando: i problemi trovati finiscono solo nel log */
     pub fn new() -> Self {
+        let (instance, problems) = Self::load();
+        for problem in problems {
+            log(problem.severity(), &problem.to_string());
+        }
+        instance
+    }
+
+    /*
+    Per le interrogazioni della riga di comando: meta.json viene solo letto, anche se è rovinato
+    o ci sono libri spariti
+    */
+    pub fn read_only() -> Result<Self, LibraryError> {
+        let mut instance = BookCase {
+            library: Vector::new(),
+        };
+        let mut saved_books = Self::fetch_saved(false)?;
+        let mut problems = Vec::new();
+        instance.populate(&mut saved_books, &mut problems);
+        for problem in problems {
+            log(problem.severity(), &problem.to_string());
+        }
+        Ok(instance)
+    }
+
+    /* La libreria salvata, con i problemi trovati da mostrare all'utente */
+    pub fn load() -> (Self, Vec<LibraryError>) {
         /*
         Constructor:
          1. Read books in folder into folder_books : Vec<String>
-         2. Read from meta file into saved_books : HashMap<String, BookInfo>
+         2. Read from meta file into saved_books : Vec<BookInfo>, nell'ordine del file
          3. Create a Vec<BookInfo> based on folder books taking info present in saved books if present
          4. Update saved_books with the new vector
         */
@@ -199,59 +560,109 @@ impl BookCase {
         }
         */
 
-        let mut saved_books: HashMap<String, BookInfo> = Self::fetch_saved(); //contiene tutti i libri letti dal file
-        if instance.populate(&mut saved_books) {
-            instance.update_meta()
+        let mut problems = Vec::new();
+        let mut saved_books: Vec<BookInfo> = match Self::fetch_saved(true) {
+            Ok(saved_books) => saved_books, //contiene tutti i libri letti dal file
+            Err(e) => {
+                problems.push(e);
+                Vec::new()
+            }
+        };
+        if instance.populate(&mut saved_books, &mut problems) {
+            if let Err(e) = instance.update_meta() {
+                problems.push(e);
+            }
         }
-        instance
+        (instance, problems)
     }
 
-    fn fetch_saved() -> HashMap<String, BookInfo> {
-        let mut library: HashMap<String, BookInfo> = HashMap::new();
-        match File::open(FILE_NAME) {
-            Ok(mut file) => {
-                let mut buf = String::new();
-                let cwd = env::current_dir().unwrap();
-                file.read_to_string(&mut buf).unwrap();
-                let ser_l: SerializableBookCase = match serde_json::from_str(&buf) {
-                    Ok(r) => r,
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        panic!();
-                    }
-                };
-                let l: BookCase = ser_l.into();
-                for book_info in l.library {
-                    let absolute_path = PathBuf::from(book_info.path.clone());
-                    let relative_path = match absolute_path.clone().strip_prefix(cwd.clone()) {
-                        Ok(path) => ".".to_string() + path.to_str().unwrap(),
-                        Err(_e) => {
-                            absolute_path.clone().to_str().unwrap().to_string()
-                        }
-                    };
-                    library
-                        .entry(relative_path) /* In caso di duplicati */
-                        .or_insert(book_info);
-                }
-                library
+    /*
+    Un meta.json rovinato viene messo da parte se backup è vero, altrimenti il prossimo
+    salvataggio lo sovrascriverebbe con la libreria vuota
+    */
+    fn fetch_saved(backup: bool) -> Result<Vec<BookInfo>, LibraryError> {
+        let buf = match fs::read_to_string(FILE_NAME) {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(LibraryError::Meta(e.to_string())),
+        };
+        match Self::parse_saved(&buf) {
+            Ok(library) => Ok(library),
+            Err(e) if !backup => Err(LibraryError::Meta(e.to_string())),
+            Err(e) => {
+                let backup = format!("{}.bak", FILE_NAME);
+                Err(LibraryError::Meta(match fs::rename(FILE_NAME, &backup) {
+                    Ok(_) => format!("{}, the old library was moved to {}", e, backup),
+                    Err(_) => e.to_string(),
+                }))
             }
-            Err(_) => {
-                eprintln!("No meta file found");
-                return library;
+        }
+    }
+
+    /*
+    I libri restano nell'ordine di meta.json: la riga di comando li sceglie per posizione,
+    che non deve cambiare da un'esecuzione all'altra
+    */
+    pub(crate) fn parse_saved(buf: &str) -> Result<Vec<BookInfo>, serde_json::Error> {
+        let ser_l: SerializableBookCase = serde_json::from_str(buf)?;
+        let cwd = env::current_dir().unwrap_or_default();
+        let l: BookCase = ser_l.into();
+        let mut paths = HashSet::new();
+        let mut library = Vec::new();
+        for book_info in l.library {
+            let absolute_path = PathBuf::from(book_info.path.clone());
+            let relative_path = match absolute_path.strip_prefix(&cwd) {
+                Ok(path) => ".".to_string() + &path.to_string_lossy(),
+                Err(_e) => absolute_path.to_string_lossy().to_string(),
+            };
+            /* In caso di duplicati vale il primo */
+            if paths.insert(relative_path) {
+                library.push(book_info);
             }
         }
+        Ok(library)
     }
 
-    fn populate(&mut self, saved_books: &mut HashMap<String, BookInfo>) -> bool {
+    pub(crate) fn populate(
+        &mut self,
+        saved_books: &mut Vec<BookInfo>,
+        problems: &mut Vec<LibraryError>,
+    ) -> bool {
         let mut file_need_update = false;
         /* Aggiungiamo libri al di fuori della cartella libri */
 
-        for fs_book in saved_books.into_iter() {
-            match fs::metadata(fs_book.1.path.clone()) {
-                Ok(_) => self.library.push_back(fs_book.1.clone()),
+        for fs_book in saved_books.iter_mut() {
+            /* Se il file è stato spostato ma era stato unito a un duplicato usiamo l'altra copia */
+            if fs::metadata(fs_book.path.clone()).is_err() {
+                if let Some(alt) = fs_book
+                    .alt_paths
+                    .iter()
+                    .position(|p| fs::metadata(p).is_ok())
+                {
+                    fs_book.path = fs_book.alt_paths.remove(alt);
+                    // La copia unita può avere un contenuto diverso, l'hash va ricalcolato
+                    fs_book.content_hash = String::new();
+                    file_need_update = true;
+                }
+            }
+            match fs::metadata(fs_book.path.clone()) {
+                Ok(_) => {
+                    let mut book_info = fs_book.clone();
+                    /* meta.json salvati prima dell'introduzione dell'id */
+                    if book_info.id.is_empty() {
+                        book_info.id = BookInfo::book_id(&book_info.path);
+                        file_need_update = true;
+                    }
+                    /* meta.json salvati prima dell'hash del contenuto: si calcola una volta sola */
+                    if book_info.content_hash.is_empty() {
+                        book_info.content_hash = file_hash(&book_info.path).unwrap_or_default();
+                        file_need_update = true;
+                    }
+                    self.library.push_back(book_info)
+                }
                 Err(_) => {
                     file_need_update = true;
-                    println!("File not found at path {}", fs_book.1.path.clone())
+                    problems.push(LibraryError::MissingFile(fs_book.path.clone()))
                 }
             }
         }
@@ -259,18 +670,92 @@ impl BookCase {
         file_need_update
     }
 
-    pub fn update_meta(&self) {
-        /* Write file containing our BookInfos */
-        fs::write(FILE_NAME, "").expect("Failed to write to output.txt");
-        let mut output = match OpenOptions::new().write(true).open(FILE_NAME) {
-            Ok(out) => out,
-            Err(_) => OpenOptions::new()
-                .create_new(true)
-                .write(true)
-                .open(FILE_NAME)
-                .unwrap(),
+    /*
+    Cerca nella libreria lo stesso libro importato da un altro path o in un'altra edizione
+    */
+    pub fn find_duplicate(&self, candidate: &BookInfo) -> Option<Duplicate> {
+        let duplicate = |existing: usize, kind: DuplicateKind| Duplicate {
+            existing,
+            candidate: candidate.clone(),
+            kind,
         };
+
+        if !candidate.content_hash.is_empty() {
+            if let Some(id) = self
+                .library
+                .iter()
+                .position(|b| b.content_hash == candidate.content_hash)
+            {
+                return Some(duplicate(id, DuplicateKind::SameFile));
+            }
+        }
+        if let Some(id) = self.library.iter().position(|b| b.id == candidate.id) {
+            return Some(duplicate(id, DuplicateKind::SameIdentifier));
+        }
+        let normalize = |s: &str| s.trim().to_lowercase();
+        if candidate.title != "unknown" {
+            if let Some(id) = self.library.iter().position(|b| {
+                normalize(&b.title) == normalize(&candidate.title)
+                    && normalize(&b.creator) == normalize(&candidate.creator)
+            }) {
+                return Some(duplicate(id, DuplicateKind::SameTitle));
+            }
+        }
+        None
+    }
+
+    /*
+    Merge: il libro esistente ricorda il nuovo path come copia alternativa
+    Replace: il libro esistente punta al nuovo file mantenendo posizione di lettura e dati OCR
+    KeepBoth: il nuovo libro viene aggiunto come voce separata
+    */
+    pub fn resolve_duplicate(
+        &mut self,
+        duplicate: Duplicate,
+        choice: DuplicateChoice,
+    ) -> Result<(), LibraryError> {
+        let existing = &mut self.library[duplicate.existing];
+        match choice {
+            DuplicateChoice::Merge => {
+                if !existing.alt_paths.contains(&duplicate.candidate.path) {
+                    existing.alt_paths.push_back(duplicate.candidate.path);
+                }
+            }
+            DuplicateChoice::Replace => {
+                let mut replacement = duplicate.candidate;
+                replacement.start_chapter = existing.start_chapter;
+                replacement.start_element_number = existing.start_element_number;
+                replacement.ocr = existing.ocr.clone();
+                replacement.mapped_pages = existing.mapped_pages.clone();
+                replacement.edition = existing.edition.clone();
+                replacement.editions = existing.editions.clone();
+                replacement.alt_paths = existing.alt_paths.clone();
+                *existing = replacement;
+            }
+            DuplicateChoice::KeepBoth => self.library.push_back(duplicate.candidate),
+        }
+        self.update_meta()
+    }
+
+    pub fn save_position(&mut self, path: &str, nav: Navigation) -> Result<(), LibraryError> {
+        for book_info in self.library.iter_mut() {
+            if book_info.path == path {
+                book_info.start_chapter = nav.get_ch();
+                book_info.start_element_number = nav.get_element_numer();
+                break;
+            }
+        }
+        self.update_meta()
+    }
+
+    /* Write file containing our BookInfos: prima in un file temporaneo, così un errore non tronca la libreria */
+    pub fn update_meta(&self) -> Result<(), LibraryError> {
         let ser_book_case: SerializableBookCase = self.clone().into();
-        serde_json::to_writer(&mut output, &ser_book_case).unwrap();
+        let json =
+            serde_json::to_string(&ser_book_case).map_err(|e| LibraryError::Meta(e.to_string()))?;
+        let tmp = format!("{}.tmp", FILE_NAME);
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, FILE_NAME))
+            .map_err(|e| LibraryError::Meta(e.to_string()))
     }
 }
@@ -0,0 +1,333 @@
+use crate::book::Book;
+use crate::bookcase::{BookCase, BookInfo};
+use crate::layout::LayoutModel;
+use crate::ocr::{find_matches, is_ambiguous, WordIndex};
+use crate::settings::Settings;
+use crate::utilities::{ocr_image, xml_to_text};
+use crate::view::view::View;
+use rayon::ThreadPoolBuilder;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage:
+    ebook_reader                          start the graphical interface
+    ebook_reader list                     list the books in the library (meta.json)
+    ebook_reader import <epub>...         add epub files to the library
+    ebook_reader text <book> [chapter]    print the chapters of a book as plain text
+    ebook_reader pages <book>             print the paper book page mapping of a book
+    ebook_reader ocr <book> <image>       find the position of a photographed page in a book
+    ebook_reader bench <book> [image]     time loading and OCR search with one thread and with all cores
+
+<book> is the index shown by 'list', the path of the epub or its title.";
+
+/*
+Modalità a riga di comando: nessuna finestra, lavora direttamente su meta.json
+*/
+pub fn run(args: &[String]) -> i32 {
+    let result = match args
+        .iter()
+        .map(|a| a.as_str())
+        .collect::<Vec<&str>>()
+        .as_slice()
+    {
+        ["list"] => list(),
+        ["import", paths @ ..] if !paths.is_empty() => import(paths),
+        ["text", book] => text(book, None),
+        ["text", book, chapter] => match chapter.parse::<usize>() {
+            Ok(chapter) => text(book, Some(chapter)),
+            Err(_) => Err(format!("Invalid chapter number '{}'", chapter)),
+        },
+        ["pages", book] => pages(book),
+        ["ocr", book, image] => ocr(book, image),
+        ["bench", book] => bench(book, None),
+        ["bench", book, image] => bench(book, Some(image)),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn find_book(bookcase: &BookCase, selector: &str) -> Result<BookInfo, String> {
+    if let Ok(id) = selector.parse::<usize>() {
+        return bookcase
+            .library
+            .get(id)
+            .cloned()
+            .ok_or(format!("No book with index {}", id));
+    }
+    bookcase
+        .library
+        .iter()
+        .find(|b| b.path == selector || b.name == selector || b.title == selector)
+        .cloned()
+        .ok_or(format!("No book matching '{}' in the library", selector))
+}
+
+fn load_book(book_info: &BookInfo) -> Result<Book, String> {
+    Book::new(book_info.get_path(), 0, 0, &book_info.mapped_pages)
+        .map_err(|e| format!("Couldn't load book: {}", e))
+}
+
+/* Le interrogazioni non riscrivono mai meta.json, solo import lo aggiorna */
+fn library() -> Result<BookCase, String> {
+    BookCase::read_only().map_err(|e| e.to_string())
+}
+
+fn list() -> Result<(), String> {
+    let bookcase = library()?;
+    for (id, book_info) in bookcase.library.iter().enumerate() {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            id,
+            book_info.title,
+            book_info.creator,
+            book_info.language,
+            if book_info.mapped_pages.is_empty() {
+                "-"
+            } else {
+                "aligned"
+            },
+            book_info.path
+        );
+    }
+    Ok(())
+}
+
+fn import(paths: &[&str]) -> Result<(), String> {
+    let mut bookcase = BookCase::new();
+    let mut failed = false;
+    for &path in paths {
+        let path = match PathBuf::from(path).canonicalize() {
+            Ok(p) => p.to_str().unwrap_or(path).to_string(),
+            Err(_) => path.to_string(),
+        };
+        if bookcase.library.iter().any(|b| b.path == path) {
+            eprintln!("{}: book already in library", path);
+            continue;
+        }
+        match BookInfo::new(path.clone()) {
+            Ok(book_info) => match bookcase.find_duplicate(&book_info) {
+                Some(duplicate) => {
+                    eprintln!("{}", duplicate.describe(&bookcase.library));
+                    failed = true;
+                }
+                None => {
+                    println!("{}: imported '{}'", path, book_info.title);
+                    bookcase.library.push_back(book_info);
+                }
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    bookcase.update_meta().map_err(|e| e.to_string())?;
+    if failed {
+        return Err("Some books were not imported".to_string());
+    }
+    Ok(())
+}
+
+fn text(selector: &str, chapter: Option<usize>) -> Result<(), String> {
+    let book = load_book(&find_book(&library()?, selector)?)?;
+    match chapter {
+        Some(id) => {
+            let ch = book.chapters.get(id).ok_or(format!(
+                "The book has only {} chapters",
+                book.chapters.len()
+            ))?;
+            println!("{}", xml_to_text(&ch.xml));
+        }
+        None => {
+            for ch in book.chapters.iter() {
+                println!("{}", xml_to_text(&ch.xml));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pages(selector: &str) -> Result<(), String> {
+    let book_info = find_book(&library()?, selector)?;
+    let book = load_book(&book_info)?;
+    /* Senza allineamento valgono le pagine indicate dall'editore nell'epub */
+    let publisher = book_info.ocr.publisher_pages
+        || (book_info.mapped_pages.is_empty() && !book.print_pages.is_empty());
+    if book_info.mapped_pages.is_empty() && !publisher {
+        return Err("The book is not aligned with a paper book".to_string());
+    }
+    let ocr = &book_info.ocr;
+    let model = LayoutModel::new(ocr);
+    if publisher {
+        println!("pages from the publisher's page list");
+    } else {
+        println!(
+            "page lines: {}, chapter start: {:.1} lines, average characters per line: {:.1}, \
+             heading scale: {:.2}",
+            model.page_lines, model.chapter_start, model.chars_per_line, model.heading_scale
+        );
+    }
+    let mut view = View::new();
+    for (id, ch) in book.chapters.iter().enumerate() {
+        if ch.initial_page == 0 {
+            println!("{}\t-\t{}", id, ch.get_path());
+            continue;
+        }
+        view.update_view(ch.format(None, None, &book.path));
+        let pages = if publisher {
+            view.last_page()
+                .map_or(1, |last| last.saturating_sub(ch.initial_page) + 1)
+        } else if model.is_part(&view.current_view) {
+            1
+        } else {
+            view.guess_lines(&model, ch.initial_page).unwrap_or(1)
+        };
+        println!(
+            "{}\t{}-{}\t{}",
+            id,
+            ch.initial_page,
+            ch.initial_page + pages - 1,
+            ch.get_path()
+        );
+    }
+    Ok(())
+}
+
+fn ocr(selector: &str, image: &str) -> Result<(), String> {
+    let book_info = find_book(&library()?, selector)?;
+    let book = load_book(&book_info)?;
+    let settings = Settings::load();
+    let text = ocr_image(PathBuf::from(image), &book_info.language, &settings)
+        .map_err(|e| format!("Couldn't load image: {}", e))?
+        .text;
+    let index = WordIndex::new(&book.chapters);
+    let matches = find_matches(
+        text,
+        &index,
+        settings.match_threshold,
+        settings.match_strategy,
+    );
+    let best = matches
+        .first()
+        .ok_or("No matches were found, please try again with a better quality image.")?;
+    let (ch, offset) = (best.ch, best.offset);
+
+    let mut view = View::new();
+    view.update_view(book.chapters[ch].format(None, None, &book.path));
+    let element = view.ocr_offset_to_element(offset);
+    print!("chapter {}, element {}", ch, element);
+
+    let ocr = &book_info.ocr;
+    if ocr.has_pages() && book.chapters[ch].initial_page != 0 {
+        if !ocr.publisher_pages {
+            let _ = view.guess_lines(&LayoutModel::new(ocr), book.chapters[ch].initial_page);
+        }
+        if let Some(el) = view.current_view.get(element) {
+            print!(", paper page {}", el.pg_offset.0);
+        }
+    }
+    println!(" (similarity {:.0}%)", best.score * 100.);
+
+    if is_ambiguous(&matches) {
+        println!("Other possible positions:");
+        for m in matches.iter().skip(1) {
+            println!(
+                "  chapter {}, similarity {:.0}%: {}",
+                m.ch,
+                m.score * 100.,
+                m.snippet
+            );
+        }
+    }
+    Ok(())
+}
+
+const BENCH_PAGE_WORDS: usize = 300; // Parole della pagina simulata quando non c'è una foto
+const BENCH_RUNS: usize = 3;
+
+/*
+Tempi di apertura del libro, indicizzazione e ricerca OCR con un solo thread e con tutti i core.
+Senza foto la pagina cercata è un pezzo del capitolo centrale
+*/
+fn bench(selector: &str, image: Option<&str>) -> Result<(), String> {
+    let book_info = find_book(&library()?, selector)?;
+    let settings = Settings::load();
+    let text = match image {
+        Some(image) => {
+            ocr_image(PathBuf::from(image), &book_info.language, &settings)
+                .map_err(|e| format!("Couldn't load image: {}", e))?
+                .text
+        }
+        None => {
+            let book = load_book(&book_info)?;
+            let ch = &book.chapters[book.chapters.len() / 2];
+            let text = xml_to_text(&ch.xml);
+            let words = text.split_whitespace().collect::<Vec<&str>>();
+            let start = words.len().saturating_sub(BENCH_PAGE_WORDS) / 2;
+            words[start..(start + BENCH_PAGE_WORDS).min(words.len())].join(" ")
+        }
+    };
+
+    let mut results = Vec::new();
+    for threads in [1, num_cpus::get()] {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| e.to_string())?;
+        /* Si tiene il tempo migliore di alcune esecuzioni per ridurre il rumore */
+        let mut times = [Duration::MAX; 3]; // Apertura, indice, ricerca
+        let mut best = None;
+        for _ in 0..BENCH_RUNS {
+            let (matches, run) = pool.install(|| -> Result<_, String> {
+                let start = Instant::now();
+                let book = load_book(&book_info)?;
+                let loaded = start.elapsed();
+                let index = WordIndex::new(&book.chapters);
+                let indexed = start.elapsed();
+                let matches = find_matches(
+                    text.clone(),
+                    &index,
+                    settings.match_threshold,
+                    settings.match_strategy,
+                );
+                let found = start.elapsed();
+                Ok((matches, [loaded, indexed - loaded, found - indexed]))
+            })?;
+            for (time, run) in times.iter_mut().zip(run) {
+                *time = (*time).min(run);
+            }
+            best = matches.first().map(|m| (m.ch, m.offset));
+        }
+        println!(
+            "{} threads: load {:?}, index {:?}, search {:?}",
+            threads, times[0], times[1], times[2]
+        );
+        results.push((times, best));
+    }
+
+    let (sequential, parallel) = (&results[0], &results[results.len() - 1]);
+    if sequential.1 != parallel.1 {
+        return Err("The best match changes with the number of threads".to_string());
+    }
+    let total = |times: &[Duration; 3]| times.iter().sum::<Duration>().as_secs_f64();
+    println!(
+        "speedup {:.2}x, best match {}",
+        total(&sequential.0) / total(&parallel.0),
+        match sequential.1 {
+            Some((ch, offset)) => format!("chapter {}, word {}", ch, offset),
+            None => "none".to_string(),
+        }
+    );
+    Ok(())
+}
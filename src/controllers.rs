@@ -1,5 +1,12 @@
+use crate::app::ApplicationState;
+use crate::image_source::{inbox_images, ImageSource};
 use druid::widget::Controller;
-use druid::{Data, Env, Event, EventCtx, Widget};
+use druid::{Data, Env, Event, EventCtx, TimerToken, Widget};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const INBOX_POLL: Duration = Duration::from_secs(2);
 
 pub struct Update<T> {
     action: Box<dyn Fn(&mut EventCtx, &mut T, &Env)>,
@@ -25,6 +32,73 @@ impl<T: Data, W: Widget<T>> Controller<T, W> for Update<T> {
     }
 }
 
+/*
+Controlla la cartella delle foto in arrivo: una foto nuova viene passata subito all'OCR, così
+fotografare la pagina basta per saltarci
+*/
+pub struct InboxWatcher {
+    timer: TimerToken,
+    known: Option<(PathBuf, HashSet<PathBuf>)>, // Cartella e foto già viste
+}
+
+impl InboxWatcher {
+    pub fn new() -> Self {
+        InboxWatcher {
+            timer: TimerToken::INVALID,
+            known: None,
+        }
+    }
+
+    /* Foto comparse dall'ultimo controllo, alla prima lettura della cartella nessuna */
+    fn arrived(&mut self, data: &ApplicationState) -> Vec<PathBuf> {
+        let folder = match data.settings.inbox() {
+            Some(folder) if data.settings.image_source == ImageSource::Inbox => folder,
+            _ => {
+                self.known = None;
+                return Vec::new();
+            }
+        };
+        let images = match inbox_images(folder) {
+            Ok(images) => images,
+            Err(_) => return Vec::new(),
+        };
+        let arrived = match &self.known {
+            Some((known_folder, known)) if known_folder.as_path() == folder => images
+                .iter()
+                .filter(|image| !known.contains(*image))
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        };
+        self.known = Some((folder.to_path_buf(), images.into_iter().collect()));
+        arrived
+    }
+}
+
+impl<W: Widget<ApplicationState>> Controller<ApplicationState, W> for InboxWatcher {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut ApplicationState,
+        env: &Env,
+    ) {
+        match event {
+            Event::WindowConnected => self.timer = ctx.request_timer(INBOX_POLL),
+            Event::Timer(token) if *token == self.timer => {
+                let arrived = self.arrived(data);
+                if !arrived.is_empty() {
+                    data.inbox_arrived(arrived, ctx.get_external_handle());
+                }
+                self.timer = ctx.request_timer(INBOX_POLL);
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
 /*
 pub struct ClickableOpacity <T: Data> {
     action: Box<dyn Fn(&mut EventCtx, &mut T, &Env)>,
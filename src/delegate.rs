@@ -1,22 +1,36 @@
 use std::env::current_dir;
 use std::fs;
 use crate::app::{
-    InputMode, FINISH_BOOK_LOAD, FINISH_IMAGE_LOAD, FINISH_LEPTO_LOAD, FINISH_SLOW_FUNCTION,
+    InputMode, CANCEL_JOB, DISMISS_NOTIFICATION, FINISH_BOOK_LOAD, FINISH_IMAGE_LOAD,
+    FINISH_INDEX, FINISH_LEPTO_LOAD, FINISH_LEPTO_LOAD_MANY, FINISH_PAGE_MAPPING,
+    FINISH_PREPROCESS, FINISH_SLOW_FUNCTION, JOB_DONE, JOB_PROGRESS, OPEN_READER_WINDOW,
 };
 use crate::book::page_element::{ContentType, ImageState};
 use crate::book::Book;
-use crate::bookcase::BookInfo;
+use crate::bookcase::{file_hash, BookInfo, DEFAULT_EDITION};
+use crate::errors::{log, BookError, LibraryError, Severity};
 use crate::ocr::OcrData;
-use crate::utilities::th_lepto_load;
+use crate::view::render::build_reader_window;
 use crate::ApplicationState;
 use druid::commands::{OPEN_PANEL_CANCELLED, SAVE_PANEL_CANCELLED};
 use druid::im::Vector;
-use druid::{commands, AppDelegate, Command, DelegateCtx, Env, Handled, Target};
+use druid::{
+    commands, AppDelegate, Command, DelegateCtx, Env, Handled, Target, WindowDesc, WindowId,
+};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-extern crate num_cpus;
+pub(crate) struct Delegate {
+    readers: HashMap<WindowId, usize>, // Finestre secondarie -> id del ReaderWindow in ApplicationState
+}
 
-pub(crate) struct Delegate {}
+impl Delegate {
+    pub fn new() -> Self {
+        Delegate {
+            readers: HashMap::new(),
+        }
+    }
+}
 
 impl AppDelegate<ApplicationState> for Delegate {
     fn command(
@@ -28,18 +42,18 @@ impl AppDelegate<ApplicationState> for Delegate {
         _env: &Env,
     ) -> Handled {
         if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
-            let target_path = file_info.path.clone().to_str().unwrap().to_string();
+            let target_path = file_info.path.to_string_lossy().to_string();
 
             if let Err(e) = data.book_to_view.save(data.modified.clone(), target_path.clone()){
-                data.error_message =
-                    Option::Some("Impossible to save epub: ".to_string() + &e.to_string());
+                data.report(&BookError::Save(target_path, e.to_string()));
 
-                let mut dir = current_dir().unwrap().to_str().unwrap().to_string();
-                dir.push_str("/tmp/");
-                let path_dir = PathBuf::from(&dir);
-                if path_dir.is_dir() {
-                    fs::remove_dir_all(&dir).unwrap();
-                    println!("removed dir");
+                /* Cartella di lavoro lasciata dal salvataggio fallito */
+                if let Ok(dir) = current_dir().map(|dir| dir.join("tmp")) {
+                    if dir.is_dir() {
+                        if let Err(e) = fs::remove_dir_all(&dir) {
+                            log(Severity::Warning, &format!("{}: {}", dir.display(), e));
+                        }
+                    }
                 }
 
                 data.is_loading = false;
@@ -48,6 +62,7 @@ impl AppDelegate<ApplicationState> for Delegate {
             data.modified.clear();
 
             let mut current = data.get_current_book_info().clone();
+            let content_hash = file_hash(&target_path).unwrap_or_default();
             //Il currentpath diventa quello del nuovo libro
             match data
                 .bookcase
@@ -60,27 +75,23 @@ impl AppDelegate<ApplicationState> for Delegate {
                     b_info.start_element_number = current.start_element_number;
                     b_info.ocr = OcrData::new();
                     b_info.mapped_pages = Vector::new();
-                    b_info.name = PathBuf::from(target_path.clone())
-                        .file_stem()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string();
-                    data.bookcase.update_meta();
+                    b_info.edition = DEFAULT_EDITION.to_string();
+                    b_info.editions = Vector::new();
+                    b_info.name = file_stem(&target_path);
+                    b_info.content_hash = content_hash;
+                    data.save_library();
                 }
                 None => {
                     current.path = target_path.clone();
-                    current.name = PathBuf::from(target_path.clone())
-                        .file_stem()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string();
+                    current.name = file_stem(&target_path);
+                    current.content_hash = content_hash;
                     current.ocr = OcrData::new();
                     current.mapped_pages = Vector::new();
+                    current.edition = DEFAULT_EDITION.to_string();
+                    current.editions = Vector::new();
 
                     data.bookcase.library.push_back(current);
-                    data.bookcase.update_meta();
+                    data.save_library();
                 }
             }
             data.set_book_to_read(Book::empty_book());
@@ -89,136 +100,93 @@ impl AppDelegate<ApplicationState> for Delegate {
             return Handled::Yes;
         }
 
+        if let Some(files) = cmd.get(commands::OPEN_FILES) {
+            if data.i_mode == InputMode::OcrSyn2 {
+                data.load_ocr_images(
+                    files.iter().map(|f| f.path.clone()).collect(),
+                    ctx.get_external_handle(),
+                );
+            }
+            return Handled::Yes;
+        }
+
         if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
             match data.i_mode {
                 InputMode::OcrJump | InputMode::OcrSyn0 | InputMode::OcrSyn1 => {
 
                     /* Qui stiamo prendendo un immagine per usare l'OCR */
-                    th_lepto_load(
-                        ctx.get_external_handle(),
-                        file_info.path.clone(),
-                        &data.get_current_book_info().language,
-                    );
+                    data.load_ocr_images(vec![file_info.path.clone()], ctx.get_external_handle());
                 }
                 InputMode::EbookAdd => {
-                    if data
-                        .bookcase
-                        .library
-                        .iter()
-                        .find(|el| el.path == file_info.path.clone().to_str().unwrap().to_string())
-                        .is_some()
-                    {
-                        data.error_message = Some("Book already in library".to_string());
+                    let path = file_info.path.to_string_lossy().to_string();
+                    if data.bookcase.library.iter().any(|el| el.path == path) {
+                        data.report(&LibraryError::AlreadyInLibrary(path));
                     } else {
-                        match BookInfo::new(file_info.path.clone().to_str().unwrap().to_string()) {
-                            Ok(b) => {
-                                data.bookcase.library.push_back(b);
-                                data.bookcase.update_meta();
-                            }
-                            Err(_) => {
-                                data.error_message =
-                                    Option::Some("Impossible to open selected Epub".to_string());
-                            }
+                        match BookInfo::new(path) {
+                            Ok(b) => match data.bookcase.find_duplicate(&b) {
+                                Some(duplicate) => data.duplicate = Some(duplicate),
+                                None => {
+                                    data.bookcase.library.push_back(b);
+                                    data.save_library();
+                                }
+                            },
+                            Err(e) => data.report(&e),
                         }
                         data.i_mode = InputMode::None;
                     }
                     data.is_loading = false;
                 }
+                InputMode::CoverAdd => {
+                    if let Some(id) = data.meta_edit {
+                        if let Err(e) = data.bookcase.library[id].set_custom_cover(&file_info.path) {
+                            data.report(&e);
+                        }
+                    }
+                    data.i_mode = InputMode::None;
+                    data.is_loading = false;
+                }
                 _ => (),
             }
             return Handled::Yes;
         }
 
-        if let Some(res) = cmd.get(FINISH_SLOW_FUNCTION) {
+        if let Some((matches, page)) = cmd.get(FINISH_SLOW_FUNCTION) {
             // If the command we received is `FINISH_SLOW_FUNCTION` handle the payload.
-            if let Some((ch, off, str)) = res {
-                match data.i_mode {
-                    InputMode::OcrJump => {
-                        data.book_to_view.get_mut_nav().set_ch(*ch);
-                        data.update_view(ctx.get_external_handle());
+            data.ocr_matches(matches.clone(), page.clone(), ctx.get_external_handle());
+            return Handled::Yes;
+        }
 
-                        data.book_to_view
-                            .get_mut_nav()
-                            .set_element_number(data.view.ocr_offset_to_element(*off));
+        if let Some(preview) = cmd.get(FINISH_PREPROCESS) {
+            data.wizard.preview = Some(preview.clone());
+            return Handled::Yes;
+        }
 
-                        if data.get_current_book_info().ocr.is_aligned() {
-                            let _ = data
-                                .get_mut_current_book_info()
-                                .unwrap()
-                                .ocr
-                                .ocr_log(str.clone());
-                        }
-                    }
-                    InputMode::OcrSyn0 => {
-                        match data
-                            .get_mut_current_book_info()
-                            .unwrap()
-                            .ocr
-                            .ocr_log_first(str.clone(), *ch)
-                        {
-                            Ok(_) => data.view.ocr_form_stage = 3,
-                            Err(_) => {
-                                data.error_message = Some(
-                                    "Image not recognized, please try with another image."
-                                        .to_string(),
-                                )
-                            }
-                        };
-                    }
-                    InputMode::OcrSyn1 => {
-                        match data
-                            .get_mut_current_book_info()
-                            .unwrap()
-                            .ocr
-                            .ocr_log_other(str.clone())
-                        {
-                            Ok(_) => data.view.ocr_form_stage = 5,
-                            Err(_) => {
-                                data.error_message = Some(
-                                    "Image not recognized, please try with another image."
-                                        .to_string(),
-                                )
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            } else {
-                data.error_message = Some(
-                    "No matches were found, please try again with a better quality image."
-                        .to_string(),
-                );
-                data.book_to_view = Book::empty_book();
-            }
-            data.i_mode = InputMode::None;
-            data.is_loading = false;
+        if let Some(pages) = cmd.get(FINISH_LEPTO_LOAD_MANY) {
+            data.ocr_log_pages(pages.clone());
             return Handled::Yes;
         }
 
-        if let Some(str) = cmd.get(FINISH_LEPTO_LOAD) {
-            match str {
-                Some(str) => match data.i_mode {
-                    InputMode::OcrJump => data.ocr_jump(ctx.get_external_handle(), str.to_string()),
-                    InputMode::OcrSyn0 => data.ocr_jump(ctx.get_external_handle(), str.to_string()),
-                    InputMode::OcrSyn1 => data.ocr_jump(ctx.get_external_handle(), str.to_string()),
+        if let Some(page) = cmd.get(FINISH_LEPTO_LOAD) {
+            match page {
+                Ok(page) => match data.i_mode {
+                    InputMode::OcrJump => data.ocr_jump(ctx.get_external_handle(), page.clone()),
+                    InputMode::OcrSyn0 => data.ocr_jump(ctx.get_external_handle(), page.clone()),
+                    InputMode::OcrSyn1 => data.ocr_jump(ctx.get_external_handle(), page.clone()),
                     _ => {}
                 },
-                None => {
-                    data.error_message = Some("Couldn't load image".to_string());
-                    data.book_to_view = Book::empty_book();
-                }
+                Err(e) => data.ocr_failed(Some(e.clone())),
             }
             return Handled::Yes;
         }
 
         if let Some(book) = cmd.get(FINISH_BOOK_LOAD) {
             match book {
-                Some(book) => {
+                Ok(book) => {
                     data.set_book_to_read(book.clone());
                     data.update_view(ctx.get_external_handle());
                 }
-                None => {
-                    data.error_message = Some("Couldn't load book".to_string());
+                Err(e) => {
+                    data.report(e);
                     data.book_to_view = Book::empty_book();
                 }
             }
@@ -226,6 +194,66 @@ impl AppDelegate<ApplicationState> for Delegate {
             return Handled::Yes;
         }
 
+        if let Some((id, fraction)) = cmd.get(JOB_PROGRESS) {
+            data.jobs.progress(*id, *fraction);
+            return Handled::Yes;
+        }
+
+        if let Some(id) = cmd.get(JOB_DONE) {
+            data.jobs.finish(*id);
+            return Handled::Yes;
+        }
+
+        if let Some(id) = cmd.get(CANCEL_JOB) {
+            data.cancel_job(*id);
+            return Handled::Yes;
+        }
+
+        if let Some(id) = cmd.get(DISMISS_NOTIFICATION) {
+            data.notifications.dismiss(*id);
+            return Handled::Yes;
+        }
+
+        if let Some((path, index)) = cmd.get(FINISH_INDEX) {
+            /* Il libro potrebbe essere stato chiuso o modificato mentre l'indice veniva calcolato */
+            for book in [&mut data.book_to_view, &mut data.book_to_align] {
+                if book.path == *path && book.index.is_none() {
+                    book.index = Some(index.clone());
+                }
+            }
+            return Handled::Yes;
+        }
+
+        if let Some((path, pages)) = cmd.get(FINISH_PAGE_MAPPING) {
+            match pages {
+                Ok(pages) => {
+                    data.finish_page_mapping(path, pages.clone(), ctx.get_external_handle())
+                }
+                Err(e) => data.report(e),
+            }
+            return Handled::Yes;
+        }
+
+        if let Some(book_info) = cmd.get(OPEN_READER_WINDOW) {
+            match Book::new(
+                book_info.get_path(),
+                book_info.start_chapter,
+                book_info.start_element_number,
+                &book_info.mapped_pages,
+            ) {
+                Ok(book) => {
+                    let id = data.open_reader(book, book_info.ocr.clone(), ctx.get_external_handle());
+                    let window = WindowDesc::new(build_reader_window(id))
+                        .title(book_info.title.clone())
+                        .window_size(data.view.get_window_size_view());
+                    self.readers.insert(window.id, id);
+                    ctx.new_window(window);
+                }
+                Err(e) => data.report(&e),
+            }
+            return Handled::Yes;
+        }
+
         if let Some((img, path)) = cmd.get(FINISH_IMAGE_LOAD) {
             if let Some(element) =
                 data.view
@@ -245,6 +273,15 @@ impl AppDelegate<ApplicationState> for Delegate {
                 }
                 // data.update_view(ctx.get_external_handle());
             }
+            /* L'immagine potrebbe essere stata richiesta da una finestra secondaria */
+            for (_, reader) in data.readers.iter_mut() {
+                for element in reader.view.current_view.iter_mut() {
+                    if element.content == ContentType::Image(ImageState::Waiting(path.clone())) {
+                        element.content = ContentType::Image(ImageState::Present(img.clone()));
+                        reader.book.imgs.entry(path.clone()).or_insert(img.clone());
+                    }
+                }
+            }
             return Handled::Yes;
         }
 
@@ -261,7 +298,11 @@ impl AppDelegate<ApplicationState> for Delegate {
                     data.i_mode = InputMode::None;
                 }
                 InputMode::OcrJump => data.i_mode = InputMode::None,
-                InputMode::OcrSyn1 | InputMode::OcrSyn0 => data.i_mode = InputMode::None,
+                InputMode::OcrSyn1 | InputMode::OcrSyn0 | InputMode::OcrSyn2 => {
+                    data.wizard.loading = false;
+                    data.i_mode = InputMode::None
+                }
+                InputMode::CoverAdd => data.i_mode = InputMode::None,
                 _ => {}
             }
             data.is_loading = false;
@@ -270,4 +311,24 @@ impl AppDelegate<ApplicationState> for Delegate {
 
         Handled::No
     }
+
+    fn window_removed(
+        &mut self,
+        id: WindowId,
+        data: &mut ApplicationState,
+        _env: &Env,
+        _ctx: &mut DelegateCtx,
+    ) {
+        if let Some(reader) = self.readers.remove(&id) {
+            data.close_reader(reader);
+        }
+    }
+}
+
+/* Nome del libro in libreria: il nome del file senza estensione */
+fn file_stem(path: &str) -> String {
+    PathBuf::from(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
 }
@@ -0,0 +1,211 @@
+use druid::im::Vector;
+use druid::{Data, Lens};
+use std::error::Error;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const LOG_FILE: &str = "ebook_reader.log";
+pub const MAX_NOTIFICATIONS: usize = 5; // Le più vecchie vengono scartate, restano comunque nel log
+
+#[derive(Clone, Copy, Data, PartialEq, Debug)]
+pub enum Severity {
+    Info,
+    Warning, // L'operazione è andata avanti con un ripiego, es. un'immagine mancante
+    Error,   // L'operazione richiesta dall'utente non è stata fatta
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Warning => write!(f, "WARNING"),
+            Severity::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/*
+Errori di un sottosistema che l'utente deve vedere: la gravità decide il colore della notifica
+*/
+pub trait Report: fmt::Display {
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+}
+
+/*
+Apertura e lettura di un epub
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookError {
+    Open(String, String),    // Epub, causa
+    Chapter(String, String), // Capitolo illeggibile, causa
+    Image(String, String),   // Immagine mancante o non decodificabile, causa
+    Save(String, String),    // Epub di destinazione, causa
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Open(path, e) => write!(f, "Couldn't open {}: {}", path, e),
+            BookError::Chapter(path, e) => write!(f, "Couldn't read chapter {}: {}", path, e),
+            BookError::Image(path, e) => write!(f, "Couldn't load image {}: {}", path, e),
+            BookError::Save(path, e) => write!(f, "Impossible to save {}: {}", path, e),
+        }
+    }
+}
+
+impl Error for BookError {}
+
+impl Report for BookError {
+    fn severity(&self) -> Severity {
+        match self {
+            BookError::Image(..) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/*
+Libreria: meta.json, copertine e import dei libri
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum LibraryError {
+    Meta(String),             // meta.json illeggibile o non scrivibile, causa
+    Import(String, String),   // Epub, causa
+    Cover(String, String),    // Copertina, causa
+    MissingFile(String),      // Libro della libreria che non è più sul disco
+    AlreadyInLibrary(String), // Epub già importato
+}
+
+impl fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LibraryError::Meta(e) => write!(f, "Library file: {}", e),
+            LibraryError::Import(path, e) => write!(f, "Impossible to open {}: {}", path, e),
+            LibraryError::Cover(path, e) => write!(f, "Couldn't create the cover {}: {}", path, e),
+            LibraryError::MissingFile(path) => write!(f, "Book not found at {}", path),
+            LibraryError::AlreadyInLibrary(path) => write!(f, "{} is already in library", path),
+        }
+    }
+}
+
+impl Error for LibraryError {}
+
+impl Report for LibraryError {
+    fn severity(&self) -> Severity {
+        match self {
+            LibraryError::Meta(_) | LibraryError::Import(..) => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/*
+Lettura delle foto e ricerca nel libro
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum OcrError {
+    Source(String),        // Nessuna foto dalla sorgente scelta, es. appunti vuoti
+    Image(String, String), // Foto, causa
+    Engine(String),        // Tesseract o il programma esterno
+    NoMatch,
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::Source(e) => write!(f, "{}", e),
+            OcrError::Image(path, e) => write!(f, "Couldn't read the image {}: {}", path, e),
+            OcrError::Engine(e) => write!(f, "OCR failed: {}", e),
+            OcrError::NoMatch => write!(
+                f,
+                "No matches were found, please try again with a better quality image."
+            ),
+        }
+    }
+}
+
+impl Error for OcrError {}
+
+impl Report for OcrError {
+    fn severity(&self) -> Severity {
+        match self {
+            OcrError::Source(_) | OcrError::NoMatch => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/*
+Aggiunge una riga al file di log, con l'ora in secondi dall'epoca. Se non si riesce a scrivere
+resta solo lo stderr: il log non deve mai far fallire l'operazione che lo chiama
+*/
+pub fn log(severity: Severity, message: &str) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or(0);
+    let line = format!("{} [{}] {}", time, severity, message);
+    eprintln!("{}", line);
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(LOG_FILE) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct Notification {
+    pub id: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/*
+Notifiche mostrate in cima alla finestra, dalla più vecchia alla più recente
+*/
+#[derive(Clone, Data, Lens, Default)]
+pub struct Notifications {
+    pub items: Vector<Notification>,
+    next: usize,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Lo stesso messaggio ripetuto non si accumula, es. la stessa foto letta due volte */
+    pub fn push(&mut self, severity: Severity, message: &str) {
+        log(severity, message);
+        if self
+            .items
+            .iter()
+            .any(|n| n.severity == severity && n.message == message)
+        {
+            return;
+        }
+        self.items.push_back(Notification {
+            id: self.next,
+            severity,
+            message: message.to_string(),
+        });
+        self.next += 1;
+        while self.items.len() > MAX_NOTIFICATIONS {
+            self.items.pop_front();
+        }
+    }
+
+    pub fn report(&mut self, error: &impl Report) {
+        self.push(error.severity(), &error.to_string());
+    }
+
+    pub fn dismiss(&mut self, id: usize) {
+        self.items.retain(|n| n.id != id);
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
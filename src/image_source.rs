@@ -0,0 +1,135 @@
+use druid::im::HashSet;
+use druid::{Application, Data};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "tif", "tiff", "bmp", "webp"];
+
+/* Formati immagine degli appunti, con i nomi usati da GTK, macOS e Windows */
+const CLIPBOARD_FORMATS: [(&str, &str); 6] = [
+    ("image/png", "png"),
+    ("public.png", "png"),
+    ("PNG", "png"),
+    ("image/jpeg", "jpg"),
+    ("public.jpeg", "jpg"),
+    ("image/bmp", "bmp"),
+];
+
+/*
+Da dove arrivano le foto delle pagine per l'OCR
+*/
+#[derive(Clone, Copy, Data, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ImageSource {
+    Dialog,    // Si sceglie il file ogni volta
+    Inbox,     // Cartella dove la sincronizzazione del telefono lascia le foto
+    Clipboard, // Immagine copiata, oppure il percorso di un file
+}
+
+impl Default for ImageSource {
+    fn default() -> Self {
+        ImageSource::Dialog
+    }
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/* Foto nella cartella, dalla più vecchia alla più recente */
+pub fn inbox_images(folder: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut images = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_image(path))
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect::<Vec<_>>();
+    images.sort();
+    Ok(images.into_iter().map(|(_, path)| path).collect())
+}
+
+/*
+Foto della cartella non ancora lette: tutte per le pagine di prova del wizard, altrimenti solo
+la più recente
+*/
+pub fn unread_images(images: Vec<PathBuf>, read: &HashSet<String>, many: bool) -> Vec<PathBuf> {
+    let mut unread = images
+        .into_iter()
+        .filter(|path| !read.contains(&path.to_string_lossy().to_string()))
+        .collect::<Vec<_>>();
+    if !many && unread.len() > 1 {
+        unread.drain(..unread.len() - 1);
+    }
+    unread
+}
+
+/* Sequenze %XX di un URI: sono i byte UTF-8 del nome, es. %C3%A8 per "è" */
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/* Percorso di una foto copiato come testo, anche come URI file:// */
+pub fn clipboard_path(text: &str) -> Option<PathBuf> {
+    let line = text.lines().next()?.trim();
+    let path = match line.strip_prefix("file://") {
+        Some(uri) => PathBuf::from(percent_decode(uri)),
+        None => PathBuf::from(line),
+    };
+    if is_image(&path) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/* L'immagine negli appunti viene salvata in un file temporaneo per passarla all'OCR */
+pub fn clipboard_image() -> Result<PathBuf, Box<dyn Error>> {
+    let clipboard = Application::global().clipboard();
+    let formats = CLIPBOARD_FORMATS.map(|(format, _)| format);
+    if let Some(format) = clipboard.preferred_format(&formats) {
+        if let Some(data) = clipboard.get_format(format) {
+            let ext = CLIPBOARD_FORMATS
+                .iter()
+                .find(|(f, _)| *f == format)
+                .map(|(_, ext)| *ext)
+                .unwrap_or("png");
+            let path = std::env::temp_dir().join(format!("ebook_reader_clipboard.{}", ext));
+            fs::write(&path, data)?;
+            return Ok(path);
+        }
+    }
+    match clipboard.get_string().as_deref().and_then(clipboard_path) {
+        Some(path) if path.is_file() => Ok(path),
+        Some(path) => Err(format!("{} doesn't exist", path.display()).into()),
+        None => Err("The clipboard doesn't contain an image".into()),
+    }
+}
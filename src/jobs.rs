@@ -0,0 +1,176 @@
+use crate::app::{JOB_DONE, JOB_PROGRESS};
+use crate::errors::{log, Severity};
+use druid::im::Vector;
+use druid::{Data, ExtEventSink, Lens, Selector, Target};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+pub type JobId = usize;
+
+/* Pool condiviso da tutti i lavori in background, un thread per core */
+fn pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(num_cpus::get().max(2))
+            .thread_name(|i| format!("job-{}", i))
+            .build()
+            .expect("failed to create the thread pool")
+    })
+}
+
+/*
+Lavori brevi e numerosi, come le immagini dei capitoli: usano il pool ma non compaiono
+nell'elenco e non si possono annullare
+*/
+pub fn background(work: impl FnOnce() + Send + 'static) {
+    pool().spawn(work);
+}
+
+/*
+Cosa sta facendo il lavoro: serve a rimettere a posto l'interfaccia quando viene annullato
+*/
+#[derive(Clone, Copy, Data, PartialEq, Debug)]
+pub enum JobKind {
+    LoadBook, // Apertura di un libro e calcolo dell'indice
+    Ocr,      // Lettura delle foto e ricerca nel libro
+    Pages,    // Ricalcolo delle pagine cartacee dopo un cambio di impaginazione
+}
+
+#[derive(Clone, Data, Lens, Debug)]
+pub struct Job {
+    pub id: JobId,
+    pub kind: JobKind,
+    pub title: String,
+    pub progress: Option<f64>, // Frazione completata, None finché il lavoro non la comunica
+    pub cancelled: bool,       // Annullato, il thread non ha ancora finito il passo corrente
+    #[data(ignore)]
+    flag: Arc<AtomicBool>,
+}
+
+/*
+Lato thread di un lavoro: controlla l'annullamento e manda avanzamento e risultati alla UI
+*/
+#[derive(Clone)]
+pub struct JobHandle {
+    pub id: JobId,
+    cancelled: Arc<AtomicBool>,
+    sink: ExtEventSink,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn progress(&self, done: usize, total: usize) {
+        if total > 0 && !self.is_cancelled() {
+            let fraction = done.min(total) as f64 / total as f64;
+            self.submit(JOB_PROGRESS, (self.id, fraction));
+        }
+    }
+
+    /*
+    Il risultato di un lavoro annullato viene scartato. L'invio fallisce solo se l'applicazione
+    si sta chiudendo
+    */
+    pub fn submit<T: Any + Send>(&self, selector: Selector<T>, payload: T) {
+        if !self.is_cancelled() {
+            if let Err(e) = self.sink.submit_command(selector, payload, Target::Auto) {
+                log(Severity::Info, &e.to_string());
+            }
+        }
+    }
+
+    fn done(&self) {
+        if let Err(e) = self.sink.submit_command(JOB_DONE, self.id, Target::Auto) {
+            log(Severity::Info, &e.to_string());
+        }
+    }
+}
+
+/*
+Lavori in corso, mostrati nell'elenco in fondo alla finestra
+*/
+#[derive(Clone, Data, Lens, Default)]
+pub struct JobManager {
+    pub jobs: Vector<Job>,
+    next: JobId,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Mette il lavoro in coda nel pool, work riceve la JobHandle per dialogare con la UI */
+    pub fn spawn<F>(&mut self, kind: JobKind, title: &str, sink: ExtEventSink, work: F) -> JobId
+    where
+        F: FnOnce(&JobHandle) + Send + 'static,
+    {
+        let (id, cancelled) = self.register(kind, title);
+        let job = JobHandle {
+            id,
+            cancelled,
+            sink,
+        };
+        pool().spawn(move || {
+            if !job.is_cancelled() {
+                work(&job);
+            }
+            job.done();
+        });
+        id
+    }
+
+    pub(crate) fn register(&mut self, kind: JobKind, title: &str) -> (JobId, Arc<AtomicBool>) {
+        let id = self.next;
+        self.next += 1;
+        let flag = Arc::new(AtomicBool::new(false));
+        self.jobs.push_back(Job {
+            id,
+            kind,
+            title: title.to_string(),
+            progress: None,
+            cancelled: false,
+            flag: flag.clone(),
+        });
+        (id, flag)
+    }
+
+    /* Il lavoro si ferma al prossimo controllo, restituisce cosa stava facendo */
+    pub fn cancel(&mut self, id: JobId) -> Option<JobKind> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id && !job.cancelled)?;
+        job.flag.store(true, Ordering::Relaxed);
+        job.cancelled = true;
+        Some(job.kind)
+    }
+
+    /* Annulla tutti i lavori del tipo dato, es. le letture OCR quando si chiude il wizard */
+    pub fn cancel_kind(&mut self, kind: JobKind) {
+        let ids = self
+            .jobs
+            .iter()
+            .filter(|job| job.kind == kind)
+            .map(|job| job.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    pub fn progress(&mut self, id: JobId, fraction: f64) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.progress = Some(fraction);
+        }
+    }
+
+    pub fn finish(&mut self, id: JobId) {
+        self.jobs.retain(|job| job.id != id);
+    }
+}
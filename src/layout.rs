@@ -0,0 +1,311 @@
+use crate::book::chapter::MAX_SIZE;
+use crate::book::epub_text::{AttributeCase, EpubText};
+use crate::book::page_element::{ContentType, ImageState, PageElement};
+use crate::ocr::OcrData;
+use druid::text::Attribute;
+use druid::{im::Vector, Data, KeyOrValue, Lens};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+const BODY_SIZE: f64 = 16.; // Dimensione del testo normale in Chapter::format
+const LINE_TOLERANCE: usize = 3; // Caratteri di troppo che la giustificazione fa stare nella riga
+const IMAGE_LINE_HEIGHT: usize = 20; // Pixel per riga delle immagini se non conosciamo la pagina
+const MIN_PART_LINES: usize = 4; // Una pagina di parte ha al massimo 3 righe
+const PART_MAX_CHARS: f64 = 80.; // Lunghezza massima di una riga di una parte se non conosciamo la pagina
+const WIDOW_LINES: f64 = 2.; // Righe minime di un paragrafo in fondo o in cima a una pagina
+
+/*
+Come è impaginato il libro cartaceo, impostato per libro. Con calibrate l'altezza dei titoli e lo
+spazio a inizio capitolo vengono misurati sulle foto quando l'OCR dà la posizione delle righe
+*/
+#[derive(Clone, Copy, Data, Lens, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutSettings {
+    #[serde(default)]
+    pub paragraph_spacing: f64, // Righe vuote tra due paragrafi
+    #[serde(default = "default_heading_spacing")]
+    pub heading_spacing: f64, // Righe vuote sopra e sotto un titolo
+    #[serde(default = "default_heading_scale")]
+    pub heading_scale: f64, // Altezza di una riga del titolo più grande (h1) rispetto al testo
+    #[serde(default)]
+    pub chapter_start: f64, // Righe vuote sopra il titolo nella prima pagina di un capitolo
+    #[serde(default = "default_true")]
+    pub widow_control: bool, // Niente righe isolate di un paragrafo, titoli attaccati al testo
+    #[serde(default = "default_true")]
+    pub calibrate: bool,
+}
+
+fn default_heading_spacing() -> f64 {
+    1.
+}
+
+fn default_heading_scale() -> f64 {
+    1.5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        LayoutSettings {
+            paragraph_spacing: 0.,
+            heading_spacing: default_heading_spacing(),
+            heading_scale: default_heading_scale(),
+            chapter_start: 0.,
+            widow_control: true,
+            calibrate: true,
+        }
+    }
+}
+
+/* Ingombro di un elemento, in righe di testo normale */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(f64), // Può essere spezzato tra due pagine
+    Heading(f64),   // Non si spezza e resta nella pagina del testo che segue
+    Image(f64),     // Non si spezza, al massimo riempie una pagina
+    Skip,           // Non stampato (elementi aggiunti da Chapter::format)
+}
+
+/*
+Modello di impaginazione usato per stimare le pagine cartacee: le impostazioni del libro più
+le misure prese dalle foto allineate
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutModel {
+    pub chars_per_line: f64,
+    pub page_lines: f64,
+    pub chapter_start: f64,
+    pub heading_scale: f64,
+    pub heading_spacing: f64,
+    pub paragraph_spacing: f64,
+    pub widow_control: bool,
+    pub block_width: Option<f64>, // Larghezza della giustificazione in interlinee, per le immagini
+}
+
+impl LayoutModel {
+    pub fn new(ocr: &OcrData) -> Self {
+        let settings = ocr.layout;
+        let page_lines = ocr.get_other_page_lines() as f64;
+        let mut model = LayoutModel {
+            chars_per_line: ocr.get_avg_ch(),
+            page_lines,
+            /* Senza misure: le righe che mancano alla prima pagina sono lo spazio iniziale */
+            chapter_start: (page_lines - ocr.get_first_page_lines() as f64).max(0.),
+            heading_scale: settings.heading_scale,
+            heading_spacing: settings.heading_spacing,
+            paragraph_spacing: settings.paragraph_spacing,
+            widow_control: settings.widow_control,
+            block_width: None,
+        };
+        if !settings.calibrate {
+            model.chapter_start = settings.chapter_start;
+        }
+
+        let measured = ocr
+            .mappings
+            .iter()
+            .filter(|m| m.line_pitch > 0)
+            .collect::<Vec<_>>();
+        if measured.is_empty() {
+            return model;
+        }
+        model.block_width = Some(median(
+            measured
+                .iter()
+                .map(|m| m.text_width as f64 / m.line_pitch as f64)
+                .collect(),
+        ));
+        if !settings.calibrate {
+            return model;
+        }
+
+        /* Altezza del testo in interlinee: nella prima pagina manca lo spazio sopra il titolo */
+        let height = |first: bool| {
+            median(
+                measured
+                    .iter()
+                    .filter(|m| m.is_first == first)
+                    .map(|m| m.text_height as f64 / m.line_pitch as f64)
+                    .collect(),
+            )
+        };
+        let (first, other) = (height(true), height(false));
+        if first > 0. && other > 0. {
+            model.chapter_start = (other - first).max(0.);
+        }
+        let scale = median(
+            measured
+                .iter()
+                .filter(|m| m.is_first && m.heading_scale > 1.)
+                .map(|m| m.heading_scale)
+                .collect(),
+        );
+        if scale > 0. {
+            model.heading_scale = scale;
+        }
+        model
+    }
+
+    /* Righe occupate da un testo di chars caratteri scritto con dimensione size */
+    pub fn text_block(&self, chars: usize, size: f64) -> Block {
+        if chars == 0 {
+            return Block::Paragraph(1.);
+        }
+        let scale = self.font_scale(size);
+        let per_line = (self.chars_per_line / scale).max(1.);
+        let mut lines = (chars as f64 / per_line).ceil();
+        let last_line = chars % (per_line as usize).max(1);
+        if lines > 1. && last_line > 0 && last_line <= LINE_TOLERANCE {
+            lines -= 1.;
+        }
+        if size > BODY_SIZE {
+            Block::Heading(lines * scale + 2. * self.heading_spacing)
+        } else {
+            Block::Paragraph(lines + self.paragraph_spacing)
+        }
+    }
+
+    /* Le immagini vengono stampate larghe quanto il testo, senza superare una pagina */
+    pub fn image_block(&self, width: usize, height: usize) -> Block {
+        let lines = match self.block_width {
+            Some(block) if width > 0 => block * height as f64 / width as f64,
+            _ => (height / IMAGE_LINE_HEIGHT) as f64,
+        };
+        Block::Image(lines.min(self.page_lines))
+    }
+
+    /* Un titolo h1 è alto heading_scale righe, gli altri in proporzione alla dimensione */
+    fn font_scale(&self, size: f64) -> f64 {
+        if size <= BODY_SIZE {
+            return 1.;
+        }
+        1. + (self.heading_scale - 1.).max(0.) * (size - BODY_SIZE) / (MAX_SIZE - BODY_SIZE)
+    }
+
+    pub fn block(&self, el: &PageElement) -> Block {
+        if el.not_in_html {
+            return Block::Skip;
+        }
+        match &el.content {
+            ContentType::Text(text) => {
+                self.text_block(text.text.trim().graphemes(true).count(), font_size(text))
+            }
+            ContentType::Image(ImageState::Present(img)) => {
+                self.image_block(img.width(), img.height())
+            }
+            _ => Block::Skip,
+        }
+    }
+
+    /*
+    Impagina i blocchi a partire dalla prima pagina del capitolo (1). Per ogni blocco restituisce
+    la pagina in cui finisce e se inizia in una pagina precedente, più il numero di pagine
+    */
+    pub fn paginate(&self, blocks: &[Block]) -> (Vec<(usize, bool)>, usize) {
+        let capacity = self.page_lines.max(1.);
+        let mut used = self.chapter_start.min(capacity - 1.);
+        let mut page = 1;
+        let mut pages = Vec::with_capacity(blocks.len());
+        for (i, block) in blocks.iter().enumerate() {
+            let mut split = false;
+            match *block {
+                Block::Skip => {}
+                Block::Paragraph(lines) => {
+                    let room = capacity - used;
+                    let orphan = self.widow_control && room < WIDOW_LINES && used > 0.;
+                    if lines <= room {
+                        used += lines;
+                    } else if room <= 0. || orphan {
+                        /* Pagina piena o una riga sola in fondo: il paragrafo va a capo pagina */
+                        page += 1;
+                        used = lines;
+                    } else {
+                        split = true;
+                        let mut rest = lines - room;
+                        if self.widow_control && rest < WIDOW_LINES && lines > 2. * WIDOW_LINES {
+                            rest = WIDOW_LINES;
+                        }
+                        page += 1;
+                        used = rest;
+                    }
+                    while used > capacity {
+                        split = true;
+                        page += 1;
+                        used -= capacity;
+                    }
+                }
+                Block::Heading(lines) | Block::Image(lines) => {
+                    /* Un titolo ha bisogno di almeno qualche riga del testo che lo segue */
+                    let next = match block {
+                        Block::Heading(_) if self.widow_control => next_lines(&blocks[i + 1..]),
+                        _ => 0.,
+                    };
+                    if used > 0. && used + lines + next > capacity {
+                        page += 1;
+                        used = 0.;
+                    }
+                    used += lines.min(capacity);
+                }
+            }
+            pages.push((page, split));
+        }
+        (pages, page)
+    }
+
+    /*
+    Pagina di una parte: al massimo tre testi, ognuno un titolo o più corto di una riga
+    (le immagini decorative non contano)
+    */
+    pub fn is_part(&self, elements: &Vector<PageElement>) -> bool {
+        let line = match self.chars_per_line {
+            chars if chars.is_finite() && chars > 0. => chars,
+            _ => PART_MAX_CHARS,
+        };
+        let texts = elements
+            .iter()
+            .filter_map(|el| match &el.content {
+                ContentType::Text(text) if !el.not_in_html => Some(text),
+                _ => None,
+            })
+            .filter(|text| !text.text.trim().is_empty())
+            .collect::<Vec<&EpubText>>();
+        !texts.is_empty()
+            && texts.len() < MIN_PART_LINES
+            && texts.iter().all(|text| {
+                font_size(text) > BODY_SIZE
+                    || ((text.text.trim().graphemes(true).count() as f64) < line)
+            })
+    }
+}
+
+/* Righe dei paragrafi che seguono un titolo, fino a WIDOW_LINES */
+fn next_lines(blocks: &[Block]) -> f64 {
+    blocks
+        .iter()
+        .find_map(|block| match block {
+            Block::Paragraph(lines) => Some(lines.min(WIDOW_LINES)),
+            Block::Skip => None,
+            _ => Some(0.),
+        })
+        .unwrap_or(0.)
+}
+
+/* Dimensione più grande usata nel testo */
+fn font_size(text: &EpubText) -> f64 {
+    text.get_attributes()
+        .get(&AttributeCase::FontSize)
+        .into_iter()
+        .flatten()
+        .filter_map(|range| match range.get_attribute() {
+            Attribute::FontSize(KeyOrValue::Concrete(size)) => Some(*size),
+            _ => None,
+        })
+        .fold(BODY_SIZE, f64::max)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values.get(values.len() / 2).copied().unwrap_or(0.)
+}
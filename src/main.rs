@@ -2,35 +2,56 @@ mod algorithms;
 mod app;
 mod book;
 mod bookcase;
+mod cli;
 mod controllers;
 mod delegate;
-mod formatters;
+mod errors;
+mod image_source;
+mod jobs;
+mod layout;
 mod ocr;
+mod ocr_backend;
+mod preprocess;
+mod settings;
 mod tests;
 mod utilities;
 mod view;
 mod widgets;
+mod wizard;
 
-use druid::{AppLauncher, WindowDesc};
+use druid::{AppLauncher, WidgetExt, WindowDesc};
+use std::env;
+use std::process;
 use view::view::WINDOW_TITLE;
 
 use crate::app::ApplicationState;
 use crate::book::page_element::ContentType;
 use crate::book::Book;
 use crate::view::render::build_main_view;
+use controllers::InboxWatcher;
 use delegate::Delegate;
+use errors::{log, Severity};
 
 fn main() {
+    // Con degli argomenti l'applicazione parte in modalità riga di comando, senza finestre
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        process::exit(cli::run(&args));
+    }
+
     let app = ApplicationState::new();
 
     // Describe the main Window
-    let main_window = WindowDesc::new(build_main_view())
+    let main_window = WindowDesc::new(build_main_view().controller(InboxWatcher::new()))
         .title(WINDOW_TITLE)
         .window_size(app.view.get_window_size_home());
 
     // Start the Application
-    AppLauncher::with_window(main_window)
-        .delegate(Delegate {})
+    if let Err(e) = AppLauncher::with_window(main_window)
+        .delegate(Delegate::new())
         .launch(app)
-        .expect("Failed to launch application");
+    {
+        log(Severity::Error, &format!("Failed to launch: {}", e));
+        process::exit(1);
+    }
 }
@@ -1,13 +1,22 @@
-use crate::algorithms::OcrAlgorithms;
+use crate::algorithms::{MatchStrategy, OcrAlgorithms};
 use crate::book::chapter::Chapter;
+use crate::errors::{log, Severity};
+use crate::layout::LayoutSettings;
+use crate::ocr_backend::{OcrLine, OcrPage};
 use crate::utilities::xml_to_text;
 use druid::{im::Vector, Data, Lens};
+use rayon::prelude::*;
 use regex;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
 use unicode_segmentation::UnicodeSegmentation;
 
+const MARGIN_GAP: f64 = 1.8; // Distanza minima, in interlinee, tra intestazione e testo
+const MARGIN_HEIGHT: f64 = 1.2; // Altezza massima, rispetto a una riga normale, di un'intestazione
+const FULL_LINE_WIDTH: f64 = 0.9;
+
 #[derive(Copy, Clone, Default, Data, Lens, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Mapping {
     pub page: usize,              //Page number
@@ -15,29 +24,114 @@ pub struct Mapping {
     pub(crate) tot_chars: usize,  //Total sum of characters to get the average
     pub(crate) full_lines: usize, //Number of lines considered to get average characters
     pub page_lines: usize,
+    /* Geometria del testo in pixel della foto, 0 se l'OCR non dà la posizione delle righe */
+    #[serde(default)]
+    pub(crate) line_pitch: u32, // Interlinea
+    #[serde(default)]
+    pub(crate) text_height: u32, // Dall'inizio della prima riga alla fine dell'ultima
+    #[serde(default)]
+    pub(crate) text_width: u32, // Larghezza della giustificazione
+    #[serde(default)]
+    pub(crate) heading_scale: f64, // Altezza della riga più alta rispetto a una riga normale
 }
 
 impl Mapping {
-    pub fn new(str: String) -> Result<Self, Box<dyn Error>> {
+    pub fn new(page: &OcrPage) -> Result<Self, Box<dyn Error>> {
         let mut init = Mapping {
             page: 0,
             is_first: false,
             tot_chars: 0,
             full_lines: 0,
             page_lines: 0,
+            line_pitch: 0,
+            text_height: 0,
+            text_width: 0,
+            heading_scale: 0.,
         };
-        init.page_stats(str)?;
+        /* Senza la posizione delle righe (es. OCR da file di testo) si contano gli a capo */
+        if page.lines.is_empty() {
+            init.page_stats(page.text.clone())?;
+        } else {
+            init.page_layout(&page.lines);
+        }
         Ok(init)
     }
 
+    /*
+    Statistiche dalla posizione delle righe. La prima e l'ultima riga sono intestazione o piè di
+    pagina se contengono solo il numero di pagina, oppure se sono staccate dal testo e non più
+    grandi di una riga normale (il titolo di un capitolo è più grande). Il numero di pagina è
+    il numero all'inizio o alla fine di una di queste. Le righe piene sono quelle larghe quanto
+    la giustificazione del testo
+    */
+    fn page_layout(&mut self, lines: &[OcrLine]) {
+        let mut lines = lines
+            .iter()
+            .filter(|l| !l.text.trim().is_empty())
+            .collect::<Vec<&OcrLine>>();
+        lines.sort_by_key(|l| l.bbox.1);
+        if lines.is_empty() {
+            return;
+        }
+
+        let height = median(lines.iter().map(|l| l.bbox.3 - l.bbox.1).collect());
+        let gaps = lines
+            .windows(2)
+            .map(|w| w[1].bbox.1.saturating_sub(w[0].bbox.1))
+            .collect::<Vec<u32>>();
+        let pitch = median(gaps.clone());
+        let is_margin = |line: &OcrLine, gap: u32| {
+            let only_number = line.text.trim().chars().all(|c| c.is_ascii_digit());
+            let small = (line.bbox.3 - line.bbox.1) as f64 <= height as f64 * MARGIN_HEIGHT;
+            only_number || (gap as f64 > pitch as f64 * MARGIN_GAP && small)
+        };
+
+        let (mut start, mut end) = (0, lines.len());
+        let mut numbers = vec![];
+        if lines.len() > 1 && is_margin(lines[0], gaps[0]) {
+            numbers.push(page_number(&lines[0].text));
+            start = 1;
+        }
+        if end - start > 1 && is_margin(lines[end - 1], gaps[end - 2]) {
+            numbers.push(page_number(&lines[end - 1].text));
+            end -= 1;
+        }
+        if let Some(page) = numbers.into_iter().flatten().next() {
+            self.page = page;
+        }
+
+        let body = &lines[start..end];
+        self.page_lines = body.len();
+        let width = body.iter().map(|l| l.bbox.2 - l.bbox.0).max().unwrap_or(0);
+        let full = body
+            .iter()
+            .filter(|l| (l.bbox.2 - l.bbox.0) as f64 >= width as f64 * FULL_LINE_WIDTH)
+            .map(|l| l.text.trim().graphemes(true).count())
+            .collect::<Vec<usize>>();
+        self.full_lines = full.len();
+        self.tot_chars = full.iter().sum();
+
+        self.line_pitch = pitch;
+        self.text_height = body.last().map_or(0, |l| l.bbox.3) - body[0].bbox.1;
+        self.text_width = width;
+        let tallest = body.iter().map(|l| l.bbox.3 - l.bbox.1).max().unwrap_or(0);
+        self.heading_scale = match height {
+            0 => 0.,
+            _ => tallest as f64 / height as f64,
+        };
+    }
+
     fn page_stats(&mut self, str: String) -> Result<(), Box<dyn Error>> {
         //ARRAY CON UNA LINEA PER OGNI RIGA
         let mut lines = str
             .split("\n")
             .filter(|s| {
                 if s.graphemes(true).count() < 4 {
+                    /* \d riconosce anche cifre non ASCII che parse rifiuta */
                     if let Some(pg_num) = Regex::new(r"\d+").unwrap().captures(s) {
-                        self.page = pg_num[0].parse::<usize>().unwrap();
+                        if let Ok(page) = pg_num[0].parse::<usize>() {
+                            self.page = page;
+                        }
                     }
                     return false;
                 } else {
@@ -79,12 +173,147 @@ impl Mapping {
     }
 }
 
+/*
+Prima pagina cartacea di ogni capitolo di chapters, il primo dei quali è il capitolo first e inizia
+a pagina start. Le ancore sono punti in cui la pagina è nota: tra due ancore la stima viene scalata
+perché coincida con entrambe, dopo l'ultima prosegue con la stima. Le ancore inserite a mano hanno
+la precedenza su start e su quelle dell'OCR, che vengono ignorate se le contraddicono
+*/
+pub fn fit_chapter_pages(
+    chapters: &[ChapterPages],
+    first: usize,
+    start: usize,
+    anchors: &[PageAnchor],
+) -> Vec<usize> {
+    let mut starts = vec![0.];
+    for ch in chapters.iter() {
+        starts.push(starts[starts.len() - 1] + ch.pages as f64);
+    }
+
+    let points = |manual: bool| {
+        let mut points = anchors
+            .iter()
+            .filter(|a| a.manual == manual && a.ch >= first && a.ch < first + chapters.len())
+            .filter_map(|a| {
+                /* L'OCR legge la pagina in cui finisce l'elemento, a mano si indica dove inizia */
+                let (begin, end) = chapters[a.ch - first].element_pages.get(a.element)?;
+                let page = if a.manual { begin } else { end };
+                Some((starts[a.ch - first] + *page as f64, a.page as f64))
+            })
+            .collect::<Vec<(f64, f64)>>();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points
+    };
+    let manual = points(true);
+    let mut fit = Vec::new();
+    for point in manual
+        .iter()
+        .copied()
+        .chain([(0., start as f64)])
+        .chain(points(false))
+    {
+        /* Un punto entra solo se è crescente rispetto ai vicini già presenti */
+        let pos = fit.partition_point(|p: &(f64, f64)| p.0 < point.0);
+        let after_prev = pos == 0 || (fit[pos - 1].0 < point.0 && fit[pos - 1].1 < point.1);
+        let before_next = fit.get(pos).map_or(true, |next: &(f64, f64)| {
+            point.0 < next.0 && point.1 < next.1
+        });
+        if after_prev && before_next {
+            fit.insert(pos, point);
+        }
+    }
+
+    let segment = |estimated: f64| fit.iter().rposition(|p| p.0 <= estimated).unwrap_or(0);
+    let page_at = |estimated: f64| {
+        let i = segment(estimated);
+        let (e0, p0) = fit[i];
+        let scale = match fit.get(i + 1) {
+            Some((e1, p1)) => (p1 - p0) / (e1 - e0),
+            None => 1.,
+        };
+        (p0 + (estimated - e0) * scale).round() as usize
+    };
+    let is_manual = |estimated: f64| fit.iter().any(|p| p.0 == estimated && manual.contains(p));
+
+    /*
+    Una parte su una pagina pari passa alla dispari successiva e sposta anche i capitoli seguenti,
+    finché un'ancora non fissa di nuovo la pagina
+    */
+    let mut pages = Vec::with_capacity(chapters.len());
+    let (mut shift, mut shift_segment) = (0, 0);
+    for (i, ch) in chapters.iter().enumerate() {
+        if segment(starts[i]) != shift_segment {
+            shift = 0;
+            shift_segment = segment(starts[i]);
+        }
+        let page = page_at(starts[i]) + shift;
+        /* Una pagina pari scelta a mano per una parte resta com'è */
+        if ch.part && page % 2 == 0 && !is_manual(starts[i]) {
+            shift += 1;
+            pages.push(page + 1);
+        } else {
+            pages.push(page);
+        }
+    }
+    pages
+}
+
+/*
+Prime pagine dei capitoli scritte a mano: 0 (o vuoto) vuol dire nessuna pagina, le altre non
+possono tornare indietro rispetto ai capitoli precedenti
+*/
+pub fn parse_chapter_pages(inputs: &[String]) -> Result<Vec<usize>, String> {
+    let mut pages = Vec::with_capacity(inputs.len());
+    let mut last: Option<(usize, usize)> = None;
+    for (ch, input) in inputs.iter().enumerate() {
+        let page = match input.trim() {
+            "" => 0,
+            text => text
+                .parse::<usize>()
+                .map_err(|_| format!("Chapter {}: '{}' is not a page number", ch + 1, text))?,
+        };
+        if page != 0 {
+            if let Some((last_ch, last_page)) = last.filter(|(_, last_page)| page < *last_page) {
+                return Err(format!(
+                    "Chapter {} starts at page {}, before chapter {} (page {})",
+                    ch + 1,
+                    page,
+                    last_ch + 1,
+                    last_page
+                ));
+            }
+            last = Some((ch, page));
+        }
+        pages.push(page);
+    }
+    Ok(pages)
+}
+
+fn median(mut values: Vec<u32>) -> u32 {
+    values.sort();
+    values.get(values.len() / 2).copied().unwrap_or(0)
+}
+
+/* Numero da solo, o all'inizio o alla fine di un titolo corrente ("12  I PROMESSI SPOSI") */
+fn page_number(text: &str) -> Option<usize> {
+    let mut words = text.split_whitespace();
+    let first = words.next();
+    [first, words.last()]
+        .into_iter()
+        .flatten()
+        .find(|w| w.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|w| w.parse::<usize>().ok())
+}
+
 #[derive(Default, Clone, Data, Lens, PartialEq, Debug)]
 pub struct OcrData {
     pub mappings: Vector<Mapping>,
     pub first_chap: Option<usize>,
     pub first: Option<usize>,
     pub other: Option<usize>,
+    pub anchors: Vector<PageAnchor>, // Ordinate per capitolo ed elemento
+    pub layout: LayoutSettings,
+    pub publisher_pages: bool, // Pagine lette dai segnaposto dell'epub, esatte e senza foto
 }
 
 #[derive(Default, Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -93,6 +322,36 @@ pub struct SerializableOcrData {
     pub first_chap: Option<usize>,
     pub first: Option<usize>,
     pub other: Option<usize>,
+    #[serde(default)]
+    pub anchors: Vec<PageAnchor>,
+    #[serde(default)]
+    pub layout: LayoutSettings,
+    #[serde(default)]
+    pub publisher_pages: bool,
+}
+
+/*
+Pagina cartacea nota per un elemento del libro: letta da una foto saltando con l'OCR (l'elemento
+finisce in quella pagina) oppure indicata dall'utente (l'elemento inizia in quella pagina)
+*/
+#[derive(Copy, Clone, Default, Data, Lens, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PageAnchor {
+    pub ch: usize,
+    pub element: usize,
+    pub page: usize,
+    #[serde(default)]
+    pub manual: bool,
+}
+
+/*
+Stima di come un capitolo si divide in pagine: quante ne occupa e in quale pagina,
+contando da 0, inizia e finisce ogni elemento
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChapterPages {
+    pub pages: usize,
+    pub element_pages: Vec<(usize, usize)>,
+    pub part: bool, // Le pagine di una parte iniziano sempre a destra (dispari)
 }
 
 impl From<OcrData> for SerializableOcrData {
@@ -102,6 +361,9 @@ impl From<OcrData> for SerializableOcrData {
             first_chap: ocr_data.first_chap,
             first: ocr_data.first,
             other: ocr_data.other,
+            anchors: ocr_data.anchors.iter().map(|a| *a).collect(),
+            layout: ocr_data.layout,
+            publisher_pages: ocr_data.publisher_pages,
         }
     }
 }
@@ -113,6 +375,9 @@ impl From<SerializableOcrData> for OcrData {
             first_chap: s_ocr_data.first_chap,
             first: s_ocr_data.first,
             other: s_ocr_data.other,
+            anchors: s_ocr_data.anchors.iter().map(|a| *a).collect(),
+            layout: s_ocr_data.layout,
+            publisher_pages: s_ocr_data.publisher_pages,
         }
     }
 }
@@ -124,19 +389,53 @@ impl OcrData {
             first_chap: None,
             first: None,
             other: None,
+            anchors: Vector::new(),
+            layout: LayoutSettings::default(),
+            publisher_pages: false,
         }
     }
 
-    pub fn ocr_log(&mut self, str: String) -> Result<usize, ()> {
-        if self.first.is_none() || self.other.is_none() {
-            return Err(());
+    /*
+    Aggiunge un'ancora, sostituendo quella già presente per lo stesso elemento (ma un'ancora
+    dell'OCR non sostituisce una inserita a mano). Restituisce i capitoli la cui prima pagina può
+    cambiare: da quello dell'ancora precedente (o del primo capitolo allineato) a quello
+    dell'ancora successiva, None se arriva fino alla fine del libro
+    */
+    pub fn add_anchor(&mut self, mut anchor: PageAnchor) -> (usize, Option<usize>) {
+        let key = |a: &PageAnchor| (a.ch, a.element);
+        if let Some(manual) = self
+            .anchors
+            .iter()
+            .find(|a| key(a) == key(&anchor) && a.manual && !anchor.manual)
+        {
+            anchor = *manual;
         }
-        match Mapping::new(str) {
+        self.anchors.retain(|a| key(a) != key(&anchor));
+        let pos = self
+            .anchors
+            .iter()
+            .position(|a| key(a) > key(&anchor))
+            .unwrap_or(self.anchors.len());
+        self.anchors.insert(pos, anchor);
+
+        let first = match pos {
+            0 => self.first_chap.unwrap_or(0),
+            _ => self.anchors[pos - 1].ch,
+        };
+        (first, self.anchors.get(pos + 1).map(|a| a.ch))
+    }
+
+    pub fn ocr_log(&mut self, page: &OcrPage) -> Result<usize, ()> {
+        let (first, other) = match (self.first, self.other) {
+            (Some(first), Some(other)) => (first, other),
+            _ => return Err(()),
+        };
+        match Mapping::new(page) {
             Ok(mut mapping) => {
-                let first_lines = self.mappings[self.first.unwrap()].page_lines;
-                let other_lines = self.mappings[self.other.unwrap()].page_lines;
-                let range = first_lines - 2..first_lines + 3;
-                let other_range = other_lines - 2..other_lines + 3;
+                let first_lines = self.mappings[first].page_lines;
+                let other_lines = self.mappings[other].page_lines;
+                let range = first_lines.saturating_sub(2)..first_lines + 3;
+                let other_range = other_lines.saturating_sub(2)..other_lines + 3;
                 if range.contains(&mapping.page_lines) {
                     mapping.is_first = true
                 } else if !other_range.contains(&mapping.page_lines) {
@@ -147,14 +446,14 @@ impl OcrData {
                 return Ok(&self.mappings.len() - 1);
             }
             Err(e) => {
-                eprintln!("{}", e);
+                log(Severity::Warning, &e.to_string());
                 Err(())
             }
         }
     }
 
-    pub fn ocr_log_first(&mut self, str: String, ch: usize) -> Result<(), Box<dyn Error>> {
-        match Mapping::new(str) {
+    pub fn ocr_log_first(&mut self, page: &OcrPage, ch: usize) -> Result<(), Box<dyn Error>> {
+        match Mapping::new(page) {
             Ok(mut mapping) => {
                 mapping.is_first = true;
                 self.mappings.push_back(mapping);
@@ -163,21 +462,21 @@ impl OcrData {
                 return Ok(());
             }
             Err(e) => {
-                eprintln!("{:?}", e);
+                log(Severity::Warning, &e.to_string());
                 Err(e)
             }
         }
     }
 
-    pub fn ocr_log_other(&mut self, str: String) -> Result<(), Box<dyn Error>> {
-        match Mapping::new(str) {
+    pub fn ocr_log_other(&mut self, page: &OcrPage) -> Result<(), Box<dyn Error>> {
+        match Mapping::new(page) {
             Ok(mapping) => {
                 self.mappings.push_back(mapping);
                 self.other = Some(&self.mappings.len() - 1);
                 return Ok(());
             }
             Err(e) => {
-                eprintln!("{:?}", e);
+                log(Severity::Warning, &e.to_string());
                 Err(e)
             }
         }
@@ -187,6 +486,11 @@ impl OcrData {
         self.first.is_some() && self.other.is_some()
     }
 
+    /* Il libro ha le pagine cartacee: stimate dalle foto oppure indicate dall'editore */
+    pub fn has_pages(&self) -> bool {
+        self.publisher_pages || self.is_aligned()
+    }
+
     pub fn _desync(&mut self) {
         *self = Self::new();
     }
@@ -231,20 +535,188 @@ impl OcrData {
     }
 }
 
-pub(crate) fn find_ch(str: String, chs: Vector<Chapter>) -> Option<(usize, usize)> {
-    for (index, ch) in chs.iter().enumerate() {
-        let plain_text = xml_to_text(&ch.xml).replace("\n", " ").replace(".", " ");
-        let p_clone = plain_text.clone();
-        let t_clone = str
-            .clone()
-            .replace("-\n", "")
-            .replace("\n", " ")
-            .replace(".", " ");
-        if let Some(offset) =
-            OcrAlgorithms::fuzzy_match(p_clone, t_clone, OcrAlgorithms::fuzzy_linear_compare)
-        {
-            return Some((index, offset));
+/*
+Possibile posizione della pagina fotografata: capitolo, offset in parole lunghe e somiglianza tra 0 e 1
+*/
+#[derive(Clone, Data, Lens, Debug, PartialEq)]
+pub struct OcrMatch {
+    pub ch: usize,
+    pub offset: usize,
+    pub score: f64,
+    pub snippet: String,
+}
+
+/*
+Indice invertito delle parole lunghe di un libro: per ogni parola i capitoli e gli offset in cui compare.
+Viene calcolato una sola volta per libro e serve a scegliere poche finestre candidate prima del confronto fuzzy
+*/
+#[derive(Default, Debug)]
+pub struct WordIndex {
+    texts: Vec<String>,
+    words: Vec<Vec<String>>,
+    positions: Vec<Vec<usize>>, // Posizione di ogni parola nel testo del capitolo, per gli estratti
+    postings: HashMap<String, Vec<(usize, usize)>>,
+}
+
+const MAX_POSTINGS: usize = 500; // Parole troppo frequenti non aiutano a localizzare la pagina
+const MAX_CANDIDATES: usize = 20;
+pub const MAX_MATCHES: usize = 5;
+const AMBIGUITY_MARGIN: f64 = 0.1; // Sotto questo distacco dal primo chiediamo all'utente
+const SNIPPET_LEN: usize = 200;
+
+impl WordIndex {
+    /*
+    Il testo dei capitoli viene estratto in parallelo, l'indice si riempie poi in ordine di
+    capitolo così da essere identico qualunque sia il numero di thread
+    */
+    pub fn new(chs: &Vector<Chapter>) -> Self {
+        let chapters = chs
+            .iter()
+            .collect::<Vec<&Chapter>>()
+            .par_iter()
+            .map(|ch| {
+                let plain_text = xml_to_text(&ch.xml).replace("\n", " ").replace(".", " ");
+                let (positions, words): (Vec<usize>, Vec<String>) =
+                    OcrAlgorithms::long_words_at(&plain_text)
+                        .into_iter()
+                        .unzip();
+                (plain_text, positions, words)
+            })
+            .collect::<Vec<_>>();
+
+        let mut index = WordIndex::default();
+        for (ch_id, (plain_text, positions, words)) in chapters.into_iter().enumerate() {
+            for (offset, word) in words.iter().enumerate() {
+                index
+                    .postings
+                    .entry(word.clone())
+                    .or_insert(Vec::new())
+                    .push((ch_id, offset));
+            }
+            index.texts.push(plain_text);
+            index.words.push(words);
+            index.positions.push(positions);
         }
+        index
     }
-    None
+
+    /*
+    Ogni parola della pagina vota per l'inizio di finestra (capitolo, offset) compatibile con la sua posizione
+    */
+    pub fn candidates(&self, page: &[String]) -> Vec<(usize, usize)> {
+        let mut votes: HashMap<(usize, usize), usize> = HashMap::new();
+        for (pos, word) in page.iter().enumerate() {
+            match self.postings.get(word) {
+                Some(postings) if postings.len() <= MAX_POSTINGS => {
+                    for (ch, offset) in postings {
+                        if *offset >= pos && offset - pos + page.len() <= self.words[*ch].len() {
+                            *votes.entry((*ch, offset - pos)).or_insert(0) += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut candidates = votes.into_iter().collect::<Vec<((usize, usize), usize)>>();
+        candidates.sort_by(|(w1, v1), (w2, v2)| v2.cmp(v1).then(w1.cmp(w2)));
+        candidates
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|(window, _)| window)
+            .collect()
+    }
+
+    /*
+    Le migliori posizioni sopra la soglia, dalla più simile. Finestre sovrapposte nello stesso
+    capitolo contano come una sola posizione
+    */
+    pub fn rank(&self, text: &str, threshold: f64, strategy: MatchStrategy) -> Vec<OcrMatch> {
+        let page = OcrAlgorithms::long_words(text);
+        if page.len() == 0 {
+            return Vec::new();
+        }
+        let all_words = OcrAlgorithms::all_words(text);
+        /* Le finestre candidate vengono confrontate in parallelo, collect mantiene il loro ordine */
+        let mut scored = self
+            .candidates(&page)
+            .into_par_iter()
+            .map(|(ch, offset)| {
+                let score = match strategy {
+                    MatchStrategy::Linear => OcrAlgorithms::fuzzy_linear_score(
+                        &self.words[ch][offset..offset + page.len()],
+                        &page,
+                    ),
+                    MatchStrategy::Alignment => OcrAlgorithms::fuzzy_alignment_score(
+                        &self.window_words(ch, offset, page.len()),
+                        &all_words,
+                    ),
+                };
+                (ch, offset, score)
+            })
+            .filter(|(_, _, score)| *score >= threshold)
+            .collect::<Vec<(usize, usize, f64)>>();
+        // Ordinamento stabile: a parità di somiglianza resta prima la finestra con più voti
+        scored.sort_by(|(_, _, s1), (_, _, s2)| s2.total_cmp(s1));
+
+        let mut matches: Vec<OcrMatch> = Vec::new();
+        for (ch, offset, score) in scored {
+            if matches
+                .iter()
+                .any(|m| m.ch == ch && m.offset.abs_diff(offset) < page.len())
+            {
+                continue;
+            }
+            matches.push(OcrMatch {
+                ch,
+                offset,
+                score,
+                snippet: self.snippet(ch, offset),
+            });
+            if matches.len() == MAX_MATCHES {
+                break;
+            }
+        }
+        matches
+    }
+
+    /*
+    Tutte le parole del capitolo attorno alla finestra, con un margine per le parole che l'OCR
+    ha aggiunto o perso
+    */
+    fn window_words(&self, ch: usize, offset: usize, len: usize) -> Vec<String> {
+        let slack = (len / 5).max(2);
+        let positions = &self.positions[ch];
+        let start = positions[offset.saturating_sub(slack)];
+        let end = match positions.get(offset + len + slack) {
+            Some(end) => *end,
+            None => self.texts[ch].len(),
+        };
+        OcrAlgorithms::all_words(&self.texts[ch][start..end])
+    }
+
+    fn snippet(&self, ch: usize, offset: usize) -> String {
+        let text = &self.texts[ch][self.positions[ch][offset]..];
+        let mut snippet = text.chars().take(SNIPPET_LEN).collect::<String>();
+        if text.len() > snippet.len() {
+            snippet.push_str("...");
+        }
+        snippet.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+}
+
+/*
+Le prime due posizioni sono troppo vicine per scegliere da soli
+*/
+pub fn is_ambiguous(matches: &[OcrMatch]) -> bool {
+    matches.len() > 1 && matches[0].score - matches[1].score < AMBIGUITY_MARGIN
+}
+
+pub(crate) fn find_matches(
+    str: String,
+    index: &WordIndex,
+    threshold: f64,
+    strategy: MatchStrategy,
+) -> Vec<OcrMatch> {
+    let t_clone = str.replace("-\n", "").replace("\n", " ").replace(".", " ");
+    index.rank(&t_clone, threshold, strategy)
 }
@@ -0,0 +1,291 @@
+use crate::errors::{log, Severity};
+use crate::preprocess::GrayImage;
+use crate::settings::Settings;
+use crate::utilities::{choose_languages, installed_languages, preprocess_image};
+use druid::Data;
+use roxmltree::{Document, Node, ParsingOptions};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+/* Classi hOCR che contengono una riga di testo */
+const LINE_CLASSES: [&str; 4] = ["ocr_line", "ocr_header", "ocr_textfloat", "ocr_caption"];
+
+/*
+Motore usato per leggere le foto delle pagine
+*/
+#[derive(Clone, Copy, Data, PartialEq, Debug, Serialize, Deserialize)]
+pub enum OcrEngine {
+    Tesseract, // leptess, con la preparazione dell'immagine
+    TextFile,  // Testo già riconosciuto altrove, in un .txt accanto alla foto
+    Command,   // Un eseguibile qualsiasi che stampa il testo su stdout
+}
+
+impl Default for OcrEngine {
+    fn default() -> Self {
+        OcrEngine::Tesseract
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcrLine {
+    pub text: String,
+    pub bbox: (u32, u32, u32, u32), // x0, y0, x1, y1 in pixel dell'immagine letta
+    pub confidence: Option<f32>,    // 0-100
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OcrOutput {
+    pub text: String,
+    pub lines: Vec<OcrLine>, // Vuoto se il motore non restituisce la posizione delle righe
+    pub confidence: Option<f32>,
+    pub image: Option<GrayImage>, // Immagine effettivamente letta, per l'anteprima
+}
+
+impl OcrOutput {
+    fn from_text(text: String) -> Self {
+        OcrOutput {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+/*
+Testo e righe di una pagina letta, passa dal thread dell'OCR fino al Mapping
+*/
+#[derive(Clone, Data, Debug, Default, PartialEq)]
+pub struct OcrPage {
+    pub text: String,
+    pub lines: Arc<Vec<OcrLine>>,
+}
+
+impl From<OcrOutput> for OcrPage {
+    fn from(output: OcrOutput) -> Self {
+        OcrPage {
+            text: output.text,
+            lines: Arc::new(output.lines),
+        }
+    }
+}
+
+pub trait OcrBackend {
+    /* lang è la lingua del libro (ISO 639-3), le impostazioni possono sostituirla */
+    fn recognize(&self, path: &Path, lang: &str) -> Result<OcrOutput, Box<dyn Error>>;
+}
+
+pub fn ocr_backend(settings: &Settings) -> Box<dyn OcrBackend> {
+    match settings.ocr_engine {
+        OcrEngine::Tesseract => Box::new(TesseractBackend {
+            settings: settings.clone(),
+        }),
+        OcrEngine::TextFile => Box::new(TextFileBackend {}),
+        OcrEngine::Command => Box::new(CommandBackend {
+            command: settings.ocr_command.clone(),
+            languages: settings.ocr_languages.clone(),
+        }),
+    }
+}
+
+fn requested_languages<'a>(book_lang: &'a str, languages: &'a str) -> &'a str {
+    match languages.trim() {
+        "" => book_lang,
+        langs => langs,
+    }
+}
+
+pub struct TesseractBackend {
+    pub settings: Settings,
+}
+
+impl TesseractBackend {
+    fn read(&self, lt: &mut leptess::LepTess, img: GrayImage) -> Result<OcrOutput, Box<dyn Error>> {
+        lt.set_image_from_mem(&img.to_pgm())?;
+        let text = lt.get_utf8_text()?;
+        let lines = parse_hocr(&lt.get_hocr_text(0)?);
+        Ok(OcrOutput {
+            text,
+            lines,
+            confidence: Some(lt.mean_text_conf() as f32),
+            image: Some(img),
+        })
+    }
+}
+
+impl OcrBackend for TesseractBackend {
+    fn recognize(&self, path: &Path, lang: &str) -> Result<OcrOutput, Box<dyn Error>> {
+        let requested = requested_languages(lang, &self.settings.ocr_languages);
+        let lang = choose_languages(requested, &installed_languages(&self.settings))?;
+        let img = preprocess_image(&path.to_path_buf(), &self.settings)?;
+        let mut lt = leptess::LepTess::new(self.settings.tessdata(), &lang).map_err(|e| {
+            format!(
+                "Tesseract couldn't load the language data '{}' ({}), check the OCR settings",
+                lang, e
+            )
+        })?;
+        lt.set_variable(
+            leptess::Variable::TesseditPagesegMode,
+            self.settings.page_layout.psm(),
+        )
+        .map_err(|_| "Tesseract rejected the page layout setting".to_string())?;
+
+        let output = self.read(&mut lt, img)?;
+        if self.settings.preprocess_rotation {
+            /* Una pagina girata di 90° può essere finita sottosopra: vince la lettura più sicura */
+            if let Some(img) = output.image.as_ref() {
+                let flipped = self.read(&mut lt, img.rotate_180())?;
+                if flipped.confidence > output.confidence {
+                    return Ok(flipped);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/*
+Per chi fa l'OCR con un altro programma: si sceglie direttamente il .txt oppure la foto,
+e in quel caso si legge il .txt con lo stesso nome
+*/
+pub struct TextFileBackend {}
+
+impl OcrBackend for TextFileBackend {
+    fn recognize(&self, path: &Path, _lang: &str) -> Result<OcrOutput, Box<dyn Error>> {
+        let text_path = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("txt") => path.to_path_buf(),
+            _ => path.with_extension("txt"),
+        };
+        let text = fs::read_to_string(&text_path)
+            .map_err(|e| format!("{}: {}", text_path.display(), e))?;
+        Ok(OcrOutput::from_text(text))
+    }
+}
+
+/*
+Esegue command sostituendo {image} con il percorso della foto e {lang} con le lingue,
+senza passare per una shell. Se l'output è hOCR vengono lette anche le righe
+*/
+pub struct CommandBackend {
+    pub command: String,
+    pub languages: String,
+}
+
+impl OcrBackend for CommandBackend {
+    fn recognize(&self, path: &Path, lang: &str) -> Result<OcrOutput, Box<dyn Error>> {
+        let args = command_args(
+            &self.command,
+            &path.to_string_lossy(),
+            requested_languages(lang, &self.languages),
+        );
+        let (program, args) = args.split_first().ok_or("No OCR command set")?;
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|e| format!("{}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        if stdout.contains("ocr_line") {
+            let lines = parse_hocr(&stdout);
+            let text = lines
+                .iter()
+                .map(|l| l.text.clone())
+                .collect::<Vec<String>>()
+                .join("\n");
+            return Ok(OcrOutput {
+                text,
+                lines,
+                ..Default::default()
+            });
+        }
+        Ok(OcrOutput::from_text(stdout))
+    }
+}
+
+/* Se il comando non usa {image} il percorso viene aggiunto in fondo */
+pub fn command_args(command: &str, image: &str, lang: &str) -> Vec<String> {
+    let mut args = command
+        .split_whitespace()
+        .map(|arg| arg.replace("{image}", image).replace("{lang}", lang))
+        .collect::<Vec<String>>();
+    if !command.contains("{image}") && !args.is_empty() {
+        args.push(image.to_string());
+    }
+    args
+}
+
+/* Righe di un documento hOCR con il loro riquadro e la confidenza media delle parole */
+pub fn parse_hocr(hocr: &str) -> Vec<OcrLine> {
+    let opt = ParsingOptions { allow_dtd: true };
+    let doc = match Document::parse_with_options(hocr, opt) {
+        Ok(doc) => doc,
+        Err(e) => {
+            log(Severity::Warning, &format!("hOCR: {}", e));
+            return vec![];
+        }
+    };
+    doc.descendants()
+        .filter(|n| has_class(n, &LINE_CLASSES))
+        .filter_map(|line| {
+            let bbox = title_field(line, "bbox")?
+                .split_whitespace()
+                .filter_map(|n| n.parse::<u32>().ok())
+                .collect::<Vec<u32>>();
+            if bbox.len() != 4 {
+                return None;
+            }
+            let words = line
+                .descendants()
+                .filter(|n| has_class(n, &["ocrx_word"]))
+                .collect::<Vec<Node>>();
+            let text = words
+                .iter()
+                .map(|w| {
+                    w.descendants()
+                        .filter(|n| n.is_text())
+                        .filter_map(|n| n.text())
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                })
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<String>>()
+                .join(" ");
+            let confs = words
+                .iter()
+                .filter_map(|w| title_field(*w, "x_wconf")?.trim().parse::<f32>().ok())
+                .collect::<Vec<f32>>();
+            Some(OcrLine {
+                text,
+                bbox: (bbox[0], bbox[1], bbox[2], bbox[3]),
+                confidence: match confs.len() {
+                    0 => None,
+                    n => Some(confs.iter().sum::<f32>() / n as f32),
+                },
+            })
+        })
+        .collect()
+}
+
+fn has_class(node: &Node, classes: &[&str]) -> bool {
+    node.attribute("class")
+        .map_or(false, |class| classes.contains(&class))
+}
+
+/* Campo key dell'attributo title, es. "bbox 10 20 30 40; x_wconf 95" */
+fn title_field<'a>(node: Node<'a, 'a>, key: &str) -> Option<&'a str> {
+    node.attribute("title")?
+        .split(';')
+        .map(|field| field.trim())
+        .find_map(|field| field.strip_prefix(key))
+        .filter(|value| value.starts_with(' '))
+}
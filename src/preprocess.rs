@@ -0,0 +1,296 @@
+/*
+Preparazione delle foto delle pagine prima dell'OCR: le foto fatte col telefono sono storte,
+illuminate male e spesso contengono anche la pagina di fronte
+*/
+
+const DESKEW_MAX_ANGLE: f64 = 10.; // Gradi
+const DESKEW_STEP: f64 = 0.5;
+const DESKEW_SAMPLE_WIDTH: usize = 800; // La stima dell'angolo lavora su una versione ridotta
+const BINARIZE_T: f64 = 0.15;
+const CROP_MARGIN: usize = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>, // 0 nero, 255 bianco
+}
+
+impl GrayImage {
+    pub fn new(width: usize, height: usize, value: u8) -> Self {
+        GrayImage {
+            width,
+            height,
+            pixels: vec![value; width * height],
+        }
+    }
+
+    /* Converte pixel con channels canali (1 grigio, 3 RGB, 4 RGBA) in scala di grigi */
+    pub fn from_pixels(width: usize, height: usize, raw: &[u8], channels: usize) -> Self {
+        let pixels = raw
+            .chunks(channels)
+            .take(width * height)
+            .map(|px| match channels {
+                1 | 2 => px[0],
+                _ => (0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64) as u8,
+            })
+            .collect();
+        GrayImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.get(x, y) < 128
+    }
+
+    /* Formato PGM binario, che Leptonica sa leggere dalla memoria */
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut pgm = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        pgm.extend_from_slice(&self.pixels);
+        pgm
+    }
+
+    /*
+    Soglia adattiva (metodo di Bradley): un pixel è nero se è più scuro della media del suo intorno,
+    così l'illuminazione non uniforme non cancella parti della pagina
+    */
+    pub fn binarize(&self) -> GrayImage {
+        let (w, h) = (self.width, self.height);
+        if w == 0 || h == 0 {
+            return self.clone();
+        }
+        let mut integral = vec![0u64; (w + 1) * (h + 1)];
+        for y in 0..h {
+            let mut row = 0u64;
+            for x in 0..w {
+                row += self.get(x, y) as u64;
+                integral[(y + 1) * (w + 1) + x + 1] = integral[y * (w + 1) + x + 1] + row;
+            }
+        }
+
+        let half = (w.max(h) / 16).max(1) / 2;
+        let mut out = GrayImage::new(w, h, 255);
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(half), (y + half + 1).min(h));
+            for x in 0..w {
+                let (x0, x1) = (x.saturating_sub(half), (x + half + 1).min(w));
+                let count = ((x1 - x0) * (y1 - y0)) as f64;
+                let sum = integral[y1 * (w + 1) + x1] + integral[y0 * (w + 1) + x0]
+                    - integral[y0 * (w + 1) + x1]
+                    - integral[y1 * (w + 1) + x0];
+                if (self.get(x, y) as f64) * count < sum as f64 * (1. - BINARIZE_T) {
+                    out.pixels[y * w + x] = 0;
+                }
+            }
+        }
+        out
+    }
+
+    /*
+    Angolo (in gradi) di cui è inclinato il testo: quello per cui la proiezione dei pixel neri sulle
+    righe è più "a picchi", cioè le righe di testo sono orizzontali
+    */
+    pub fn skew_angle(&self) -> f64 {
+        let sample = self.scaled(DESKEW_SAMPLE_WIDTH);
+        let dark = sample.dark_pixels();
+        if dark.is_empty() {
+            return 0.;
+        }
+        let steps = (DESKEW_MAX_ANGLE / DESKEW_STEP) as i32;
+        let mut best = (0., 0.);
+        for step in -steps..=steps {
+            let angle = step as f64 * DESKEW_STEP;
+            let tan = angle.to_radians().tan();
+            let offset = (sample.width as f64 * tan.abs()).ceil() as usize;
+            let mut bins = vec![0u64; sample.height + 2 * offset + 1];
+            for (x, y) in dark.iter() {
+                let row = *y as f64 + offset as f64 - *x as f64 * tan;
+                bins[row.round().max(0.) as usize] += 1;
+            }
+            let score = bins.iter().map(|b| (b * b) as f64).sum::<f64>();
+            if score > best.1 || (score == best.1 && angle.abs() < f64::abs(best.0)) {
+                best = (angle, score);
+            }
+        }
+        best.0
+    }
+
+    pub fn deskew(&self) -> GrayImage {
+        let angle = self.skew_angle();
+        if angle == 0. {
+            return self.clone();
+        }
+        self.rotate(angle)
+    }
+
+    /* Rotazione attorno al centro, i pixel scoperti diventano bianchi */
+    pub fn rotate(&self, degrees: f64) -> GrayImage {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (cx, cy) = (self.width as f64 / 2., self.height as f64 / 2.);
+        let mut out = GrayImage::new(self.width, self.height, 255);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+                let sx = (cx + dx * cos - dy * sin).round();
+                let sy = (cy + dx * sin + dy * cos).round();
+                if sx >= 0. && sy >= 0. && (sx as usize) < self.width && (sy as usize) < self.height
+                {
+                    out.pixels[y * self.width + x] = self.get(sx as usize, sy as usize);
+                }
+            }
+        }
+        out
+    }
+
+    /* Rotazione di 90° in senso orario */
+    pub fn rotate_90(&self) -> GrayImage {
+        let mut out = GrayImage::new(self.height, self.width, 255);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out.pixels[x * out.width + (self.height - 1 - y)] = self.get(x, y);
+            }
+        }
+        out
+    }
+
+    pub fn rotate_180(&self) -> GrayImage {
+        let mut out = self.clone();
+        out.pixels.reverse();
+        out
+    }
+
+    /*
+    Le righe di testo orizzontali alternano righe di pixel piene e vuote, quindi il profilo per righe
+    varia molto più di quello per colonne. Se succede il contrario la foto è girata di 90°
+    */
+    pub fn is_sideways(&self) -> bool {
+        let sample = self.scaled(DESKEW_SAMPLE_WIDTH);
+        let mut rows = vec![0f64; sample.height];
+        let mut cols = vec![0f64; sample.width];
+        for (x, y) in sample.dark_pixels() {
+            rows[y] += 1.;
+            cols[x] += 1.;
+        }
+        variation(&cols) > variation(&rows)
+    }
+
+    /*
+    Ritaglia il blocco di testo più grande: le colonne con inchiostro separate da uno spazio bianco
+    ampio (come la piega tra due pagine) formano blocchi diversi, teniamo quello con più inchiostro
+    */
+    pub fn crop_text_block(&self) -> GrayImage {
+        let mut cols = vec![0usize; self.width];
+        for (x, _) in self.dark_pixels() {
+            cols[x] += 1;
+        }
+        let min_ink = (self.height / 100).max(1);
+        let (x0, x1) = match largest_block(&cols, min_ink, (self.width / 30).max(1)) {
+            Some(block) => block,
+            None => return self.clone(),
+        };
+
+        let mut rows = vec![0usize; self.height];
+        for (x, y) in self.dark_pixels() {
+            if x >= x0 && x <= x1 {
+                rows[y] += 1;
+            }
+        }
+        let (y0, y1) = match largest_block(&rows, 1, (self.height / 8).max(1)) {
+            Some(block) => block,
+            None => return self.clone(),
+        };
+        self.crop(
+            x0.saturating_sub(CROP_MARGIN),
+            y0.saturating_sub(CROP_MARGIN),
+            (x1 + CROP_MARGIN).min(self.width - 1),
+            (y1 + CROP_MARGIN).min(self.height - 1),
+        )
+    }
+
+    pub fn crop(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> GrayImage {
+        let (width, height) = (x1 - x0 + 1, y1 - y0 + 1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in y0..=y1 {
+            pixels.extend_from_slice(&self.pixels[y * self.width + x0..=y * self.width + x1]);
+        }
+        GrayImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn dark_pixels(&self) -> Vec<(usize, usize)> {
+        let mut dark = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_dark(x, y) {
+                    dark.push((x, y));
+                }
+            }
+        }
+        dark
+    }
+
+    /* Copia ridotta (nearest neighbour) larga al massimo max_width */
+    fn scaled(&self, max_width: usize) -> GrayImage {
+        if self.width <= max_width {
+            return self.clone();
+        }
+        let ratio = self.width as f64 / max_width as f64;
+        let height = ((self.height as f64 / ratio) as usize).max(1);
+        let mut out = GrayImage::new(max_width, height, 255);
+        for y in 0..height {
+            for x in 0..max_width {
+                let sx = ((x as f64 * ratio) as usize).min(self.width - 1);
+                let sy = ((y as f64 * ratio) as usize).min(self.height - 1);
+                out.pixels[y * max_width + x] = self.get(sx, sy);
+            }
+        }
+        out
+    }
+}
+
+/* Coefficiente di variazione al quadrato di un profilo */
+fn variation(profile: &[f64]) -> f64 {
+    let n = profile.len() as f64;
+    let mean = profile.iter().sum::<f64>() / n;
+    if mean == 0. {
+        return 0.;
+    }
+    profile.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n / (mean * mean)
+}
+
+/*
+Intervallo [inizio, fine] del profilo con più inchiostro: le posizioni sopra min_ink separate
+da meno di max_gap posizioni vuote fanno parte dello stesso blocco
+*/
+fn largest_block(profile: &[usize], min_ink: usize, max_gap: usize) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize, usize)> = None;
+    let mut current: Option<(usize, usize, usize)> = None;
+    for (i, ink) in profile.iter().enumerate() {
+        if *ink < min_ink {
+            continue;
+        }
+        current = match current {
+            Some((start, end, total)) if i - end <= max_gap => Some((start, i, total + ink)),
+            _ => {
+                if current.map_or(false, |c| best.map_or(true, |b| c.2 > b.2)) {
+                    best = current;
+                }
+                Some((i, i, *ink))
+            }
+        };
+    }
+    if current.map_or(false, |c| best.map_or(true, |b| c.2 > b.2)) {
+        best = current;
+    }
+    best.map(|(start, end, _)| (start, end))
+}
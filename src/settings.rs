@@ -0,0 +1,129 @@
+use crate::algorithms::MatchStrategy;
+use crate::errors::{log, Severity};
+use crate::image_source::ImageSource;
+use crate::ocr_backend::OcrEngine;
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const FILE_NAME: &str = "settings.json";
+
+/*
+Come Tesseract deve dividere la pagina in blocchi di testo
+*/
+#[derive(Clone, Copy, Data, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PageLayout {
+    Auto,        // Tesseract cerca da solo colonne, titoli e blocchi
+    SingleBlock, // Tutta l'immagine è un unico blocco, va bene se la foto è già ritagliata
+}
+
+impl Default for PageLayout {
+    fn default() -> Self {
+        PageLayout::Auto
+    }
+}
+
+impl PageLayout {
+    /* Valore di tessedit_pageseg_mode */
+    pub fn psm(&self) -> &'static str {
+        match self {
+            PageLayout::Auto => "3",
+            PageLayout::SingleBlock => "6",
+        }
+    }
+}
+
+/*
+Impostazioni globali dell'applicazione, salvate accanto a meta.json
+*/
+#[derive(Clone, Data, Lens, Debug, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default = "default_match_threshold")]
+    pub match_threshold: f64, // Somiglianza minima perché una posizione OCR venga proposta
+    #[serde(default)]
+    pub match_strategy: MatchStrategy,
+    /* Preparazione delle foto prima dell'OCR */
+    #[serde(default = "default_true")]
+    pub preprocess_binarize: bool,
+    #[serde(default = "default_true")]
+    pub preprocess_deskew: bool,
+    #[serde(default = "default_true")]
+    pub preprocess_crop: bool,
+    #[serde(default)]
+    pub preprocess_rotation: bool, // Raddoppia il tempo di OCR
+    #[serde(default)]
+    pub ocr_engine: OcrEngine,
+    #[serde(default)]
+    pub ocr_command: String, // Per OcrEngine::Command, es. "tesseract {image} - -l {lang}"
+    /* Tesseract */
+    #[serde(default)]
+    pub ocr_languages: String, // Es. "ita+eng", vuoto per usare la lingua del libro
+    #[serde(default)]
+    pub tessdata_path: String, // Vuoto per la cartella predefinita di Tesseract
+    #[serde(default)]
+    pub page_layout: PageLayout,
+    #[serde(default)]
+    pub image_source: ImageSource,
+    #[serde(default)]
+    pub inbox_folder: String, // Per ImageSource::Inbox
+}
+
+fn default_match_threshold() -> f64 {
+    0.5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            match_threshold: default_match_threshold(),
+            match_strategy: MatchStrategy::default(),
+            preprocess_binarize: true,
+            preprocess_deskew: true,
+            preprocess_crop: true,
+            preprocess_rotation: false,
+            ocr_engine: OcrEngine::default(),
+            ocr_command: String::new(),
+            ocr_languages: String::new(),
+            tessdata_path: String::new(),
+            page_layout: PageLayout::default(),
+            image_source: ImageSource::default(),
+            inbox_folder: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn tessdata(&self) -> Option<&str> {
+        match self.tessdata_path.trim() {
+            "" => None,
+            path => Some(path),
+        }
+    }
+
+    pub fn inbox(&self) -> Option<&Path> {
+        match self.inbox_folder.trim() {
+            "" => None,
+            path => Some(Path::new(path)),
+        }
+    }
+
+    pub fn load() -> Self {
+        match fs::read_to_string(FILE_NAME) {
+            Ok(buf) => serde_json::from_str(&buf).unwrap_or_else(|e| {
+                log(Severity::Warning, &format!("{}: {}", FILE_NAME, e));
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| format!("{}: {}", FILE_NAME, e))?;
+        fs::write(FILE_NAME, json).map_err(|e| format!("{}: {}", FILE_NAME, e))
+    }
+}
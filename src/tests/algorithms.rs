@@ -0,0 +1,42 @@
+#[allow(unused_imports)]
+use crate::algorithms::OcrAlgorithms;
+
+#[allow(dead_code)]
+fn words(text: &str) -> Vec<String> {
+    OcrAlgorithms::all_words(text)
+}
+
+#[test]
+fn test_all_words_keeps_short_words() {
+    assert_eq!(words("Il gatto, dorme."), vec!["il", "gatto", "dorme"]);
+}
+
+#[test]
+fn test_char_similarity() {
+    assert_eq!(OcrAlgorithms::char_similarity("inquieti", "inquieti"), 1.);
+    assert_eq!(OcrAlgorithms::char_similarity("inquieti", "inqu1eti"), 0.875);
+    assert_eq!(OcrAlgorithms::char_similarity("abc", "xyz"), 0.);
+}
+
+#[test]
+fn test_alignment_inserted_word() {
+    let text = words("il gatto dorme sul divano rosso");
+    let page = words("il gatto nero dorme sul divano rosso");
+    assert!(OcrAlgorithms::fuzzy_linear_score(&text, &page) < 0.5);
+    let score = OcrAlgorithms::fuzzy_alignment_score(&text, &page);
+    assert!((score - 6. / 7.).abs() < 1e-9);
+}
+
+#[test]
+fn test_alignment_free_text_ends() {
+    let text = words("ieri il gatto dorme sul divano rosso e poi");
+    let page = words("gatto dorme sul divano");
+    assert_eq!(OcrAlgorithms::fuzzy_alignment_score(&text, &page), 1.);
+}
+
+#[test]
+fn test_alignment_unrelated_text() {
+    let text = words("il gatto dorme sul divano rosso");
+    let page = words("tanto gentile e tanto onesta pare");
+    assert!(OcrAlgorithms::fuzzy_alignment_score(&text, &page) < 0.5);
+}
@@ -0,0 +1,151 @@
+#[allow(unused_imports)]
+use crate::bookcase::{
+    BookCase, BookInfo, DuplicateKind, Edition, SerializableBookCase, DEFAULT_EDITION,
+};
+#[allow(unused_imports)]
+use crate::errors::LibraryError;
+#[allow(unused_imports)]
+use druid::im::Vector;
+#[allow(unused_imports)]
+use std::fs;
+#[allow(unused_imports)]
+use std::path::Path;
+
+#[allow(dead_code)]
+fn book_with_pages(pages: &[usize]) -> BookInfo {
+    BookInfo {
+        edition: DEFAULT_EDITION.to_string(),
+        mapped_pages: pages.iter().copied().collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_add_edition() {
+    let mut book = book_with_pages(&[1, 10]);
+    assert_eq!(book.add_edition(" Paperback "), Ok(()));
+    assert_eq!(book.edition, "Paperback");
+    assert!(book.mapped_pages.is_empty());
+    assert_eq!(
+        book.editions,
+        Vector::from(vec![Edition {
+            name: DEFAULT_EDITION.to_string(),
+            mapped_pages: Vector::from(vec![1, 10]),
+            ..Default::default()
+        }])
+    );
+}
+
+#[test]
+fn test_add_edition_invalid_name() {
+    let mut book = book_with_pages(&[1, 10]);
+    assert!(book.add_edition("  ").is_err());
+    assert!(book.add_edition(DEFAULT_EDITION).is_err());
+    assert!(book.editions.is_empty());
+}
+
+#[test]
+fn test_select_edition() {
+    let mut book = book_with_pages(&[1, 10]);
+    book.add_edition("Paperback").unwrap();
+    book.mapped_pages = Vector::from(vec![3, 20]);
+
+    book.select_edition(DEFAULT_EDITION).unwrap();
+    assert_eq!(book.mapped_pages, Vector::from(vec![1, 10]));
+    assert_eq!(book.edition_names(), vec![DEFAULT_EDITION, "Paperback"]);
+
+    book.select_edition("Paperback").unwrap();
+    assert_eq!(book.mapped_pages, Vector::from(vec![3, 20]));
+    assert!(book.select_edition("Hardcover").is_err());
+}
+
+#[test]
+fn test_remove_edition() {
+    let mut book = book_with_pages(&[1, 10]);
+    book.add_edition("Paperback").unwrap();
+    assert!(book.remove_edition("Paperback").is_err());
+    assert_eq!(book.remove_edition(DEFAULT_EDITION), Ok(()));
+    assert_eq!(book.edition_names(), vec!["Paperback"]);
+}
+
+#[test]
+fn test_duplicate_by_stored_hash() {
+    /* I file non esistono: il confronto usa solo gli hash salvati */
+    let existing = BookInfo {
+        id: "a".to_string(),
+        content_hash: "0123".to_string(),
+        path: "./missing/first.epub".to_string(),
+        title: "I promessi sposi".to_string(),
+        ..Default::default()
+    };
+    let bookcase = BookCase {
+        library: Vector::from(vec![existing]),
+    };
+    let mut candidate = BookInfo {
+        id: "b".to_string(),
+        content_hash: "0123".to_string(),
+        path: "./missing/copy.epub".to_string(),
+        title: "I Malavoglia".to_string(),
+        ..Default::default()
+    };
+    let duplicate = bookcase.find_duplicate(&candidate).unwrap();
+    assert_eq!(duplicate.kind, DuplicateKind::SameFile);
+    assert_eq!(duplicate.existing, 0);
+
+    candidate.content_hash = String::new();
+    assert!(bookcase.find_duplicate(&candidate).is_none());
+}
+
+#[test]
+fn test_svg_cover_rejected() {
+    let mut book = book_with_pages(&[]);
+    assert!(matches!(
+        book.set_custom_cover(Path::new("/tmp/cover.svg")),
+        Err(LibraryError::Cover(..))
+    ));
+    assert!(!book.custom_cover);
+}
+
+#[test]
+fn test_library_order_is_stable() {
+    let dir = std::env::temp_dir().join(format!("ebook_reader_library_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let names = ["d.epub", "a.epub", "c.epub", "b.epub"];
+    let library = names
+        .iter()
+        .map(|name| {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            BookInfo {
+                id: name.to_string(),
+                content_hash: name.to_string(),
+                path: path.to_string_lossy().to_string(),
+                ..Default::default()
+            }
+        })
+        .collect::<Vector<BookInfo>>();
+    let saved: SerializableBookCase = BookCase { library }.into();
+    let json = serde_json::to_string(&saved).unwrap();
+
+    /* Le posizioni mostrate da list sono quelle di meta.json, a ogni lettura */
+    let list = || {
+        let mut bookcase = BookCase::default();
+        let mut saved_books = BookCase::parse_saved(&json).unwrap();
+        bookcase.populate(&mut saved_books, &mut Vec::new());
+        bookcase
+            .library
+            .iter()
+            .map(|b| b.path.clone())
+            .collect::<Vec<String>>()
+    };
+    let first = list();
+    assert_eq!(first, list());
+    assert_eq!(
+        first,
+        names
+            .iter()
+            .map(|name| dir.join(name).to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,100 @@
+#[allow(unused_imports)]
+use crate::book::chapter::Chapter;
+#[allow(unused_imports)]
+use crate::book::page_element::ContentType;
+#[allow(unused_imports)]
+use crate::book::print_pages;
+#[allow(unused_imports)]
+use druid::im::{HashMap, Vector};
+
+#[allow(dead_code)]
+fn chapter(body: &str, initial_page: usize) -> Chapter {
+    Chapter::new(
+        "OEBPS/ch.xhtml".to_string(),
+        format!(
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\
+                <head><title>Title</title></head><body>{}</body></html>",
+            body
+        ),
+        initial_page,
+    )
+}
+
+#[allow(dead_code)]
+fn pg_offsets(ch: &Chapter) -> Vec<(usize, bool)> {
+    ch.format(None, None, "")
+        .iter()
+        .map(|el| el.pg_offset)
+        .collect()
+}
+
+#[test]
+fn test_format_page_breaks() {
+    let ch = chapter(
+        "<p>Before</p><span epub:type=\"pagebreak\" id=\"p12\" title=\"12\"/>\
+            <p>First <span role=\"doc-pagebreak\">13</span>second</p><p>After</p>",
+        11,
+    );
+    assert_eq!(
+        pg_offsets(&ch),
+        vec![
+            (11, false),
+            (11, false),
+            (13, true),
+            (13, false),
+            (13, false),
+            (13, false),
+        ]
+    );
+    /* Il numero del segnaposto non finisce nel testo */
+    assert!(ch
+        .format(None, None, "")
+        .iter()
+        .all(|el| match &el.content {
+            ContentType::Text(text) => !text.text.contains("13"),
+            _ => true,
+        }));
+}
+
+#[test]
+fn test_format_without_page_breaks() {
+    let ch = chapter("<p>Text</p>", 5);
+    assert!(pg_offsets(&ch).iter().all(|pg| *pg == (0, false)));
+}
+
+#[test]
+fn test_page_targets() {
+    let mut ch = chapter("<p>One</p><p id=\"pg5\">Two</p>", 4);
+    let mut targets = HashMap::new();
+    targets.insert("pg5".to_string(), 5);
+    ch.page_targets = targets;
+    assert_eq!(ch.page_marks(), vec![(true, 5)]);
+    assert_eq!(
+        pg_offsets(&ch),
+        vec![(4, false), (4, false), (5, false), (5, false)]
+    );
+}
+
+#[test]
+fn test_print_pages() {
+    let mut chapters = Vector::new();
+    chapters.push_back(chapter("<p>Cover</p>", 0));
+    chapters.push_back(chapter(
+        "<span epub:type=\"pagebreak\" title=\"1\"/><p>A</p>\
+            <span epub:type=\"pagebreak\" title=\"2\"/><p>B</p>",
+        0,
+    ));
+    chapters.push_back(chapter(
+        "<p>Continues</p><span epub:type=\"pagebreak\" title=\"3\"/><p>C</p>",
+        0,
+    ));
+    chapters.push_back(chapter("<p>No marks</p>", 0));
+    assert_eq!(print_pages(&chapters), Vector::from(vec![0, 1, 2, 3]));
+}
+
+#[test]
+fn test_print_pages_without_marks() {
+    let mut chapters = Vector::new();
+    chapters.push_back(chapter("<p>Text</p>", 0));
+    assert!(print_pages(&chapters).is_empty());
+}
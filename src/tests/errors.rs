@@ -0,0 +1,67 @@
+#[allow(unused_imports)]
+use crate::errors::{
+    BookError, LibraryError, Notifications, OcrError, Report, Severity, MAX_NOTIFICATIONS,
+};
+
+#[test]
+fn test_notifications_queue() {
+    let mut notifications = Notifications::new();
+    notifications.push(
+        Severity::Error,
+        "Couldn't open book.epub: not a zip archive",
+    );
+    notifications.push(Severity::Info, "The book is not aligned with a paper book");
+    assert_eq!(notifications.items.len(), 2);
+    assert_eq!(notifications.items[0].severity, Severity::Error);
+
+    /* Lo stesso messaggio non compare due volte */
+    notifications.push(Severity::Info, "The book is not aligned with a paper book");
+    assert_eq!(notifications.items.len(), 2);
+
+    let first = notifications.items[0].id;
+    notifications.dismiss(first);
+    assert_eq!(notifications.items.len(), 1);
+    assert_eq!(notifications.items[0].severity, Severity::Info);
+}
+
+#[test]
+fn test_notifications_keep_latest() {
+    let mut notifications = Notifications::new();
+    for i in 0..MAX_NOTIFICATIONS + 2 {
+        notifications.push(Severity::Warning, &format!("Book not found at {}.epub", i));
+    }
+    assert_eq!(notifications.items.len(), MAX_NOTIFICATIONS);
+    assert_eq!(notifications.items[0].message, "Book not found at 2.epub");
+
+    notifications.clear();
+    assert!(notifications.items.is_empty());
+}
+
+#[test]
+fn test_report_severity() {
+    let mut notifications = Notifications::new();
+    notifications.report(&BookError::Image(
+        "img/cover.png".to_string(),
+        "missing".to_string(),
+    ));
+    notifications.report(&LibraryError::Meta("expected value".to_string()));
+    notifications.report(&OcrError::NoMatch);
+
+    let severities = notifications
+        .items
+        .iter()
+        .map(|n| n.severity)
+        .collect::<Vec<Severity>>();
+    assert_eq!(
+        severities,
+        vec![Severity::Warning, Severity::Error, Severity::Warning]
+    );
+    assert_eq!(
+        notifications.items[0].message,
+        "Couldn't load image img/cover.png: missing"
+    );
+    assert_eq!(
+        BookError::Open("book.epub".to_string(), "not found".to_string()).severity(),
+        Severity::Error
+    );
+}
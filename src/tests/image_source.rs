@@ -0,0 +1,86 @@
+#[allow(unused_imports)]
+use crate::image_source::{clipboard_path, inbox_images, is_image, unread_images};
+#[allow(unused_imports)]
+use druid::im::HashSet;
+#[allow(unused_imports)]
+use std::fs;
+#[allow(unused_imports)]
+use std::path::PathBuf;
+#[allow(unused_imports)]
+use std::time::{Duration, SystemTime};
+
+#[allow(dead_code)]
+fn paths(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_is_image() {
+    assert!(is_image(&PathBuf::from("/inbox/IMG_0001.JPG")));
+    assert!(is_image(&PathBuf::from("scan.webp")));
+    assert!(!is_image(&PathBuf::from("page.txt")));
+    assert!(!is_image(&PathBuf::from("photo")));
+}
+
+#[test]
+fn test_unread_images() {
+    let images = paths(&["a.jpg", "b.jpg", "c.jpg"]);
+    let mut read = HashSet::new();
+    read.insert("c.jpg".to_string());
+
+    assert_eq!(
+        unread_images(images.clone(), &read, false),
+        paths(&["b.jpg"])
+    );
+    assert_eq!(
+        unread_images(images.clone(), &read, true),
+        paths(&["a.jpg", "b.jpg"])
+    );
+
+    read.insert("a.jpg".to_string());
+    read.insert("b.jpg".to_string());
+    assert!(unread_images(images, &read, false).is_empty());
+}
+
+#[test]
+fn test_clipboard_path() {
+    assert_eq!(
+        clipboard_path("file:///home/me/My%20Photos/page.png\n"),
+        Some(PathBuf::from("/home/me/My Photos/page.png"))
+    );
+    assert_eq!(
+        clipboard_path("file:///home/me/Libri/perch%C3%A8%20100%25.jpg"),
+        Some(PathBuf::from("/home/me/Libri/perchè 100%.jpg"))
+    );
+    /* Solo gli URI sono codificati, un percorso può contenere % */
+    assert_eq!(
+        clipboard_path("/tmp/100%25.jpg"),
+        Some(PathBuf::from("/tmp/100%25.jpg"))
+    );
+    assert_eq!(
+        clipboard_path("  /tmp/page.jpg  "),
+        Some(PathBuf::from("/tmp/page.jpg"))
+    );
+    assert_eq!(clipboard_path("Quel ramo del lago di Como"), None);
+    assert_eq!(clipboard_path(""), None);
+}
+
+#[test]
+fn test_inbox_images() {
+    let dir = std::env::temp_dir().join("ebook_reader_inbox_test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    /* La foto più recente è l'ultima, anche se il nome viene prima */
+    let now = SystemTime::now();
+    for (name, age) in [("b.jpg", 20), ("a.png", 10), ("notes.txt", 0)] {
+        let file = fs::File::create(dir.join(name)).unwrap();
+        file.set_modified(now - Duration::from_secs(age)).unwrap();
+    }
+
+    assert_eq!(
+        inbox_images(&dir).unwrap(),
+        vec![dir.join("b.jpg"), dir.join("a.png")]
+    );
+    assert!(inbox_images(&dir.join("missing")).is_err());
+}
@@ -0,0 +1,51 @@
+#[allow(unused_imports)]
+use crate::jobs::{JobKind, JobManager};
+#[allow(unused_imports)]
+use std::sync::atomic::Ordering;
+
+#[test]
+fn test_register_jobs() {
+    let mut jobs = JobManager::new();
+    let (book, _) = jobs.register(JobKind::LoadBook, "Opening the book");
+    let (ocr, _) = jobs.register(JobKind::Ocr, "Reading the photo");
+    assert_ne!(book, ocr);
+    assert_eq!(jobs.jobs.len(), 2);
+    assert_eq!(jobs.jobs[1].title, "Reading the photo");
+    assert_eq!(jobs.jobs[1].progress, None);
+
+    jobs.progress(ocr, 0.5);
+    assert_eq!(jobs.jobs[1].progress, Some(0.5));
+
+    jobs.finish(book);
+    assert_eq!(jobs.jobs.len(), 1);
+    assert_eq!(jobs.jobs[0].id, ocr);
+}
+
+#[test]
+fn test_cancel_job() {
+    let mut jobs = JobManager::new();
+    let (id, flag) = jobs.register(JobKind::Ocr, "Reading the photo");
+
+    assert_eq!(jobs.cancel(id), Some(JobKind::Ocr));
+    assert!(flag.load(Ordering::Relaxed));
+    assert!(jobs.jobs[0].cancelled);
+
+    /* Resta nell'elenco finché il thread non ha finito, ma non si annulla due volte */
+    assert_eq!(jobs.cancel(id), None);
+    jobs.finish(id);
+    assert!(jobs.jobs.is_empty());
+    assert_eq!(jobs.cancel(id), None);
+}
+
+#[test]
+fn test_cancel_kind() {
+    let mut jobs = JobManager::new();
+    let (_, book) = jobs.register(JobKind::LoadBook, "Opening the book");
+    let (_, first) = jobs.register(JobKind::Ocr, "Reading the photo");
+    let (_, second) = jobs.register(JobKind::Ocr, "Reading 3 photos");
+
+    jobs.cancel_kind(JobKind::Ocr);
+    assert!(!book.load(Ordering::Relaxed));
+    assert!(first.load(Ordering::Relaxed));
+    assert!(second.load(Ordering::Relaxed));
+}
@@ -0,0 +1,132 @@
+#[allow(unused_imports)]
+use crate::layout::{Block, LayoutModel, LayoutSettings};
+#[allow(unused_imports)]
+use crate::ocr::{Mapping, OcrData};
+
+/* Pagine da 10 righe da 50 caratteri, titoli h1 alti due righe */
+#[allow(dead_code)]
+fn model() -> LayoutModel {
+    LayoutModel {
+        chars_per_line: 50.,
+        page_lines: 10.,
+        chapter_start: 0.,
+        heading_scale: 2.,
+        heading_spacing: 1.,
+        paragraph_spacing: 0.,
+        widow_control: true,
+        block_width: Some(20.),
+    }
+}
+
+#[allow(dead_code)]
+fn mapping(is_first: bool, page_lines: usize, text_height: u32) -> Mapping {
+    Mapping {
+        is_first,
+        page_lines,
+        tot_chars: 500,
+        full_lines: 10,
+        line_pitch: 30,
+        text_height,
+        text_width: 600,
+        heading_scale: if is_first { 2.2 } else { 1. },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_text_blocks() {
+    let model = model();
+    assert_eq!(model.text_block(100, 16.), Block::Paragraph(2.));
+    assert_eq!(model.text_block(102, 16.), Block::Paragraph(2.)); // Giustificazione
+    assert_eq!(model.text_block(0, 16.), Block::Paragraph(1.));
+    /* h1: 25 caratteri per riga, due righe alte il doppio più lo spazio sopra e sotto */
+    assert_eq!(model.text_block(30, 35.), Block::Heading(6.));
+}
+
+#[test]
+fn test_image_scaled_to_text_block() {
+    let mut model = model();
+    assert_eq!(model.image_block(400, 100), Block::Image(5.));
+    assert_eq!(model.image_block(400, 800), Block::Image(10.)); // Al massimo una pagina
+    model.block_width = None;
+    assert_eq!(model.image_block(400, 100), Block::Image(5.));
+}
+
+#[test]
+fn test_paginate_widows_and_orphans() {
+    let mut model = model();
+    /* Resterebbe una riga sola in fondo alla pagina */
+    let blocks = [Block::Paragraph(9.), Block::Paragraph(3.)];
+    assert_eq!(model.paginate(&blocks), (vec![(1, false), (2, false)], 2));
+    /* Resterebbe una riga sola in cima alla pagina successiva */
+    let blocks = [
+        Block::Paragraph(6.),
+        Block::Paragraph(5.),
+        Block::Paragraph(9.),
+    ];
+    assert_eq!(
+        model.paginate(&blocks),
+        (vec![(1, false), (2, true), (3, true)], 3)
+    );
+
+    model.widow_control = false;
+    let blocks = [Block::Paragraph(9.), Block::Paragraph(3.)];
+    assert_eq!(model.paginate(&blocks), (vec![(1, false), (2, true)], 2));
+    let blocks = [
+        Block::Paragraph(6.),
+        Block::Paragraph(5.),
+        Block::Paragraph(9.),
+    ];
+    assert_eq!(
+        model.paginate(&blocks),
+        (vec![(1, false), (2, true), (2, false)], 2)
+    );
+}
+
+#[test]
+fn test_paginate_headings_and_chapter_start() {
+    let mut model = model();
+    /* Il titolo ci starebbe, ma senza il testo che lo segue */
+    let blocks = [
+        Block::Paragraph(7.),
+        Block::Heading(3.),
+        Block::Skip,
+        Block::Paragraph(5.),
+    ];
+    assert_eq!(
+        model.paginate(&blocks),
+        (vec![(1, false), (2, false), (2, false), (2, false)], 2)
+    );
+
+    model.chapter_start = 4.;
+    assert_eq!(
+        model.paginate(&[Block::Paragraph(7.)]),
+        (vec![(2, true)], 2)
+    );
+}
+
+#[test]
+fn test_calibration_from_photos() {
+    let mut ocr = OcrData::new();
+    /* Interlinea di 30 pixel: 5 interlinee di testo nella prima pagina, 9 nelle altre */
+    ocr.mappings.push_back(mapping(true, 6, 150));
+    ocr.mappings.push_back(mapping(false, 10, 270));
+    ocr.first = Some(0);
+    ocr.other = Some(1);
+
+    let model = LayoutModel::new(&ocr);
+    assert_eq!(model.page_lines, 10.);
+    assert_eq!(model.chars_per_line, 50.);
+    assert_eq!(model.chapter_start, 4.);
+    assert_eq!(model.heading_scale, 2.2);
+    assert_eq!(model.block_width, Some(20.));
+
+    ocr.layout = LayoutSettings {
+        calibrate: false,
+        chapter_start: 1.,
+        ..Default::default()
+    };
+    let model = LayoutModel::new(&ocr);
+    assert_eq!(model.chapter_start, 1.);
+    assert_eq!(model.heading_scale, LayoutSettings::default().heading_scale);
+}
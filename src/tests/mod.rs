@@ -1 +1,14 @@
+pub(crate) mod algorithms;
+pub(crate) mod bookcase;
+pub(crate) mod chapter;
+pub(crate) mod errors;
+pub(crate) mod image_source;
+pub(crate) mod jobs;
+pub(crate) mod layout;
+pub(crate) mod ocr;
+pub(crate) mod ocr_backend;
+pub(crate) mod opf;
+pub(crate) mod preprocess;
 pub(crate) mod utilities;
+pub(crate) mod view;
+pub(crate) mod wizard;
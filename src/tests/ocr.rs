@@ -0,0 +1,318 @@
+#[allow(unused_imports)]
+use crate::algorithms::MatchStrategy;
+#[allow(unused_imports)]
+use crate::book::chapter::Chapter;
+#[allow(unused_imports)]
+use crate::ocr::{
+    find_matches, fit_chapter_pages, is_ambiguous, parse_chapter_pages, ChapterPages, Mapping,
+    OcrData, PageAnchor, WordIndex,
+};
+#[allow(unused_imports)]
+use crate::ocr_backend::{OcrLine, OcrPage};
+#[allow(unused_imports)]
+use druid::im::Vector;
+#[allow(unused_imports)]
+use std::sync::Arc;
+
+#[allow(dead_code)]
+fn chapters() -> Vector<Chapter> {
+    let texts = [
+        "Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura",
+        "Quando Gregorio Samsa risvegliandosi una mattina da sogni inquieti si trovò trasformato nel suo letto in un enorme insetto",
+        "Quel ramo del lago di Como che volge a mezzogiorno tra due catene non interrotte di monti tutto seni e golfi",
+    ];
+    texts
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            Chapter::new(
+                format!("ch{}.xhtml", i),
+                format!("<html><body><p>{}</p></body></html>", t),
+                0,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_index_finds_chapter_and_offset() {
+    let index = WordIndex::new(&chapters());
+    let page = "mezzogiorno tra due catene non\ninterrotte di monti".to_string();
+    let matches = find_matches(page, &index, 0.5, MatchStrategy::Linear);
+    assert_eq!((matches[0].ch, matches[0].offset), (2, 1));
+    assert_eq!(matches[0].score, 1.);
+    assert!(matches[0].snippet.starts_with("mezzogiorno tra due catene"));
+}
+
+#[test]
+fn test_index_tolerates_ocr_errors() {
+    let index = WordIndex::new(&chapters());
+    let page = "Samsa risve-\ngliandosi una mattina da sogni inqu1eti si trovò trasformat0".to_string();
+    let matches = find_matches(page, &index, 0.5, MatchStrategy::Linear);
+    assert_eq!((matches[0].ch, matches[0].offset), (1, 2));
+    assert!(!is_ambiguous(&matches));
+}
+
+#[test]
+fn test_index_no_match() {
+    let index = WordIndex::new(&chapters());
+    let page = "Tanto gentile e tanto onesta pare".to_string();
+    assert!(find_matches(page, &index, 0.5, MatchStrategy::Linear).is_empty());
+}
+
+#[test]
+fn test_index_same_result_on_any_thread_count() {
+    /* Due capitoli uguali: a parità di punteggio deve vincere sempre il primo */
+    let mut chs = chapters();
+    chs.push_back(chs[2].clone());
+    let page = "mezzogiorno tra due catene non interrotte di monti".to_string();
+    let run = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let index = WordIndex::new(&chs);
+            find_matches(page.clone(), &index, 0.5, MatchStrategy::Alignment)
+        })
+    };
+    let sequential = run(1);
+    assert_eq!((sequential[0].ch, sequential[0].offset), (2, 1));
+    assert_eq!(sequential, run(4));
+}
+
+#[test]
+fn test_index_threshold() {
+    let index = WordIndex::new(&chapters());
+    let page = "Samsa risvegliandosi una domenica da sogni inquieti".to_string();
+    assert_eq!(find_matches(page.clone(), &index, 0.5, MatchStrategy::Linear).len(), 1);
+    assert!(find_matches(page, &index, 0.9, MatchStrategy::Linear).is_empty());
+}
+
+#[test]
+fn test_repeated_passage_is_ambiguous() {
+    let mut chs = chapters();
+    chs.push_back(Chapter::new(
+        "ch3.xhtml".to_string(),
+        "<html><body><p>Come disse il poeta: nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura</p></body></html>".to_string(),
+        0,
+    ));
+    let index = WordIndex::new(&chs);
+    let matches = find_matches("cammin di nostra vita mi ritrovai per una selva".to_string(), &index, 0.5, MatchStrategy::Linear);
+    assert_eq!(matches.len(), 2);
+    assert_eq!((matches[0].ch, matches[1].ch), (0, 3));
+    assert!(is_ambiguous(&matches));
+}
+
+#[test]
+fn test_alignment_tolerates_inserted_words() {
+    let index = WordIndex::new(&chapters());
+    let page = "Gregorio Samsa forse risvegliandosi quella mattina sogni inquieti".to_string();
+    assert!(find_matches(page.clone(), &index, 0.5, MatchStrategy::Linear).is_empty());
+    let matches = find_matches(page, &index, 0.5, MatchStrategy::Alignment);
+    assert_eq!((matches[0].ch, matches[0].offset), (1, 1));
+}
+
+/* Righe alte 20 pixel ogni 30, larghe 600 tranne l'ultima del paragrafo */
+#[allow(dead_code)]
+fn body_lines(top: u32, count: u32) -> Vec<OcrLine> {
+    (0..count)
+        .map(|i| match i + 1 == count {
+            true => line("fine.", (100, top + i * 30, 400, top + i * 30 + 20)),
+            false => line(
+                "una riga piena di testo",
+                (100, top + i * 30, 700, top + i * 30 + 20),
+            ),
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+fn line(text: &str, bbox: (u32, u32, u32, u32)) -> OcrLine {
+    OcrLine {
+        text: text.to_string(),
+        bbox,
+        confidence: None,
+    }
+}
+
+#[test]
+fn test_layout_running_title_with_page_number() {
+    let mut lines = vec![line("12 I PROMESSI SPOSI", (100, 40, 500, 60))];
+    lines.extend(body_lines(120, 10));
+    let page = OcrPage {
+        text: String::new(),
+        lines: Arc::new(lines),
+    };
+    let mapping = Mapping::new(&page).unwrap();
+    assert_eq!(mapping.page, 12);
+    assert_eq!(mapping.page_lines, 10);
+    assert_eq!(mapping.full_lines, 9);
+    assert_eq!(mapping.tot_chars, 9 * 23);
+}
+
+#[test]
+fn test_layout_chapter_title_and_footer() {
+    /* Il titolo è staccato dal testo ma più grande, quindi conta come riga */
+    let mut lines = vec![line("CAPITOLO I", (250, 40, 550, 80))];
+    lines.extend(body_lines(160, 6));
+    lines.push(line("7", (390, 500, 410, 520)));
+    let page = OcrPage {
+        text: String::new(),
+        lines: Arc::new(lines),
+    };
+    let mapping = Mapping::new(&page).unwrap();
+    assert_eq!(mapping.page, 7);
+    assert_eq!(mapping.page_lines, 7);
+    assert_eq!(mapping.full_lines, 5);
+}
+
+#[test]
+fn test_layout_falls_back_to_text() {
+    let page = OcrPage {
+        text: "una riga piena di testo\nuna riga piena di testo\nfine.\n\n15".to_string(),
+        lines: Arc::new(vec![]),
+    };
+    let mapping = Mapping::new(&page).unwrap();
+    assert_eq!(mapping.page, 15);
+    assert_eq!(mapping.page_lines, 3);
+}
+
+#[allow(dead_code)]
+fn chapter(pages: usize, part: bool) -> ChapterPages {
+    ChapterPages {
+        pages,
+        element_pages: (0..pages).map(|page| (page, page)).collect(),
+        part,
+    }
+}
+
+#[test]
+fn test_fit_without_anchors() {
+    let chapters = [chapter(9, false), chapter(2, true), chapter(5, false)];
+    /* La parte va a pagina dispari, il capitolo dopo comincia dopo le sue due pagine */
+    assert_eq!(fit_chapter_pages(&chapters, 3, 11, &[]), vec![11, 21, 23]);
+    assert_eq!(fit_chapter_pages(&chapters, 3, 12, &[]), vec![12, 21, 23]);
+}
+
+#[test]
+fn test_fit_part_shift_stops_at_anchor() {
+    let chapters = [chapter(2, true), chapter(10, false), chapter(10, false)];
+    /* Il secondo capitolo è spostato dalla parte, il terzo è fissato dall'ancora */
+    assert_eq!(
+        fit_chapter_pages(&chapters, 0, 2, &[manual(2, 14)]),
+        vec![3, 5, 14]
+    );
+}
+
+#[test]
+fn test_fit_scales_between_anchors() {
+    let chapters = [chapter(10, false), chapter(10, false), chapter(10, false)];
+    /* Stimata a pagina 15, osservata a pagina 21 */
+    let anchor = PageAnchor {
+        ch: 1,
+        element: 4,
+        page: 21,
+        manual: false,
+    };
+    assert_eq!(
+        fit_chapter_pages(&chapters, 0, 1, &[anchor]),
+        vec![1, 15, 27]
+    );
+
+    /* Un'ancora che torna indietro rispetto alla precedente viene ignorata */
+    let wrong = PageAnchor {
+        ch: 2,
+        element: 0,
+        page: 5,
+        manual: false,
+    };
+    assert_eq!(
+        fit_chapter_pages(&chapters, 0, 1, &[anchor, wrong]),
+        vec![1, 15, 27]
+    );
+}
+
+#[test]
+fn test_add_anchor_range() {
+    let mut ocr = OcrData::new();
+    ocr.first_chap = Some(1);
+    let anchor = |ch, element, page| PageAnchor {
+        ch,
+        element,
+        page,
+        manual: false,
+    };
+    assert_eq!(ocr.add_anchor(anchor(3, 5, 40)), (1, None));
+    assert_eq!(ocr.add_anchor(anchor(5, 0, 60)), (3, None));
+    assert_eq!(ocr.add_anchor(anchor(4, 2, 50)), (3, Some(5)));
+    assert_eq!(ocr.add_anchor(anchor(4, 2, 52)), (3, Some(5)));
+    assert_eq!(
+        ocr.anchors.iter().map(|a| a.page).collect::<Vec<usize>>(),
+        vec![40, 52, 60]
+    );
+}
+
+#[allow(dead_code)]
+fn manual(ch: usize, page: usize) -> PageAnchor {
+    PageAnchor {
+        ch,
+        element: 0,
+        page,
+        manual: true,
+    }
+}
+
+#[test]
+fn test_fit_manual_anchors_win() {
+    let chapters = [chapter(10, false), chapter(10, false), chapter(10, false)];
+    let ocr = PageAnchor {
+        ch: 1,
+        element: 4,
+        page: 25,
+        manual: false,
+    };
+    /* L'ancora dell'OCR contraddice quella manuale e viene ignorata */
+    assert_eq!(
+        fit_chapter_pages(&chapters, 0, 1, &[ocr, manual(2, 21)]),
+        vec![1, 11, 21]
+    );
+    /* Anche la pagina di partenza cede a un'ancora manuale sul primo capitolo */
+    assert_eq!(
+        fit_chapter_pages(&chapters, 0, 1, &[manual(0, 3)]),
+        vec![3, 13, 23]
+    );
+    /* Una parte messa a mano su una pagina pari non viene spostata */
+    let chapters = [chapter(2, true), chapter(10, false)];
+    assert_eq!(fit_chapter_pages(&chapters, 0, 3, &[]), vec![3, 5]);
+    assert_eq!(fit_chapter_pages(&chapters, 0, 1, &[manual(0, 4)]), vec![4, 6]);
+}
+
+#[test]
+fn test_ocr_anchor_keeps_manual() {
+    let mut ocr = OcrData::new();
+    ocr.first_chap = Some(0);
+    ocr.add_anchor(manual(2, 30));
+    ocr.add_anchor(PageAnchor {
+        ch: 2,
+        element: 0,
+        page: 35,
+        manual: false,
+    });
+    assert_eq!(ocr.anchors.iter().collect::<Vec<_>>(), vec![&manual(2, 30)]);
+    ocr.add_anchor(manual(2, 32));
+    assert_eq!(ocr.anchors.iter().collect::<Vec<_>>(), vec![&manual(2, 32)]);
+}
+
+#[test]
+fn test_parse_chapter_pages() {
+    let inputs = |pages: &[&str]| pages.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        parse_chapter_pages(&inputs(&["0", " 5", "", "9"])),
+        Ok(vec![0, 5, 0, 9])
+    );
+    assert!(parse_chapter_pages(&inputs(&["5", "0", "3"]))
+        .unwrap_err()
+        .starts_with("Chapter 3"));
+    assert!(parse_chapter_pages(&inputs(&["v"])).is_err());
+}
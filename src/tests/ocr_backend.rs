@@ -0,0 +1,81 @@
+#[allow(unused_imports)]
+use crate::ocr_backend::{command_args, parse_hocr, OcrBackend, OcrLine, TextFileBackend};
+#[allow(unused_imports)]
+use std::fs;
+#[allow(unused_imports)]
+use std::path::PathBuf;
+
+#[allow(dead_code)]
+const HOCR: &str = "<div class='ocr_page' id='page_1' title='image \"\"; bbox 0 0 800 1200; ppageno 0'>
+ <div class='ocr_carea' id='block_1_1' title=\"bbox 100 80 700 140\">
+  <p class='ocr_par' id='par_1_1' lang='ita' title=\"bbox 100 80 700 140\">
+   <span class='ocr_header' id='line_1_1' title=\"bbox 300 80 500 100; baseline 0 -3; x_size 20\">
+    <span class='ocrx_word' id='word_1_1' title='bbox 300 80 500 100; x_wconf 90'>CAPITOLO</span>
+   </span>
+   <span class='ocr_line' id='line_1_2' title=\"bbox 100 120 700 140; baseline 0 -4\">
+    <span class='ocrx_word' id='word_1_2' title='bbox 100 120 200 140; x_wconf 80'>Quel</span>
+    <span class='ocrx_word' id='word_1_3' title='bbox 210 120 300 140; x_wconf 60'><strong>ramo</strong></span>
+    <span class='ocrx_word' id='word_1_4' title='bbox 310 120 330 140; x_wconf 70'>&amp;</span>
+   </span>
+  </p>
+ </div>
+</div>";
+
+#[test]
+fn test_hocr_lines() {
+    let lines = parse_hocr(HOCR);
+    assert_eq!(
+        lines,
+        vec![
+            OcrLine {
+                text: "CAPITOLO".to_string(),
+                bbox: (300, 80, 500, 100),
+                confidence: Some(90.),
+            },
+            OcrLine {
+                text: "Quel ramo &".to_string(),
+                bbox: (100, 120, 700, 140),
+                confidence: Some(70.),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_hocr_with_doctype() {
+    let doc = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n<html><body>{}</body></html>",
+        HOCR
+    );
+    assert_eq!(parse_hocr(&doc).len(), 2);
+    assert!(parse_hocr("not hocr <").is_empty());
+}
+
+#[test]
+fn test_command_placeholders() {
+    assert_eq!(
+        command_args("tesseract {image} - -l {lang}", "/tmp/my page.png", "ita+eng"),
+        vec!["tesseract", "/tmp/my page.png", "-", "-l", "ita+eng"]
+    );
+    assert_eq!(
+        command_args("my-ocr --fast", "/tmp/page.png", "ita"),
+        vec!["my-ocr", "--fast", "/tmp/page.png"]
+    );
+    assert!(command_args("  ", "/tmp/page.png", "ita").is_empty());
+}
+
+#[test]
+fn test_text_file_next_to_photo() {
+    let dir = std::env::temp_dir().join(format!("ebook_reader_ocr_backend_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("page.txt"), "Quel ramo del lago di Como").unwrap();
+
+    let backend = TextFileBackend {};
+    for file in ["page.jpg", "page.txt"] {
+        let output = backend.recognize(&dir.join(file), "ita").unwrap();
+        assert_eq!(output.text, "Quel ramo del lago di Como");
+        assert!(output.lines.is_empty());
+    }
+    assert!(backend.recognize(&dir.join("missing.png"), "ita").is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
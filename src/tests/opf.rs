@@ -0,0 +1,131 @@
+#[allow(unused_imports)]
+use crate::book::opf::{
+    cover_item, nav_item, page_label, page_list, set_cover, set_metadata, unique_identifier,
+};
+
+#[allow(dead_code)]
+const OPF: &str = "<package><metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\
+            <dc:title id=\"t1\">Old title</dc:title>\
+            <dc:creator>Someone</dc:creator>\
+            <dc:language/>\
+        </metadata></package>";
+
+#[test]
+fn test_set_metadata_replace() {
+    let opf = set_metadata(OPF, "title", "New & improved");
+    assert!(opf.contains("<dc:title id=\"t1\">New &amp; improved</dc:title>"));
+    assert!(!opf.contains("Old title"));
+}
+
+#[test]
+fn test_set_metadata_self_closing() {
+    let opf = set_metadata(OPF, "language", "it");
+    assert!(opf.contains("<dc:language>it</dc:language>"));
+    assert!(opf.contains("<dc:creator>Someone</dc:creator>"));
+}
+
+#[test]
+fn test_set_metadata_insert() {
+    let opf = set_metadata(OPF, "description", "A book");
+    assert!(opf.contains("<dc:description>A book</dc:description>\n</metadata>"));
+}
+
+#[test]
+fn test_set_metadata_remove() {
+    let opf = set_metadata(OPF, "creator", "");
+    assert!(!opf.contains("dc:creator"));
+}
+
+#[test]
+fn test_cover_item_epub3() {
+    let opf = "<package><manifest>\
+            <item id=\"c\" href=\"images/c.png\" media-type=\"image/png\" properties=\"cover-image\"/>\
+        </manifest></package>";
+    assert_eq!(
+        cover_item(opf),
+        Some(("images/c.png".to_string(), "image/png".to_string()))
+    );
+}
+
+#[test]
+fn test_cover_item_epub2() {
+    let opf = "<package><metadata><meta name=\"cover\" content=\"img\"/></metadata>\
+        <manifest><item id=\"img\" href=\"cover.jpg\" media-type=\"image/jpeg\"/></manifest></package>";
+    assert_eq!(
+        cover_item(opf),
+        Some(("cover.jpg".to_string(), "image/jpeg".to_string()))
+    );
+}
+
+#[test]
+fn test_set_cover() {
+    let opf = "<package><metadata><meta name=\"cover\" content=\"img\"/></metadata>\
+        <manifest><item id=\"img\" href=\"cover.jpg\" media-type=\"image/jpeg\" properties=\"cover-image\"/></manifest></package>";
+    let opf = set_cover(opf, "custom-cover.png", "image/png");
+    assert_eq!(
+        cover_item(&opf),
+        Some(("custom-cover.png".to_string(), "image/png".to_string()))
+    );
+}
+
+#[test]
+fn test_unique_identifier() {
+    let opf = "<package unique-identifier=\"uid\"><metadata>\
+            <dc:identifier xmlns:dc=\"dc\" id=\"isbn\">978</dc:identifier>\
+            <dc:identifier xmlns:dc=\"dc\" id=\"uid\"> urn:uuid:1234 </dc:identifier>\
+        </metadata></package>";
+    assert_eq!(unique_identifier(opf), Some("urn:uuid:1234".to_string()));
+}
+
+#[test]
+fn test_nav_item() {
+    let opf = "<package><manifest>\
+            <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\
+            <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\
+        </manifest></package>";
+    assert_eq!(nav_item(opf), Some("nav.xhtml".to_string()));
+    let opf = "<package><manifest>\
+            <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\
+        </manifest></package>";
+    assert_eq!(nav_item(opf), Some("toc.ncx".to_string()));
+}
+
+#[test]
+fn test_page_label() {
+    assert_eq!(page_label(" 12 "), Some(12));
+    assert_eq!(page_label("Page 7"), Some(7));
+    assert_eq!(page_label("xii"), None);
+    assert_eq!(page_label(""), None);
+}
+
+#[test]
+fn test_page_list_nav() {
+    let nav = "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\"><body>\
+            <nav epub:type=\"toc\"><ol><li><a href=\"Text/ch1.xhtml#c1\">1</a></li></ol></nav>\
+            <nav epub:type=\"page-list\"><ol>\
+                <li><a href=\"Text/ch1.xhtml#pg_iv\">iv</a></li>\
+                <li><a href=\"Text/ch1.xhtml#pg1\">1</a></li>\
+                <li><a href=\"../Misc/ch2.xhtml#pg2\"><span>2</span></a></li>\
+            </ol></nav>\
+        </body></html>";
+    assert_eq!(
+        page_list(nav, "OEBPS/nav.xhtml"),
+        vec![
+            ("OEBPS/Text/ch1.xhtml".to_string(), "pg1".to_string(), 1),
+            ("Misc/ch2.xhtml".to_string(), "pg2".to_string(), 2),
+        ]
+    );
+}
+
+#[test]
+fn test_page_list_ncx() {
+    let ncx = "<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\"><pageList>\
+            <pageTarget type=\"normal\" value=\"5\">\
+                <navLabel><text>5</text></navLabel><content src=\"ch3.xhtml#page5\"/>\
+            </pageTarget>\
+        </pageList></ncx>";
+    assert_eq!(
+        page_list(ncx, "toc.ncx"),
+        vec![("ch3.xhtml".to_string(), "page5".to_string(), 5)]
+    );
+}
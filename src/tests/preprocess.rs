@@ -0,0 +1,71 @@
+#[allow(unused_imports)]
+use crate::preprocess::GrayImage;
+
+/* Pagina finta: righe di "testo" nere spesse 4 pixel ogni 12, tra le colonne from e to */
+#[allow(dead_code)]
+fn page(width: usize, height: usize, from: usize, to: usize) -> GrayImage {
+    let mut img = GrayImage::new(width, height, 230);
+    for y in 20..height - 20 {
+        if y % 12 < 4 {
+            for x in from..to {
+                img.pixels[y * width + x] = 20;
+            }
+        }
+    }
+    img
+}
+
+#[test]
+fn test_binarize_handles_uneven_lighting() {
+    /* Sfondo che si scurisce verso destra: una soglia fissa renderebbe nera la parte destra */
+    let mut img = page(200, 200, 20, 180);
+    for y in 0..200 {
+        for x in 0..200 {
+            let px = &mut img.pixels[y * 200 + x];
+            *px = px.saturating_sub((x / 2) as u8);
+        }
+    }
+    let bin = img.binarize();
+    assert_eq!(bin.get(190, 30), 255); // Sfondo scuro a destra
+    assert_eq!(bin.get(150, 24), 0); // Riga di testo
+    assert_eq!(bin.get(150, 30), 255); // Interlinea
+}
+
+#[test]
+fn test_skew_angle_is_found_and_removed() {
+    let img = page(300, 300, 30, 270);
+    assert_eq!(img.skew_angle(), 0.);
+    let tilted = img.rotate(4.);
+    let angle = tilted.skew_angle();
+    assert!((angle.abs() - 4.).abs() <= 0.5, "angle {}", angle);
+    assert!(tilted.deskew().skew_angle().abs() <= 0.5);
+}
+
+#[test]
+fn test_crop_keeps_the_largest_text_block() {
+    /* Due pagine affiancate, quella di destra è solo un margine della pagina di fronte */
+    let mut img = page(400, 200, 20, 260);
+    let facing = page(400, 200, 340, 380);
+    for (px, f) in img.pixels.iter_mut().zip(facing.pixels.iter()) {
+        *px = (*px).min(*f);
+    }
+    let crop = img.crop_text_block();
+    assert!(crop.width <= 240 + 20 && crop.width >= 240, "width {}", crop.width);
+    assert_eq!(crop.height, (171 - 24 + 1) + 2 * 10); // Righe con inchiostro più i margini
+}
+
+#[test]
+fn test_sideways_pages_are_detected() {
+    let img = page(300, 300, 30, 270);
+    assert!(!img.is_sideways());
+    assert!(img.rotate_90().is_sideways());
+    assert!(!img.rotate_90().rotate_90().rotate_90().rotate_90().is_sideways());
+    assert_eq!(img.rotate_180().rotate_180(), img);
+}
+
+#[test]
+fn test_pgm_header() {
+    let img = GrayImage::from_pixels(2, 1, &[255, 0, 0, 255, 0, 0, 0, 255], 4);
+    assert_eq!(img.pixels, vec![76, 0]);
+    assert_eq!(img.to_pgm(), b"P5\n2 1\n255\nL\0".to_vec());
+}
@@ -1,5 +1,5 @@
 #[allow(unused_imports)]
-use crate::utilities::{unify_paths, xml_to_plain, xml_to_text};
+use crate::utilities::{choose_languages, unify_paths, xml_to_plain, xml_to_text};
 #[allow(unused_imports)]
 use std::path::PathBuf;
 #[allow(unused_imports)]
@@ -122,3 +122,29 @@ fn test_xml_to_text_nested_elements() {
     assert_eq!(xml_to_text(xml), expected_output);
 }
 
+
+#[allow(dead_code)]
+fn langs(langs: &[&str]) -> Vec<String> {
+    langs.iter().map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn test_choose_languages_keeps_installed() {
+    let installed = langs(&["eng", "ita"]);
+    assert_eq!(choose_languages("ita", &installed), Ok("ita".to_string()));
+    assert_eq!(choose_languages("ita+eng", &installed), Ok("ita+eng".to_string()));
+    assert_eq!(choose_languages(" ita + fra ", &installed), Ok("ita".to_string()));
+}
+
+#[test]
+fn test_choose_languages_falls_back() {
+    assert_eq!(choose_languages("fra", &langs(&["deu", "eng"])), Ok("eng".to_string()));
+    assert_eq!(choose_languages("fra", &langs(&["deu", "ita"])), Ok("deu".to_string()));
+    assert_eq!(choose_languages("", &langs(&["ita"])), Ok("ita".to_string()));
+}
+
+#[test]
+fn test_choose_languages_without_tessdata() {
+    assert_eq!(choose_languages("ita+eng", &[]), Ok("ita+eng".to_string()));
+    assert!(choose_languages("", &[]).is_err());
+}
@@ -0,0 +1,46 @@
+#[allow(unused_imports)]
+use crate::book::epub_text::EpubText;
+#[allow(unused_imports)]
+use crate::book::page_element::PageElement;
+#[allow(unused_imports)]
+use crate::view::view::View;
+
+/* Un elemento per ogni pg_offset, come li lascia guess_lines */
+#[allow(dead_code)]
+fn view_with_pages(pages: &[(usize, bool)]) -> View {
+    let mut view = View::new();
+    view.update_view(
+        pages
+            .iter()
+            .map(|pg| {
+                let mut el = PageElement::from_error(EpubText::from("text".to_string()), false);
+                el.pg_offset = *pg;
+                el
+            })
+            .collect(),
+    );
+    view
+}
+
+#[test]
+fn test_page_range() {
+    let view = view_with_pages(&[(10, false), (10, false), (11, true), (11, false), (12, true)]);
+    assert_eq!(view.page_range(0, 1), Some((10, 10)));
+    assert_eq!(view.page_range(1, 2), Some((10, 11)));
+    assert_eq!(view.page_range(3, 10), Some((11, 12)));
+}
+
+#[test]
+fn test_page_range_not_aligned() {
+    let view = view_with_pages(&[(0, false), (0, false)]);
+    assert_eq!(view.page_range(0, 1), None);
+}
+
+#[test]
+fn test_element_of_page() {
+    let view = view_with_pages(&[(10, false), (11, true), (11, false), (12, true)]);
+    assert_eq!(view.element_of_page(10), Some(0));
+    assert_eq!(view.element_of_page(11), Some(1));
+    assert_eq!(view.element_of_page(12), Some(3));
+    assert_eq!(view.element_of_page(13), None);
+}
@@ -0,0 +1,98 @@
+#[allow(unused_imports)]
+use crate::wizard::{OcrWizard, WizardStep};
+
+/* Wizard fermo al controllo della prima pagina, con la foto già letta */
+#[allow(dead_code)]
+fn wizard_at_first_check() -> OcrWizard {
+    let mut wizard = OcrWizard::new();
+    wizard.next();
+    wizard.start_loading();
+    wizard.page_read("Chapter one\nIt was a dark night", 0, 0, 24);
+    wizard
+}
+
+#[test]
+fn test_wizard_steps() {
+    let mut wizard = wizard_at_first_check();
+    assert_eq!(wizard.step, WizardStep::CheckFirst);
+    assert_eq!(wizard.number(), 3);
+    assert!(!wizard.loading);
+    assert_eq!(wizard.current_page().unwrap().mapping, 0);
+    assert_eq!(wizard.page_input, "");
+    assert_eq!(wizard.lines_input, "24");
+
+    wizard.next();
+    assert_eq!(wizard.step, WizardStep::OtherPage);
+    assert!(wizard.current_page().is_none());
+
+    /* In attesa della foto non si va avanti senza caricarla */
+    wizard.next();
+    assert_eq!(wizard.step, WizardStep::OtherPage);
+
+    wizard.page_read("in the middle of the chapter", 1, 0, 30);
+    assert_eq!(wizard.step, WizardStep::CheckOther);
+    wizard.next();
+    assert_eq!(wizard.step, WizardStep::Samples);
+
+    wizard.sample_read("another page", 2, None);
+    assert_eq!(wizard.pages.len(), 3);
+    assert_eq!(wizard.back(), None);
+    assert_eq!(wizard.step, WizardStep::Samples);
+
+    wizard.next();
+    assert_eq!(wizard.step, WizardStep::Done);
+    assert_eq!(wizard.number(), 7);
+}
+
+#[test]
+fn test_wizard_check_inputs() {
+    let mut wizard = wizard_at_first_check();
+    assert_eq!(
+        wizard.check_inputs(),
+        Err("Type the page number printed on the photo".to_string())
+    );
+
+    wizard.page_input = " 7 ".to_string();
+    assert_eq!(wizard.check_inputs(), Ok((7, 24)));
+
+    wizard.lines_input = "2".to_string();
+    assert!(wizard.check_inputs().is_err());
+    wizard.lines_input = "abc".to_string();
+    assert!(wizard.check_inputs().is_err());
+
+    /* Per la pagina piena il numero di pagina non serve */
+    wizard.lines_input = "24".to_string();
+    wizard.next();
+    wizard.page_read("in the middle of the chapter", 1, 0, 30);
+    wizard.page_input = String::new();
+    assert_eq!(wizard.check_inputs(), Ok((0, 30)));
+}
+
+#[test]
+fn test_wizard_back_discards_photo() {
+    let mut wizard = wizard_at_first_check();
+    wizard.error = Some("Type the page number printed on the photo".to_string());
+
+    assert_eq!(wizard.back(), Some(0));
+    assert_eq!(wizard.step, WizardStep::FirstPage);
+    assert!(wizard.pages.is_empty());
+    assert!(wizard.error.is_none());
+
+    /* Dal passo della foto si torna indietro senza scartare nulla */
+    assert_eq!(wizard.back(), None);
+    assert_eq!(wizard.step, WizardStep::Intro);
+    assert_eq!(wizard.back(), None);
+    assert_eq!(wizard.step, WizardStep::Intro);
+}
+
+#[test]
+fn test_wizard_fail() {
+    let mut wizard = OcrWizard::new();
+    wizard.next();
+    wizard.start_loading();
+    wizard.fail("Couldn't read the image".to_string());
+    assert_eq!(wizard.step, WizardStep::FirstPage);
+    assert!(!wizard.loading);
+    assert_eq!(wizard.error, Some("Couldn't read the image".to_string()));
+    assert!(wizard.pages.is_empty());
+}
@@ -1,14 +1,27 @@
-use crate::app::{FINISH_BOOK_LOAD, FINISH_IMAGE_LOAD, FINISH_LEPTO_LOAD};
-use crate::book::page_element::PageElement;
+use crate::app::{
+    FINISH_BOOK_LOAD, FINISH_IMAGE_LOAD, FINISH_LEPTO_LOAD, FINISH_LEPTO_LOAD_MANY,
+    FINISH_PREPROCESS,
+};
 use crate::book::Book;
-use crate::ContentType;
-use druid::im::Vector;
+use crate::errors::{log, BookError, OcrError, Report, Severity};
+use crate::jobs::{background, JobHandle, JobKind, JobManager};
+use crate::ocr_backend::{ocr_backend, OcrOutput, OcrPage};
+use crate::preprocess::GrayImage;
+use crate::settings::Settings;
+use druid::im::{HashMap, Vector};
+use druid::piet::ImageFormat;
 use druid::{ExtEventSink, FileDialogOptions, FileSpec, ImageBuf, Target};
 use roxmltree::{Document, Node, ParsingOptions};
-use std::io::Read;
+use std::env::current_dir;
+use std::error::Error;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::thread;
-use unicode_segmentation::UnicodeSegmentation;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+pub const DEFAULT_IMAGE: &str = "./images/default.jpg";
 
 pub fn unify_paths(mut p1: PathBuf, p2: PathBuf) -> PathBuf {
     for el in p2.into_iter() {
@@ -30,24 +43,100 @@ pub fn convert_path_separators(href: String) -> String {
     path
 }
 
-pub fn get_image_buf(book_path: PathBuf, image_path: String) -> Option<ImageBuf> {
-    let zipfile = std::fs::File::open(book_path).unwrap();
-    let mut archive = zip::ZipArchive::new(zipfile).unwrap();
-    let mut file = match archive.by_name(&image_path) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error in opening archive at {}", e);
-            return None;
-        }
-    };
+pub fn get_image_buf(book_path: PathBuf, image_path: String) -> Result<ImageBuf, BookError> {
+    let error = |e: &dyn Error| BookError::Image(image_path.clone(), e.to_string());
+    let zipfile = fs::File::open(&book_path)
+        .map_err(|e| BookError::Open(book_path.display().to_string(), e.to_string()))?;
+    let mut archive = zip::ZipArchive::new(zipfile)
+        .map_err(|e| BookError::Open(book_path.display().to_string(), e.to_string()))?;
+    let mut file = archive.by_name(&image_path).map_err(|e| error(&e))?;
     let mut contents: Vec<u8> = vec![];
+    file.read_to_end(&mut contents).map_err(|e| error(&e))?;
+    ImageBuf::from_data(&contents).map_err(|e| error(e.as_ref()))
+}
+
+/*
+Immagine mostrata al posto di quelle mancanti o rovinate, vuota se manca anche lei
+*/
+pub fn default_image() -> ImageBuf {
+    ImageBuf::from_file(DEFAULT_IMAGE).unwrap_or_else(|e| {
+        log(Severity::Warning, &format!("{}: {}", DEFAULT_IMAGE, e));
+        ImageBuf::empty()
+    })
+}
+
+/*
+Copy the archive at src_path into target_path replacing the entries in the map
+*/
+pub fn rewrite_epub(
+    src_path: &str,
+    target_path: &str,
+    replaced: &HashMap<String, Vec<u8>>,
+) -> Result<(), Box<dyn Error>> {
+    let new_target_path = if target_path == src_path {
+        let mut new = current_dir()?.to_str().ok_or("No string conversion")?.to_string();
+        new.push_str("/tmp.epub");
+        new
+    } else {
+        let _ = fs::remove_file(target_path); //Non mettere '?' -> se non c'è non è un problema: con questa istruzione mi assicuro soltanto che non esista già
+        target_path.to_string()
+    };
+
+    let file = fs::File::open(src_path)?;
+    let writer = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(PathBuf::from(new_target_path.clone()))?;
+
+    let mut archive = zip::ZipArchive::new(file)?;
 
+    // Set the options for creating a new file entry in the zip file
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
 
-    file.read_to_end(&mut contents).unwrap(); //
-    match ImageBuf::from_data(&contents) {
-        Ok(im) => Some(im),
-        Err(_) => None,
+    let mut zip_writer = ZipWriter::new(writer);
+
+    // Iterate through the entries in the ZipArchive and add them to the ZipWriter, replacing the modified ones
+    let mut written = Vec::new();
+    for i in 0..archive.len() {
+        let zip_entry = archive.by_index(i)?;
+        let file_name = zip_entry.name().to_string();
+        written.push(file_name.clone());
+
+        match replaced.get(&file_name) {
+            None => {
+                if file_name.ends_with('/') {
+                    // Add a directory to the zip file
+                    zip_writer.add_directory(file_name, options)?;
+                } else {
+                    // Add a file to the zip file
+                    zip_writer.start_file(file_name, options)?;
+                    zip_writer.raw_copy_file(zip_entry)?;
+                }
+            }
+            Some(content) => {
+                zip_writer.start_file(file_name, options)?;
+                zip_writer.write_all(content)?;
+            }
+        }
     }
+
+    // Entries not present in the original archive are appended
+    for (file_name, content) in replaced.iter() {
+        if !written.contains(file_name) {
+            zip_writer.start_file(file_name.clone(), options)?;
+            zip_writer.write_all(content)?;
+        }
+    }
+
+    zip_writer.finish()?;
+
+    if target_path != new_target_path {
+        fs::remove_file(target_path)?;
+        fs::copy(&new_target_path, target_path)?;
+        fs::remove_file(&new_target_path)?;
+    }
+
+    Ok(())
 }
 
 pub fn save_file(name: String) -> FileDialogOptions {
@@ -73,30 +162,73 @@ pub fn open_epub() -> FileDialogOptions {
 }
 
 pub fn open_image() -> FileDialogOptions {
-    let jpg = FileSpec::new("jpg file", &["jpg"]);
-    let jpeg = FileSpec::new("JPeG file", &["jpeg"]);
-    let jpg_caps = FileSpec::new("JPG file", &["JPG"]);
+    let image = FileSpec::new(
+        "Image",
+        &[
+            "jpg", "jpeg", "JPG", "JPEG", "png", "PNG", "tif", "tiff", "TIF", "TIFF", "bmp", "BMP",
+            "webp", "WEBP",
+        ],
+    );
+    let jpg = FileSpec::new("jpg file", &["jpg", "jpeg", "JPG", "JPEG"]);
+    let txt = FileSpec::new("Recognized text", &["txt"]); // Per il motore OCR "file di testo"
+    let png = FileSpec::new("PNG file", &["png", "PNG"]);
+    let tiff = FileSpec::new("TIFF file", &["tif", "tiff", "TIF", "TIFF"]);
+    let bmp = FileSpec::new("BMP file", &["bmp", "BMP"]);
+    let webp = FileSpec::new("WebP file", &["webp", "WEBP"]);
     FileDialogOptions::new()
-        .allowed_types(vec![jpg, jpeg, jpg_caps])
-        .default_type(jpg)
+        .allowed_types(vec![image, jpg, png, tiff, bmp, webp, txt])
+        .default_type(image)
         .default_name("image.JPG")
         .name_label("Source")
         .title("Select an image to Import")
         .button_text("Import")
 }
 
+/* Più foto di pagine alla volta, per aggiungere campioni all'allineamento */
+pub fn open_images() -> FileDialogOptions {
+    open_image()
+        .multi_selection()
+        .title("Select the page photos to Import")
+}
+
+pub fn open_cover() -> FileDialogOptions {
+    let image = FileSpec::new("Image file", &["jpg", "jpeg", "JPG", "png", "webp"]);
+    FileDialogOptions::new()
+        .allowed_types(vec![image])
+        .default_type(image)
+        .name_label("Source")
+        .title("Select a cover image")
+        .button_text("Import")
+}
+
+/*
+FNV-1a: hash stabile tra diverse esecuzioni, usato per identificare libri e copertine
+*/
+pub fn stable_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
 /* FOR OCR PURPOSES */
 
 pub fn xml_to_text(xml: &str) -> String {
     let opt = ParsingOptions { allow_dtd: true };
     let doc = match Document::parse_with_options(xml, opt) {
         Result::Ok(doc) => doc,
-        Err(_e) => {
-            println!("Error");
+        Err(e) => {
+            log(Severity::Warning, &format!("Chapter not readable as text: {}", e));
             return " ".to_string();
         }
     };
-    let node = doc.root_element().last_element_child().unwrap();
+    /* L'ultimo figlio di <html> è il <body>, un capitolo che non ce l'ha non ha testo */
+    let node = match doc.root_element().last_element_child() {
+        Some(node) => node,
+        None => return " ".to_string(),
+    };
     let mut cur_text = String::new();
     xml_to_plain(node, &mut cur_text);
     cur_text
@@ -114,8 +246,7 @@ pub fn xml_to_plain(node: Node, current_text: &mut String) {
 
     /*  Actual Transformation */
 
-    if node.is_text() {
-        let text = node.text().unwrap();
+    if let Some(text) = node.text().filter(|_| node.is_text()) {
         let content: Vec<_> = text.split_ascii_whitespace().collect();
         if text.starts_with(char::is_whitespace) {
             current_text.push_str(" ");
@@ -167,113 +298,245 @@ pub fn xml_to_plain(node: Node, current_text: &mut String) {
     }
 }
 
-pub fn is_part(vec: Vector<PageElement>) -> bool {
-    /* Elementi non vuoti */
-    let filtered = vec
-        .iter()
-        .filter(|elem| {
-            if let ContentType::Text(text) = (*elem).clone().content {
-                if text.text.trim().len() > 0 {
-                    return true;
-                }
-                return false;
-            }
-            false
-        })
-        .map(|el| (*el).clone())
-        .collect::<Vector<PageElement>>();
-    /* Elementi non vuoti non maggiori di 80 char*/
-    if filtered.clone().iter().any(|el| {
-        if let ContentType::Text(text) = (*el).clone().content {
-            if text.text.replace(" ", "").graphemes(true).count() > 80 {
-                return true;
+const TESSDATA_DIRS: [&str; 7] = [
+    "/usr/share/tesseract-ocr/5/tessdata",
+    "/usr/share/tesseract-ocr/4.00/tessdata",
+    "/usr/share/tessdata",
+    "/usr/local/share/tessdata",
+    "/opt/homebrew/share/tessdata",
+    "/opt/local/share/tessdata",
+    "C:\\Program Files\\Tesseract-OCR\\tessdata",
+];
+
+pub fn th_lepto_load(
+    jobs: &mut JobManager,
+    sink: ExtEventSink,
+    path: PathBuf,
+    lang: &str,
+    settings: Settings,
+) {
+    let lang = lang.to_string();
+    jobs.spawn(JobKind::Ocr, "Reading the photo", sink, move |job| {
+        lepto_load(job, path, lang, settings)
+    });
+}
+
+fn lepto_load(job: &JobHandle, path: PathBuf, lang: String, settings: Settings) {
+    match ocr_image(path.clone(), &lang, &settings) {
+        Ok(mut output) => {
+            /* L'anteprima dell'immagine elaborata viene mostrata nel wizard OCR */
+            if let Some(preview) = ocr_preview(&mut output) {
+                job.submit(FINISH_PREPROCESS, preview);
             }
+            job.submit(FINISH_LEPTO_LOAD, Ok(OcrPage::from(output)));
+        }
+        Err(e) => {
+            let error = OcrError::Image(path.display().to_string(), e.to_string());
+            log(error.severity(), &error.to_string());
+            job.submit(FINISH_LEPTO_LOAD, Err(error));
         }
-        return false;
-    }) {
-        return false;
-    };
-    /* Massimo 3 elementi */
-    if filtered.len() > 0 && filtered.len() < 4 {
-        return true;
     }
+}
+
+pub fn th_lepto_load_many(
+    jobs: &mut JobManager,
+    sink: ExtEventSink,
+    paths: Vec<PathBuf>,
+    lang: &str,
+    settings: Settings,
+) {
+    let lang = lang.to_string();
+    let title = format!("Reading {} photos", paths.len());
+    jobs.spawn(JobKind::Ocr, &title, sink, move |job| {
+        /* Una foto illeggibile non blocca le altre, l'annullamento sì */
+        let total = paths.len();
+        let mut texts = Vec::new();
+        for (i, path) in paths.into_iter().enumerate() {
+            if job.is_cancelled() {
+                return;
+            }
+            texts.push(match ocr_image(path.clone(), &lang, &settings) {
+                Ok(mut output) => {
+                    let preview = ocr_preview(&mut output);
+                    Ok((OcrPage::from(output), preview))
+                }
+                Err(e) => {
+                    log(Severity::Error, &format!("{}: {}", path.display(), e));
+                    Err(e.to_string())
+                }
+            });
+            job.progress(i + 1, total);
+        }
+        job.submit(FINISH_LEPTO_LOAD_MANY, texts);
+    });
+}
 
-    false
+/* La foto come è stata passata a Tesseract, dopo la preparazione */
+fn ocr_preview(output: &mut OcrOutput) -> Option<ImageBuf> {
+    output
+        .image
+        .take()
+        .map(|img| ImageBuf::from_raw(img.pixels, ImageFormat::Grayscale, img.width, img.height))
 }
 
-pub fn th_lepto_load(sink: ExtEventSink, path: PathBuf, lang: &str) {
-    let s = lang.to_string().clone();
-    thread::spawn(move || lepto_load(sink, path, s));
+/*
+Prepara la foto per Tesseract secondo le impostazioni: scala di grigi, binarizzazione,
+raddrizzamento, ritaglio del blocco di testo e rotazione di 90° se necessaria
+*/
+pub fn preprocess_image(path: &PathBuf, settings: &Settings) -> Result<GrayImage, Box<dyn Error>> {
+    let buf = ImageBuf::from_file(path)?;
+    let mut img = GrayImage::from_pixels(
+        buf.width(),
+        buf.height(),
+        buf.raw_pixels(),
+        buf.format().bytes_per_pixel(),
+    );
+    if settings.preprocess_binarize {
+        img = img.binarize();
+    }
+    if settings.preprocess_rotation && img.is_sideways() {
+        img = img.rotate_90();
+    }
+    if settings.preprocess_deskew {
+        img = img.deskew();
+    }
+    if settings.preprocess_crop {
+        img = img.crop_text_block();
+    }
+    Ok(img)
 }
 
-fn lepto_load(sink: ExtEventSink, path: PathBuf, lang: String) {
-    let mut lt = leptess::LepTess::new(None, &lang).unwrap();
-    lt.set_image(path).unwrap();
-    match lt.get_utf8_text() {
-        Ok(text) => {
-            sink.submit_command(
-                FINISH_LEPTO_LOAD,
-                Option::Some(String::from(text)),
-                Target::Auto,
-            )
-            .expect("command failed to submit")
-        }
-        Err(_) => {
-            sink.submit_command(FINISH_LEPTO_LOAD, Option::None, Target::Auto)
-                .expect("command failed to submit");
+/*
+Cartelle in cui cercare i traineddata: quella scelta dall'utente, TESSDATA_PREFIX
+e quelle in cui le installano i pacchetti più comuni
+*/
+pub fn tessdata_dirs(settings: &Settings) -> Vec<PathBuf> {
+    if let Some(path) = settings.tessdata() {
+        return vec![PathBuf::from(path)];
+    }
+    let mut dirs = vec![];
+    if let Ok(prefix) = std::env::var("TESSDATA_PREFIX") {
+        dirs.push(PathBuf::from(&prefix));
+        dirs.push(PathBuf::from(&prefix).join("tessdata"));
+    }
+    dirs.extend(TESSDATA_DIRS.iter().map(PathBuf::from));
+    dirs
+}
+
+/* Lingue installate nella prima cartella tessdata che ne contiene, "osd" non è una lingua */
+pub fn installed_languages(settings: &Settings) -> Vec<String> {
+    for dir in tessdata_dirs(settings) {
+        let mut langs = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| {
+                    e.file_name()
+                        .to_str()
+                        .and_then(|name| name.strip_suffix(".traineddata"))
+                        .map(|lang| lang.to_string())
+                })
+                .filter(|lang| lang != "osd")
+                .collect::<Vec<String>>(),
+            Err(_) => continue,
+        };
+        if !langs.is_empty() {
+            langs.sort();
+            return langs;
         }
     }
+    vec![]
 }
 
-pub fn th_load_book(
-    sink: ExtEventSink,
+/*
+Stringa di lingue per Tesseract: tiene le lingue richieste (separate da +) che sono installate,
+se nessuna lo è ripiega sull'inglese o sulla prima installata. Se non si trova nessuna
+cartella tessdata le lingue richieste vengono passate così come sono
+*/
+pub fn choose_languages(requested: &str, installed: &[String]) -> Result<String, String> {
+    let requested = requested
+        .split('+')
+        .map(|lang| lang.trim())
+        .filter(|lang| !lang.is_empty())
+        .collect::<Vec<&str>>();
+    if installed.is_empty() {
+        return match requested.is_empty() {
+            true => Err("No OCR language set and no Tesseract language data found".to_string()),
+            false => Ok(requested.join("+")),
+        };
+    }
+    let available = requested
+        .iter()
+        .filter(|lang| installed.iter().any(|i| i == *lang))
+        .map(|lang| lang.to_string())
+        .collect::<Vec<String>>();
+    if !available.is_empty() {
+        return Ok(available.join("+"));
+    }
+    let fallback = match installed.iter().find(|lang| *lang == "eng") {
+        Some(eng) => eng.clone(),
+        None => installed[0].clone(),
+    };
+    log(
+        Severity::Warning,
+        &format!(
+            "Tesseract language data for '{}' not installed, using '{}'",
+            requested.join("+"),
+            fallback
+        ),
+    );
+    Ok(fallback)
+}
+
+/* Testo riconosciuto dal motore OCR scelto nelle impostazioni */
+pub fn ocr_image(
     path: PathBuf,
-    init_ch: usize,
-    init_el: usize,
-    ch_pg: Vector<usize>,
-) {
-    thread::spawn(move || load_book(sink, path, init_ch, init_el, ch_pg));
+    lang: &str,
+    settings: &Settings,
+) -> Result<OcrOutput, Box<dyn Error>> {
+    ocr_backend(settings).recognize(&path, lang)
 }
 
-fn load_book(
+pub fn th_load_book(
+    jobs: &mut JobManager,
     sink: ExtEventSink,
     path: PathBuf,
     init_ch: usize,
     init_el: usize,
     ch_pg: Vector<usize>,
 ) {
+    jobs.spawn(JobKind::LoadBook, "Opening the book", sink, move |job| {
+        load_book(job, path, init_ch, init_el, ch_pg)
+    });
+}
+
+fn load_book(job: &JobHandle, path: PathBuf, init_ch: usize, init_el: usize, ch_pg: Vector<usize>) {
     match Book::new(path, init_ch, init_el, &ch_pg) {
-        Ok(book) => sink
-            .submit_command(FINISH_BOOK_LOAD, Some(book), Target::Auto)
-            .expect("command failed to submit"),
-        Err(e) => {
-            println!("Error in loading book: {}", e);
-            sink.submit_command(FINISH_BOOK_LOAD, None, Target::Auto)
-                .expect("command failed to submit");
+        Ok(mut book) => {
+            /* L'indice per l'OCR è la parte lenta */
+            job.progress(1, 2);
+            if job.is_cancelled() {
+                return;
+            }
+            book.build_index();
+            job.submit(FINISH_BOOK_LOAD, Ok(book));
         }
+        Err(e) => job.submit(FINISH_BOOK_LOAD, Err(e)),
     }
 }
 
 pub fn th_load_image(sink: ExtEventSink, epub_img_path: String, epub_path: String) {
-    thread::spawn(move || load_image(sink, epub_img_path, epub_path));
+    background(move || load_image(sink, epub_img_path, epub_path));
 }
 
+/* Un'immagine mancante nell'epub non merita una notifica, basta il log */
 fn load_image(sink: ExtEventSink, epub_img_path: String, epub_path: String) {
-    match get_image_buf(PathBuf::from(epub_path), epub_img_path.clone()) {
-        Some(img) => sink
-            .submit_command(FINISH_IMAGE_LOAD, (img, epub_img_path), Target::Auto)
-            .expect("command failed to submit"),
-        None => {
-            sink.submit_command(
-                FINISH_IMAGE_LOAD,
-                (
-                    ImageBuf::from_file("./images/default.jpg").unwrap(),
-                    epub_img_path,
-                ),
-                Target::Auto,
-            )
-            .expect("command failed to submit");
-        }
+    let img = get_image_buf(PathBuf::from(epub_path), epub_img_path.clone()).unwrap_or_else(|e| {
+        log(e.severity(), &e.to_string());
+        default_image()
+    });
+    /* Fallisce solo se l'applicazione si sta chiudendo */
+    if let Err(e) = sink.submit_command(FINISH_IMAGE_LOAD, (img, epub_img_path), Target::Auto) {
+        log(Severity::Info, &e.to_string());
     }
 }
 
@@ -1,11 +1,14 @@
-use crate::app::InputMode;
-use crate::bookcase::BookInfo;
+use crate::app::{InputMode, ReaderWindow, CANCEL_JOB, DISMISS_NOTIFICATION, OPEN_READER_WINDOW};
+use crate::bookcase::{BookInfo, DuplicateChoice};
+use crate::errors::{Notification, Severity};
+use crate::jobs::Job;
 use crate::ocr::OcrData;
-use crate::utilities::{open_epub, open_image, save_file, th_load_book};
+use crate::utilities::{open_cover, open_epub, save_file, th_load_book};
 use crate::widgets::custom_tooltip::TipExt;
+use crate::wizard::WizardStep;
 use crate::{ApplicationState, Book};
 use druid::im::Vector;
-use druid::widget::{Align, Button, Click, ControllerHost, Svg, SvgData, ViewSwitcher};
+use druid::widget::{Align, Button, Click, ControllerHost, Flex, Svg, SvgData, ViewSwitcher};
 use druid::{Env, Widget, WidgetExt};
 
 //use crate::controllers::ClickableOpacity;
@@ -51,6 +54,42 @@ impl Buttons {
             )
     }
 
+    pub fn btn_reader_next() -> impl Widget<ReaderWindow> {
+        let right_svg = match include_str!("../../icons/right.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(right_svg.clone())
+            .fix_width(LIBRARY_SVG_BIG)
+            .center()
+            .on_click(|ctx, data: &mut ReaderWindow, _env| {
+                data.book.go_on(1);
+                data.update_view(ctx.get_external_handle())
+            })
+            .tooltip(
+                |_data: &ReaderWindow, _env: &Env| "Next Page".to_string(),
+                false,
+            )
+    }
+
+    pub fn btn_reader_prev() -> impl Widget<ReaderWindow> {
+        let left_svg = match include_str!("../../icons/left.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(left_svg.clone())
+            .fix_width(LIBRARY_SVG_BIG)
+            .center()
+            .on_click(|ctx, data: &mut ReaderWindow, _env| {
+                data.book.go_back(1);
+                data.update_view(ctx.get_external_handle())
+            })
+            .tooltip(
+                |_data: &ReaderWindow, _env: &Env| "Prev Page".to_string(),
+                false,
+            )
+    }
+
     pub fn btn_confirm() -> impl Widget<ApplicationState> {
         let confirm_svg = match include_str!("../../icons/confirm.svg").parse::<SvgData>() {
             Ok(svg) => svg,
@@ -161,15 +200,15 @@ impl Buttons {
                             .center()
                             .on_click(move |_ctx, data: &mut ApplicationState, _env| {
                                 let book_info = data.bookcase.library[book_info_id].clone();
-                                data.set_book_to_align(
-                                    Book::new(
-                                        book_info.get_path(),
-                                        book_info.start_chapter,
-                                        book_info.start_element_number,
-                                        &book_info.mapped_pages,
-                                    )
-                                    .unwrap(),
-                                );
+                                match Book::new(
+                                    book_info.get_path(),
+                                    book_info.start_chapter,
+                                    book_info.start_element_number,
+                                    &book_info.mapped_pages,
+                                ) {
+                                    Ok(book) => data.set_book_to_align(book),
+                                    Err(e) => data.report(&e),
+                                }
                             })
                             .tooltip(
                                 |_data: &ApplicationState, _env: &Env| {
@@ -186,7 +225,7 @@ impl Buttons {
                                 let mut book_info = &mut data.bookcase.library[book_info_id];
                                 book_info.ocr = OcrData::new();
                                 book_info.mapped_pages = Vector::new();
-                                data.bookcase.update_meta();
+                                data.save_library();
                             })
                             .tooltip(
                                 |_data: &ApplicationState, _env: &Env| {
@@ -219,7 +258,13 @@ impl Buttons {
             )
     }
 
-    pub fn btn_close_error() -> ControllerHost<Align<ApplicationState>, Click<ApplicationState>> {
+    pub fn btn_cancel_job() -> ControllerHost<Button<Job>, Click<Job>> {
+        Button::new("CANCEL").on_click(|ctx, job: &mut Job, _env| {
+            ctx.submit_command(CANCEL_JOB.with(job.id));
+        })
+    }
+
+    pub fn btn_dismiss_notification() -> ControllerHost<Align<Notification>, Click<Notification>> {
         let close_svg = match include_str!("../../icons/close_error.svg").parse::<SvgData>() {
             Ok(svg) => svg,
             Err(_) => SvgData::default(),
@@ -227,9 +272,8 @@ impl Buttons {
         Svg::new(close_svg.clone())
             .fix_width(20.)
             .center()
-            .on_click(|_ctx, data: &mut ApplicationState, _env| {
-                /* EDIT MODE -> EDIT MODE, Discard Changes */
-                data.error_message = None;
+            .on_click(|ctx, notification: &mut Notification, _env| {
+                ctx.submit_command(DISMISS_NOTIFICATION.with(notification.id));
             })
     }
 
@@ -295,18 +339,20 @@ impl Buttons {
             .center()
             .on_click(move |ctx, data: &mut ApplicationState, _env| {
                 /* Tries to load image and find matching line in chapter */
-                data.i_mode = InputMode::OcrJump;
-                data.is_loading = true;
-                data.set_book_to_read(
-                    Book::new(
-                        book_info.get_path(),
-                        book_info.start_chapter,
-                        book_info.start_element_number,
-                        &book_info.mapped_pages,
-                    )
-                    .unwrap(),
-                );
-                ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(open_image()));
+                match Book::new(
+                    book_info.get_path(),
+                    book_info.start_chapter,
+                    book_info.start_element_number,
+                    &book_info.mapped_pages,
+                ) {
+                    Ok(book) => {
+                        data.is_loading = true;
+                        data.jump_opened_book = true;
+                        data.set_book_to_read(book);
+                        data.request_ocr_images(ctx, InputMode::OcrJump);
+                    }
+                    Err(e) => data.report(&e),
+                }
             })
             .tooltip(
                 |_data: &ApplicationState, _env: &Env| "Jump to photo".to_string(),
@@ -343,7 +389,7 @@ impl Buttons {
             .center()
             .on_click(move |_, data: &mut ApplicationState, _| {
                 let _ = data.bookcase.library.remove(index);
-                data.bookcase.update_meta();
+                data.save_library();
             })
             .tooltip(
                 |_data: &ApplicationState, _env: &Env| "Remove book from library".to_string(),
@@ -351,6 +397,93 @@ impl Buttons {
             )
     }
 
+    pub fn btn_edit_metadata(index: usize) -> impl Widget<ApplicationState> {
+        let edit_svg = match include_str!("../../icons/edit.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(edit_svg.clone())
+            .fix_width(LIBRARY_SVG_DIM)
+            .center()
+            .on_click(move |_, data: &mut ApplicationState, _| {
+                data.edit_metadata(index);
+            })
+            .tooltip(
+                |_data: &ApplicationState, _env: &Env| "Edit book metadata".to_string(),
+                false,
+            )
+    }
+
+    /* Correzione a mano delle pagine dei capitoli, solo per i libri allineati */
+    pub fn btn_edit_pages(book_info_id: usize) -> ViewSwitcher<ApplicationState, bool> {
+        let pages_svg = match include_str!("../../icons/pages.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        ViewSwitcher::new(
+            move |data: &ApplicationState, _| {
+                data.bookcase.library[book_info_id].mapped_pages.is_empty()
+            },
+            move |cond, _data: &ApplicationState, _| -> Box<dyn Widget<ApplicationState>> {
+                match cond {
+                    true => Box::new(Flex::row()),
+                    false => Box::new(
+                        Svg::new(pages_svg.clone())
+                            .fix_width(LIBRARY_SVG_DIM)
+                            .center()
+                            .on_click(move |_ctx, data: &mut ApplicationState, _env| {
+                                data.edit_page_mapping(book_info_id);
+                            })
+                            .tooltip(
+                                |_data: &ApplicationState, _env: &Env| {
+                                    "Correct the paper pages".to_string()
+                                },
+                                false,
+                            ),
+                    ),
+                }
+            },
+        )
+    }
+
+    pub fn btn_open_window(book_info: BookInfo) -> impl Widget<ApplicationState> {
+        let window_svg = match include_str!("../../icons/window.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(window_svg.clone())
+            .fix_width(LIBRARY_SVG_DIM)
+            .center()
+            .on_click(move |ctx, _data: &mut ApplicationState, _env| {
+                ctx.submit_command(OPEN_READER_WINDOW.with(book_info.clone()));
+            })
+            .tooltip(
+                |_data: &ApplicationState, _env: &Env| "Read in a new window".to_string(),
+                false,
+            )
+    }
+
+    pub fn btn_open_current_window() -> impl Widget<ApplicationState> {
+        let window_svg = match include_str!("../../icons/window.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(window_svg.clone())
+            .fix_width(LIBRARY_SVG_DIM)
+            .center()
+            .on_click(|ctx, data: &mut ApplicationState, _env| {
+                /* La nuova finestra parte dal capitolo che si sta leggendo */
+                let mut book_info = data.get_current_book_info();
+                book_info.start_chapter = data.book_to_view.get_nav().get_ch();
+                book_info.start_element_number = data.book_to_view.get_nav().get_element_numer();
+                ctx.submit_command(OPEN_READER_WINDOW.with(book_info));
+            })
+            .tooltip(
+                |_data: &ApplicationState, _env: &Env| "Open in a new window".to_string(),
+                false,
+            )
+    }
+
     pub fn btn_add_book() -> impl Widget<ApplicationState> {
         let add_svg = match include_str!("../../icons/add.svg").parse::<SvgData>() {
             Ok(svg) => svg,
@@ -370,6 +503,33 @@ impl Buttons {
             )
     }
 
+    pub fn btn_settings() -> impl Widget<ApplicationState> {
+        let settings_svg = match include_str!("../../icons/settings.svg").parse::<SvgData>() {
+            Ok(svg) => svg,
+            Err(_) => SvgData::default(),
+        };
+        Svg::new(settings_svg)
+            .fix_width(LIBRARY_SVG_DIM)
+            .center()
+            .on_click(|_ctx, data: &mut ApplicationState, _| {
+                data.open_settings();
+            })
+            .tooltip(
+                |_data: &ApplicationState, _env: &Env| "Settings".to_string(),
+                false,
+            )
+    }
+
+    pub fn btn_close_settings() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
+    {
+        Button::new("DONE").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            if let Err(e) = data.settings.save() {
+                data.notify(Severity::Error, &e);
+            }
+            data.settings_open = false;
+        })
+    }
+
     pub fn btn_read_book(book_info: BookInfo) -> impl Widget<ApplicationState> {
         let book_svg = match include_str!("../../icons/read.svg").parse::<SvgData>() {
             Ok(svg) => svg,
@@ -381,6 +541,7 @@ impl Buttons {
             .on_click(move |ctx, data: &mut ApplicationState, _env| {
                 data.is_loading = true;
                 th_load_book(
+                    &mut data.jobs,
                     ctx.get_external_handle(),
                     book_info.get_path(),
                     book_info.start_chapter,
@@ -394,103 +555,191 @@ impl Buttons {
             )
     }
 
-    pub fn btn_submit_ocr_form0(
+    pub fn btn_wizard_next(
+        label: &str,
     ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new(label).on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.wizard.next();
+        })
+    }
+
+    pub fn btn_wizard_back() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("GO BACK").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.wizard_back();
+        })
+    }
+
+    pub fn btn_wizard_confirm() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
+    {
         Button::new("CONFIRM").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            let book_info = data.get_mut_current_book_info().unwrap();
-            if (*book_info).ocr.first.is_none() {
-                data.error_message = Option::Some("You have to fill all felds.".to_string());
-            } else {
-                let first = &(*book_info).ocr.mappings[(*book_info).ocr.first.unwrap()];
-                if (*first).page_lines != 0 && (*first).page != 0 {
-                    data.view.ocr_form_stage += 1;
-                } else {
-                    data.error_message = Option::Some("You have to fill all felds.".to_string());
-                }
-            }
+            data.wizard_confirm();
+        })
+    }
+
+    pub fn btn_wizard_close() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("LIBRARY").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.close_wizard();
         })
     }
 
-    pub fn btn_submit_ocr_form1(
+    pub fn btn_wizard_load_page(
     ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
-        Button::new("NEXT").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            let ocr = &data.get_current_book_info().ocr;
-            if (*ocr).other.is_none() {
-                data.error_message = Option::Some("You have to fill all felds.".to_string());
-            } else {
-                let other = &(*ocr).mappings[(*ocr).other.unwrap()];
-                if (*other).page_lines != 0 {
-                    let _ = data.map_pages(true);
-                    data.bookcase.update_meta();
-                    data.view.ocr_form_stage = 6;
-                } else {
-                    data.error_message = Option::Some("You have to fill all felds.".to_string());
-                }
-            }
+        Button::new("LOAD PAGE").on_click(|ctx, data: &mut ApplicationState, _env| {
+            let mode = match data.wizard.step {
+                WizardStep::FirstPage => InputMode::OcrSyn0,
+                _ => InputMode::OcrSyn1,
+            };
+            data.request_ocr_images(ctx, mode);
         })
     }
 
-    pub fn btn_ocr_form_close(
-        reset_ocr: bool,
+    pub fn btn_wizard_add_pages(
     ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
-        Button::new("LIBRARY").on_click(move |_ctx, data: &mut ApplicationState, _env| {
-            data.view.ocr_form_stage = 1;
-            if reset_ocr {
-                (*data.get_mut_current_book_info().unwrap()).ocr = OcrData::new();
-            }
-            data.book_to_align = Book::empty_book();
+        Button::new("ADD PAGES").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.request_ocr_images(ctx, InputMode::OcrSyn2);
         })
     }
 
-    pub fn btn_ocr_form_next() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
+    pub fn btn_save_metadata() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
     {
-        Button::new("NEXT").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            data.view.ocr_form_stage += 1;
+        Button::new("SAVE").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            if let Err(e) = data.save_metadata() {
+                let message = format!("Impossible to save metadata: {}", e);
+                data.notify(Severity::Error, &message);
+            }
         })
     }
 
-    pub fn btn_ocr_form_prev() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
-    {
-        Button::new("GO BACK").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            data.view.ocr_form_stage -= 1;
+    pub fn btn_discard_metadata(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("LIBRARY").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.discard_metadata();
         })
     }
 
-    pub fn btn_add_first_page() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
-    {
-        Button::new("LOAD PAGE").on_click(|ctx, data: &mut ApplicationState, _env| {
-            data.i_mode = InputMode::OcrSyn0;
-            ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(open_image()));
+    pub fn btn_change_cover() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("CHANGE COVER").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.i_mode = InputMode::CoverAdd;
             data.is_loading = true;
+            ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(open_cover()));
         })
     }
 
-    pub fn btn_add_other_page() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
+    pub fn btn_resolve_duplicate(
+        choice: Option<DuplicateChoice>,
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        let label = match choice {
+            Some(DuplicateChoice::Merge) => "MERGE",
+            Some(DuplicateChoice::Replace) => "REPLACE",
+            Some(DuplicateChoice::KeepBoth) => "KEEP BOTH",
+            None => "CANCEL",
+        };
+        Button::new(label).on_click(move |_ctx, data: &mut ApplicationState, _env| {
+            data.resolve_duplicate(choice);
+        })
+    }
+
+    pub fn btn_choose_ocr_match(
+        choice: Option<usize>,
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        let label = match choice {
+            Some(_) => "GO",
+            None => "CANCEL",
+        };
+        Button::new(label).on_click(move |ctx, data: &mut ApplicationState, _env| {
+            data.choose_ocr_match(choice, ctx.get_external_handle());
+        })
+    }
+
+    pub fn btn_page_jump() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("GO TO PAGE").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.open_page_jump();
+        })
+    }
+
+    pub fn btn_confirm_page_jump(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("GO").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.confirm_page_jump(ctx.get_external_handle());
+        })
+    }
+
+    pub fn btn_page_anchor() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("SET PAGE").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.open_page_anchor();
+        })
+    }
+
+    pub fn btn_confirm_page_anchor(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("SET").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.confirm_page_anchor(ctx.get_external_handle());
+        })
+    }
+
+    pub fn btn_close_page_anchor(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("CANCEL").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.page_anchor = None;
+        })
+    }
+
+    pub fn btn_save_page_mapping(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("SAVE").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.save_page_mapping(ctx.get_external_handle());
+        })
+    }
+
+    pub fn btn_discard_page_mapping(
+    ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("LIBRARY").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.discard_page_mapping();
+        })
+    }
+
+    pub fn btn_close_page_jump() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
     {
-        Button::new("LOAD PAGE").on_click(|ctx, data: &mut ApplicationState, _env| {
-            data.i_mode = InputMode::OcrSyn1;
-            ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(open_image()));
-            data.is_loading = true;
+        Button::new("CANCEL").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.page_jump = None;
         })
     }
 
-    pub fn btn_remove_first_page(
+    pub fn btn_editions() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::dynamic(|data: &ApplicationState, _env| {
+            format!("EDITION: {}", data.get_current_book_info().edition)
+        })
+        .on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.open_editions();
+        })
+    }
+
+    pub fn btn_select_edition(
+        name: String,
     ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
-        Button::new("GO BACK").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            let ocr = &mut data.get_mut_current_book_info().unwrap().ocr;
-            ocr.mappings.remove((*ocr).first.unwrap());
-            ocr.first = None;
-            data.view.ocr_form_stage = 2;
+        Button::new("SHOW").on_click(move |ctx, data: &mut ApplicationState, _env| {
+            data.select_edition(&name, ctx.get_external_handle());
         })
     }
 
-    pub fn btn_remove_other_page(
+    pub fn btn_remove_edition(
+        name: String,
     ) -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
-        Button::new("REMOVE").on_click(|_ctx, data: &mut ApplicationState, _env| {
-            let ocr = &mut data.get_mut_current_book_info().unwrap().ocr;
-            ocr.mappings.remove((*ocr).other.unwrap());
-            ocr.other = None;
-            data.view.ocr_form_stage = 4;
+        Button::new("DELETE").on_click(move |_ctx, data: &mut ApplicationState, _env| {
+            data.remove_edition(&name);
+        })
+    }
+
+    pub fn btn_add_edition() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>> {
+        Button::new("ADD").on_click(|ctx, data: &mut ApplicationState, _env| {
+            data.add_edition(ctx.get_external_handle());
+        })
+    }
+
+    pub fn btn_close_editions() -> ControllerHost<Button<ApplicationState>, Click<ApplicationState>>
+    {
+        Button::new("CLOSE").on_click(|_ctx, data: &mut ApplicationState, _env| {
+            data.editions_open = false;
         })
     }
 }
@@ -1,56 +1,67 @@
+use crate::algorithms::MatchStrategy;
 use crate::book::page_element::PageElement;
 use crate::book::{chapter::Chapter, Book};
-use crate::bookcase::{BookCase, BookInfo};
+use crate::bookcase::{BookCase, BookInfo, DuplicateChoice};
 use crate::controllers::Update;
-use crate::formatters::CustomFormatter;
-use crate::ocr::{Mapping, OcrData};
+use crate::errors::{Notification, Notifications, Severity};
+use crate::image_source::ImageSource;
+use crate::jobs::{Job, JobManager};
+use crate::layout::LayoutSettings;
+use crate::ocr::OcrData;
+use crate::ocr_backend::OcrEngine;
+use crate::settings::{PageLayout, Settings};
 use crate::view::buttons::Buttons;
 use crate::view::view::View;
 use crate::widgets::custom_img::BetterImage;
 use crate::widgets::custom_label::BetterLabel;
-use crate::widgets::custom_scrolls::{BetterScroll, SyncScroll};
+use crate::widgets::custom_scrolls::{BetterScroll, ReaderScroll, SyncScroll};
 use crate::widgets::custom_tooltip::TipExt;
+use crate::wizard::{OcrWizard, WizardPage, WizardStep, WIZARD_STEPS};
+use crate::app::{ReaderLens, ReaderWindow};
 use crate::{ApplicationState, ContentType};
 use druid::widget::{
-    Container, ControllerHost, CrossAxisAlignment, Flex, FlexParams, Image, Label, LineBreaking,
-    List, Padding, Painter, RawLabel, Scroll, Spinner, TextBox, ViewSwitcher,
+    Checkbox, ControllerHost, CrossAxisAlignment, FillStrat, Flex, FlexParams, Image, Label,
+    LineBreaking, List, Padding, Painter, RadioGroup, RawLabel, Scroll, Slider, Spinner, Svg,
+    SvgData, TextBox, ViewSwitcher,
 };
-use druid::{lens, Color, Env, LensExt, RenderContext, Widget, WidgetExt};
+use druid::{
+    im::Vector, lens, Color, Env, ImageBuf, Lens, LensExt, RenderContext, Widget, WidgetExt,
+};
+use std::fs;
 
 //SWITCH TRA VISUALIZZATORE ELENCO EBOOK E VISUALIZZATORE EBOOK
 pub fn build_main_view() -> impl Widget<ApplicationState> {
     Flex::column()
-        .with_child(ViewSwitcher::new(
-            |data: &ApplicationState, _| data.error_message.clone(), /* Ad ora non funziona... lo fixo */
-            |msg, _data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
-                let mut error_row = Flex::row().must_fill_main_axis(true);
-                match msg {
-                    None => {}
-                    Some(msg) => {
-                        error_row.add_child(Padding::new(10.0, Label::new(msg.to_string()).with_text_color(Color::rgb(0.9, 0.05, 0.05)).with_text_size(14.)));
-                        error_row.add_flex_spacer(1.0);
-                        error_row.add_child(Padding::new(10.0, Buttons::btn_close_error()));
-                    }
-                }
-                Box::new(error_row.background(Color::rgb(0.247, 0.194, 0.182)))
-            },
-        )
-        )
+        .with_child(render_notifications())
         .with_flex_child(ViewSwitcher::new(
-            |data: &ApplicationState, _| data.is_loading, /* Ad ora non funziona... lo fixo */
+            |data: &ApplicationState, _| (data.is_loading, data.ocr_choices.is_empty(), data.settings_open), /* Ad ora non funziona... lo fixo */
             |_load, data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
-                if !data.is_loading {
+                if !data.ocr_choices.is_empty() {
+                    /* La pagina fotografata somiglia a più punti del libro */
+                    Box::new(render_ocr_choices())
+                } else if data.settings_open {
+                    Box::new(render_settings())
+                } else if !data.is_loading {
                     Box::new(
                         ViewSwitcher::new(
                             |data: &ApplicationState, _| data.book_to_align.is_empty(),
                             |cond, _data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
                                 return if *cond {
                                     Box::new(ViewSwitcher::new(
-                                        |data: &ApplicationState, _| data.book_to_view.is_empty(), /* Condizione della useEffect (?) */
+                                        |data: &ApplicationState, _| (data.book_to_view.is_empty(), data.meta_edit, data.pages_edit, data.duplicate.is_some()), /* Condizione della useEffect (?) */
                                         |_, data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
-                                            if data.book_to_view.is_empty() {
-                                                /* Renderizziamo la libreria di libri disponibili */
-                                                Box::new(render_library())
+                                            if data.duplicate.is_some() {
+                                                /* Il libro importato è già presente in libreria */
+                                                Box::new(render_duplicate_dialog())
+                                            } else if data.book_to_view.is_empty() {
+                                                match (data.meta_edit, data.pages_edit) {
+                                                    /* Renderizziamo il form dei metadati del libro scelto */
+                                                    (Some(id), _) => Box::new(render_metadata_form(id)),
+                                                    /* Renderizziamo le pagine dei capitoli da correggere */
+                                                    (None, Some(id)) => Box::new(render_page_mapping(id, data.pages_input.len())),
+                                                    /* Renderizziamo la libreria di libri disponibili */
+                                                    (None, None) => Box::new(render_library()),
+                                                }
                                                 //Box::new(render_book())
                                             } else {
                                                 /* Renderizziamo il libro scelto */
@@ -59,7 +70,7 @@ pub fn build_main_view() -> impl Widget<ApplicationState> {
                                         },
                                     ))
                                 } else {
-                                    Box::new(render_ocr_syn())
+                                    Box::new(render_ocr_wizard())
                                 }
                             }))
                 } else {
@@ -67,15 +78,94 @@ pub fn build_main_view() -> impl Widget<ApplicationState> {
                 }
             },
         ), 1.)
+        .with_child(render_jobs())
+}
+
+/* Lavori in background, con l'avanzamento e la possibilità di annullarli */
+fn render_jobs() -> impl Widget<ApplicationState> {
+    List::new(|| {
+        Flex::row()
+            .with_child(Spinner::new().fix_size(16., 16.))
+            .with_spacer(10.)
+            .with_flex_child(
+                Label::new(|job: &Job, _env: &Env| job_status(job))
+                    .with_text_color(Color::grey(0.9)),
+                1.,
+            )
+            .with_child(ViewSwitcher::new(
+                |job: &Job, _| job.cancelled,
+                |cancelled, _job: &Job, _env| -> Box<dyn Widget<Job>> {
+                    if *cancelled {
+                        Box::new(Flex::row())
+                    } else {
+                        Box::new(Buttons::btn_cancel_job())
+                    }
+                },
+            ))
+            .padding((10., 5.))
+    })
+    .lens(lens!(ApplicationState, jobs).then(lens!(JobManager, jobs)))
+}
+
+/* Notifiche in cima alla finestra, il colore del testo dipende dalla gravità */
+fn render_notifications() -> impl Widget<ApplicationState> {
+    List::new(|| {
+        Flex::row()
+            .must_fill_main_axis(true)
+            .with_flex_child(
+                Padding::new(
+                    10.0,
+                    Label::new(|n: &Notification, _env: &Env| n.message.clone())
+                        .with_line_break_mode(LineBreaking::WordWrap)
+                        .with_text_size(14.)
+                        .env_scope(|env, n: &Notification| {
+                            env.set(druid::theme::TEXT_COLOR, severity_color(n.severity))
+                        }),
+                ),
+                1.,
+            )
+            .with_child(Padding::new(10.0, Buttons::btn_dismiss_notification()))
+            .background(Color::rgb(0.247, 0.194, 0.182))
+    })
+    .lens(lens!(ApplicationState, notifications).then(lens!(Notifications, items)))
+}
+
+fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Info => Color::grey(0.9),
+        Severity::Warning => Color::rgb(0.95, 0.75, 0.2),
+        Severity::Error => Color::rgb(0.9, 0.05, 0.05),
+    }
+}
+
+fn job_status(job: &Job) -> String {
+    match (job.cancelled, job.progress) {
+        (true, _) => format!("{} - cancelling...", job.title),
+        (false, Some(progress)) => format!("{} - {:.0}%", job.title, progress * 100.),
+        (false, None) => job.title.clone(),
+    }
 }
 
 //FUNZIONE CHE CREA I BOTTONI E FA VISUALIZZARE TESTO E IMMAGINI
 fn render_book() -> impl Widget<ApplicationState> {
     /* Switcha la modalità dell'app */
     ViewSwitcher::new(
-        |data: &ApplicationState, _| data.edit,
+        |data: &ApplicationState, _| {
+            (
+                data.edit,
+                data.page_jump.is_some(),
+                data.page_anchor.is_some(),
+                data.editions_open,
+            )
+        },
         move |_, data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
-            if data.edit {
+            if let Some(range) = data.page_jump {
+                Box::new(render_page_jump(range))
+            } else if data.page_anchor.is_some() {
+                Box::new(render_page_anchor())
+            } else if data.editions_open {
+                Box::new(render_editions())
+            } else if data.edit {
                 let mut window = Flex::column();
                 let buttons = ViewSwitcher::new(
                     |data: &ApplicationState, _| {
@@ -113,6 +203,7 @@ fn render_book() -> impl Widget<ApplicationState> {
                     .with_flex_child(Buttons::btn_edit(), 0.1)
                     .with_flex_child(Buttons::btn_save(), 0.1)
                     .with_flex_child(Buttons::btn_close_book(), 0.1)
+                    .with_flex_child(Buttons::btn_open_current_window(), 0.1)
                     .with_flex_spacer(0.3)
                     .with_flex_child(Buttons::btn_next(), 0.1);
                 let screen = BetterScroll::new(render_view_mode());
@@ -121,6 +212,7 @@ fn render_book() -> impl Widget<ApplicationState> {
                 window.add_flex_child(buttons, FlexParams::new(0.07, CrossAxisAlignment::Center));
                 window.add_child(Flex::row().fix_height(7.0));
                 window.add_flex_child(Padding::new((0.0, 0.0, 0.0, 7.0), screen), 0.9);
+                window.add_child(render_status_bar());
                 Box::new(window)
             }
         },
@@ -166,65 +258,321 @@ fn render_view_mode() -> impl Widget<ApplicationState> {
 
             let lens = lens!(ApplicationState, view).then(lens!(View, current_view));
 
-            let chapter = List::new(|| {
-                ViewSwitcher::new(
-                    |data: &PageElement, _| data.content.clone(),
-                    |ele, data: &PageElement, _| -> Box<dyn Widget<PageElement>> {
-                        match &ele {
-                            ContentType::Text(_) => {
-                                return if data.pg_offset.0 != 0 {
-                                    Box::new(BetterLabel::new().tooltip(
-                                        |data: &PageElement, _env: &Env| {
-                                            let mut po = "Page ".to_string();
-                                            if (*data).pg_offset.1 == true {
-                                                po.push_str(&((*data).pg_offset.0 - 1).to_string());
-                                                po.push_str("-");
-                                            };
-                                            po.push_str(&data.pg_offset.0.to_string());
-                                            String::from(po)
-                                        },
-                                        true,
-                                    ))
-                                } else {
-                                    Box::new(BetterLabel::new())
-                                }
-                            }
-                            ContentType::Image(img_buf) => {
-                                if data.pg_offset.0 != 0 {
-                                    Box::new(BetterImage::new(img_buf.clone()).tooltip(
-                                        |data: &PageElement, _env: &Env| {
-                                            let mut str = String::from(
-                                                "Page ".to_owned() + &data.pg_offset.0.to_string(),
-                                            );
-                                            let str2 = String::from(
-                                                "-".to_owned()
-                                                    + &(data.pg_offset.0 - 1).to_string(),
-                                            );
-                                            str.push_str(if data.pg_offset.1 { &str2 } else { "" });
-                                            str
-                                        },
-                                        true,
-                                    ))
-                                } else {
-                                    Box::new(BetterImage::new(img_buf.clone()))
-                                }
-                            }
-                            ContentType::Error(_e) => {
-                                let mut label = RawLabel::new();
-                                label.set_line_break_mode(LineBreaking::WordWrap);
-                                Box::new(label)
-                            }
-                        }
-                    },
+            let chapter = page_element_list().lens(lens);
+            viewport.add_child(chapter);
+            Box::new(Padding::new((30.0, 0.0, 30.0, 0.0), viewport))
+        },
+    )
+}
+
+fn render_page_jump((first, last): (usize, usize)) -> impl Widget<ApplicationState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Go to printed page").with_text_size(25.))
+        .with_spacer(10.)
+        .with_child(
+            Label::new(format!(
+                "Type a page number of the paper book, from {} to {}.",
+                first, last
+            ))
+            .with_text_size(18.)
+            .with_text_color(Color::grey(0.9))
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("Paper page")
+                .lens(lens!(ApplicationState, page_input))
+                .fix_width(150.),
+        )
+        .with_spacer(5.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| {
+                data.page_jump_error.clone().unwrap_or_default()
+            })
+            .with_text_color(Color::rgb(0.9, 0.05, 0.05))
+            .with_text_size(14.),
+        )
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .with_child(Buttons::btn_close_page_jump())
+                .with_spacer(5.)
+                .with_child(Buttons::btn_confirm_page_jump()),
+        )
+        .padding(20.)
+}
+
+fn render_page_anchor() -> impl Widget<ApplicationState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Set printed page").with_text_size(25.))
+        .with_spacer(10.)
+        .with_child(
+            Label::new("Type the page of the paper book where the selected paragraph starts. \
+                        The pages of the following chapters will be corrected accordingly.")
+                .with_text_size(18.)
+                .with_text_color(Color::grey(0.9))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("Paper page")
+                .lens(lens!(ApplicationState, page_input))
+                .fix_width(150.),
+        )
+        .with_spacer(5.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| {
+                data.page_jump_error.clone().unwrap_or_default()
+            })
+            .with_text_color(Color::rgb(0.9, 0.05, 0.05))
+            .with_text_size(14.),
+        )
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .with_child(Buttons::btn_close_page_anchor())
+                .with_spacer(5.)
+                .with_child(Buttons::btn_confirm_page_anchor()),
+        )
+        .padding(20.)
+}
+
+fn edition_label(name: &str, pages: &Vector<usize>) -> String {
+    let state = if pages.is_empty() {
+        "not aligned"
+    } else {
+        "aligned"
+    };
+    format!("{} ({})", name, state)
+}
+
+/* Edizioni cartacee del libro aperto: quale mostrare, aggiungerne una o eliminarla */
+fn render_editions() -> impl Widget<ApplicationState> {
+    let list = ViewSwitcher::new(
+        |data: &ApplicationState, _| data.get_current_book_info(),
+        |book_info, _data: &ApplicationState, _| -> Box<dyn Widget<ApplicationState>> {
+            let mut list = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+            list.add_child(
+                Label::new(format!(
+                    "{} - shown",
+                    edition_label(&book_info.edition, &book_info.mapped_pages)
+                ))
+                .with_text_size(18.),
+            );
+            for edition in book_info.editions.iter() {
+                list.add_spacer(5.);
+                list.add_child(
+                    Flex::row()
+                        .with_child(
+                            Label::new(edition_label(&edition.name, &edition.mapped_pages))
+                                .with_text_size(18.),
+                        )
+                        .with_spacer(10.)
+                        .with_child(Buttons::btn_select_edition(edition.name.clone()))
+                        .with_spacer(5.)
+                        .with_child(Buttons::btn_remove_edition(edition.name.clone())),
+                );
+            }
+            Box::new(list)
+        },
+    );
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Print editions").with_text_size(25.))
+        .with_spacer(10.)
+        .with_child(
+            Label::new(
+                "Every print edition of the book has its own photos and page numbers. \
+                 Choose the one you are reading along with, or add a new one to align.",
+            )
+            .with_text_size(18.)
+            .with_text_color(Color::grey(0.9))
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(list)
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("New edition, e.g. Paperback")
+                        .lens(lens!(ApplicationState, edition_input))
+                        .fix_width(250.),
                 )
+                .with_spacer(5.)
+                .with_child(Buttons::btn_add_edition()),
+        )
+        .with_spacer(5.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| {
+                data.edition_error.clone().unwrap_or_default()
             })
-            .lens(lens);
-            viewport.add_child(chapter);
+            .with_text_color(Color::rgb(0.9, 0.05, 0.05))
+            .with_text_size(14.),
+        )
+        .with_spacer(20.)
+        .with_child(Buttons::btn_close_editions())
+        .padding(20.)
+}
+
+/* Barra di stato del lettore: capitolo, pagine cartacee visibili e salto a una pagina */
+fn render_status_bar() -> impl Widget<ApplicationState> {
+    let status = Label::new(|data: &ApplicationState, _env: &Env| {
+        let mut status = format!(
+            "Chapter {} of {}",
+            data.book_to_view.get_ch() + 1,
+            data.book_to_view.chapters.len()
+        );
+        let (first, last) = data.view.visible;
+        match data.view.page_range(first, last) {
+            Some((start, end)) if start == end => status.push_str(&format!("  |  Paper page {}", start)),
+            Some((start, end)) => status.push_str(&format!("  |  Paper pages {}-{}", start, end)),
+            None if data.get_current_book_info().ocr.has_pages() => {
+                status.push_str("  |  No paper page for this chapter")
+            }
+            None => status.push_str("  |  Not aligned with a paper book"),
+        }
+        if let Some((start, end)) = data
+            .view
+            .selected
+            .and_then(|el| data.view.page_range(el, el))
+        {
+            status.push_str(&format!("  |  Selected paragraph: page {}", start));
+            if start != end {
+                status.push_str(&format!("-{}", end));
+            }
+        }
+        status
+    })
+    .with_text_size(14.)
+    .with_text_color(Color::grey(0.9));
+
+    let jump = ViewSwitcher::new(
+        |data: &ApplicationState, _| {
+            let ocr = data.get_current_book_info().ocr;
+            (
+                ocr.is_aligned(),
+                ocr.has_pages(),
+                data.view.selected.is_some(),
+            )
+        },
+        |key, _data: &ApplicationState, _| -> Box<dyn Widget<ApplicationState>> {
+            let (aligned, pages, selected) = *key;
+            let mut row = Flex::row();
+            if aligned && selected {
+                row.add_child(Buttons::btn_page_anchor());
+                row.add_spacer(5.);
+            }
+            if pages {
+                row.add_child(Buttons::btn_page_jump());
+                row.add_spacer(5.);
+            }
+            row.add_child(Buttons::btn_editions());
+            Box::new(row)
+        },
+    );
+
+    Flex::row()
+        .must_fill_main_axis(true)
+        .with_child(status)
+        .with_flex_spacer(1.)
+        .with_child(jump)
+        .padding((10., 0., 10., 5.))
+}
+
+/* Lista dei PageElement di un capitolo, condivisa tra finestra principale e finestre secondarie */
+fn page_element_list() -> List<PageElement> {
+    List::new(|| {
+        ViewSwitcher::new(
+            |data: &PageElement, _| data.content.clone(),
+            |ele, data: &PageElement, _| -> Box<dyn Widget<PageElement>> {
+                match &ele {
+                    ContentType::Text(_) => {
+                        return if data.pg_offset.0 != 0 {
+                            Box::new(BetterLabel::new().tooltip(
+                                |data: &PageElement, _env: &Env| {
+                                    let mut po = "Page ".to_string();
+                                    if (*data).pg_offset.1 == true {
+                                        po.push_str(&((*data).pg_offset.0 - 1).to_string());
+                                        po.push_str("-");
+                                    };
+                                    po.push_str(&data.pg_offset.0.to_string());
+                                    String::from(po)
+                                },
+                                true,
+                            ))
+                        } else {
+                            Box::new(BetterLabel::new())
+                        }
+                    }
+                    ContentType::Image(img_buf) => {
+                        if data.pg_offset.0 != 0 {
+                            Box::new(BetterImage::new(img_buf.clone()).tooltip(
+                                |data: &PageElement, _env: &Env| {
+                                    let mut str = String::from(
+                                        "Page ".to_owned() + &data.pg_offset.0.to_string(),
+                                    );
+                                    let str2 = String::from(
+                                        "-".to_owned()
+                                            + &(data.pg_offset.0 - 1).to_string(),
+                                    );
+                                    str.push_str(if data.pg_offset.1 { &str2 } else { "" });
+                                    str
+                                },
+                                true,
+                            ))
+                        } else {
+                            Box::new(BetterImage::new(img_buf.clone()))
+                        }
+                    }
+                    ContentType::Error(_e) => {
+                        let mut label = RawLabel::new();
+                        label.set_line_break_mode(LineBreaking::WordWrap);
+                        Box::new(label)
+                    }
+                }
+            },
+        )
+    })
+}
+
+fn render_reader_view() -> impl Widget<ReaderWindow> {
+    ViewSwitcher::new(
+        |data: &ReaderWindow, _| data.view.current_view.clone(),
+        move |_, _data: &ReaderWindow, _| -> Box<dyn Widget<ReaderWindow>> {
+            let mut viewport = Flex::column().cross_axis_alignment(CrossAxisAlignment::Baseline);
+            let lens = lens!(ReaderWindow, view).then(lens!(View, current_view));
+            viewport.add_child(page_element_list().lens(lens));
             Box::new(Padding::new((30.0, 0.0, 30.0, 0.0), viewport))
         },
     )
 }
 
+/* Finestra secondaria: un libro letto in parallelo a quello della finestra principale */
+pub fn build_reader_window(id: usize) -> impl Widget<ApplicationState> {
+    let buttons: Flex<ReaderWindow> = Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_flex_child(Buttons::btn_reader_prev(), 0.1)
+        .with_flex_spacer(0.7)
+        .with_flex_child(Buttons::btn_reader_next(), 0.1);
+
+    Flex::column()
+        .with_child(Flex::row().fix_height(7.0))
+        .with_flex_child(buttons, FlexParams::new(0.07, CrossAxisAlignment::Center))
+        .with_child(Flex::row().fix_height(7.0))
+        .with_flex_child(
+            Padding::new((0.0, 0.0, 0.0, 7.0), ReaderScroll::new(render_reader_view())),
+            0.9,
+        )
+        .lens(ReaderLens(id))
+}
+
 fn render_library() -> impl Widget<ApplicationState> {
     ViewSwitcher::new(
         |data: &ApplicationState, _| data.bookcase.library.clone(),
@@ -241,6 +589,7 @@ fn render_library() -> impl Widget<ApplicationState> {
                                 .padding(30.0),
                         )
                         .with_flex_spacer(0.7)
+                        .with_child(Padding::new((20., 20., 0., 20.), Buttons::btn_settings()))
                         .with_child(Padding::new(20., Buttons::btn_add_book())),
                 );
             for (i, book_info) in data.get_library().clone().into_iter().enumerate() {
@@ -250,11 +599,7 @@ fn render_library() -> impl Widget<ApplicationState> {
 
                 let uno = Flex::column()
                     .cross_axis_alignment(CrossAxisAlignment::Start)
-                    .with_child(
-                        Image::new(book_info.cover_buf.clone())
-                            .fix_width(300.0)
-                            .fix_height(200.0),
-                    );
+                    .with_child(render_cover(&book_info));
 
                 let due = Flex::column()
                     .with_child(
@@ -267,7 +612,13 @@ fn render_library() -> impl Widget<ApplicationState> {
                             .with_spacer(10.0)
                             .with_child(Buttons::btn_ocr(book_info.clone()))
                             .with_spacer(10.0)
-                            .with_child(Buttons::btn_ocr_syn(i)), //HERE
+                            .with_child(Buttons::btn_ocr_syn(i)) //HERE
+                            .with_spacer(10.0)
+                            .with_child(Buttons::btn_edit_pages(i))
+                            .with_spacer(10.0)
+                            .with_child(Buttons::btn_edit_metadata(i))
+                            .with_spacer(10.0)
+                            .with_child(Buttons::btn_open_window(book_info.clone())),
                     )
                     .with_spacer(15.0)
                     .cross_axis_alignment(CrossAxisAlignment::Start)
@@ -332,342 +683,719 @@ fn print_card_element(label: &str, value: &str) -> impl Widget<ApplicationState>
     };
 }
 
+fn render_metadata_form(id: usize) -> impl Widget<ApplicationState> {
+    macro_rules! info_lens {
+        ($field: tt) => {
+            lens!(ApplicationState, bookcase)
+                .then(lens!(BookCase, library))
+                .index(id)
+                .then(lens!(BookInfo, $field))
+        };
+    }
 
-fn render_ocr_syn() -> impl Widget<ApplicationState> {
-    ViewSwitcher::new(
-        |data: &ApplicationState, _| data.view.ocr_form_stage,
-        |stage, data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
-            match stage {
-                1 => {
-                    Box::new(
-                        Flex::column()
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(
-                                        Label::new("1").with_text_size(25.))
-                                    .with_child(
-                                        Label::new("2").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("3").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("4").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("5").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("6").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Label::new("Thanks to this function while reading the selected epub you can know the corresponding page of the paper book at any time.\
-                                \nIn order to be able to do this you will need to upload photos of a couple of pages of the paper book and you will have to a verify the correctness of some information from the photos.\
-                                \nPress 'NEXT' to proceed or 'LIBRARY' to return to the home page.")
-                                    .with_text_size(18.)
-                                    .with_text_color(Color::grey(0.9))
-                                    .with_line_break_mode(LineBreaking::WordWrap)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .must_fill_main_axis(true)
-                                    .with_flex_spacer(1.)
-                                    .with_child(Buttons::btn_ocr_form_close(true))
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_ocr_form_next())
-                                    .with_flex_spacer(1.)
-                            )
-                            .padding(20.)
-                    )
-                }
-                2 => {
-                    Box::new(
-                        Flex::column()
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Edit book metadata").with_text_size(25.))
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .cross_axis_alignment(CrossAxisAlignment::End)
+                .with_child(ViewSwitcher::new(
+                    move |data: &ApplicationState, _| data.bookcase.library[id].cover_path.clone(),
+                    move |_, data: &ApplicationState, _| render_cover(&data.bookcase.library[id]),
+                ))
+                .with_spacer(10.)
+                .with_child(
+                    Flex::column()
+                        .cross_axis_alignment(CrossAxisAlignment::Start)
+                        .with_child(Buttons::btn_change_cover())
+                        .with_spacer(5.)
                         .with_child(
-                            Flex::row()
-                                .with_flex_spacer(1.)
-                                .with_child(
-                                    Label::new("1").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                .with_child(
-                                    Label::new("2").with_text_size(25.))
-                                .with_child(
-                                    Label::new("3").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                .with_child(
-                                    Label::new("4").with_text_size(25.).with_text_color(Color::grey(0.5)))
+                            Checkbox::new("Embed custom cover in the epub on save")
+                                .lens(info_lens!(embed_cover)),
+                        ),
+                ),
+        )
+        .with_spacer(20.)
+        .with_child(metadata_row("Title", TextBox::new().lens(info_lens!(title))))
+        .with_spacer(10.)
+        .with_child(metadata_row("By", TextBox::new().lens(info_lens!(creator))))
+        .with_spacer(10.)
+        .with_child(metadata_row("Language", TextBox::new().lens(info_lens!(language))))
+        .with_spacer(10.)
+        .with_child(metadata_row(
+            "Description",
+            TextBox::multiline()
+                .with_line_wrapping(true)
+                .fix_height(150.)
+                .lens(info_lens!(description)),
+        ))
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .must_fill_main_axis(true)
+                .with_flex_spacer(1.)
+                .with_child(Buttons::btn_discard_metadata())
+                .with_spacer(5.)
+                .with_child(Buttons::btn_save_metadata())
+                .with_flex_spacer(1.),
+        )
+        .padding(20.)
+}
+
+/* Riga con uno slider e il suo valore, per le impostazioni di impaginazione del libro id */
+fn layout_slider(
+    id: usize,
+    label: &str,
+    max: f64,
+    field: impl Lens<LayoutSettings, f64> + Clone + 'static,
+) -> impl Widget<ApplicationState> {
+    let lens = lens!(ApplicationState, bookcase)
+        .then(lens!(BookCase, library))
+        .index(id)
+        .then(lens!(BookInfo, ocr))
+        .then(lens!(OcrData, layout))
+        .then(field);
+    metadata_row(
+        label,
+        Flex::row()
+            .with_flex_child(
+                Slider::new()
+                    .with_range(0., max)
+                    .lens(lens.clone())
+                    .expand_width(),
+                1.,
+            )
+            .with_spacer(10.)
+            .with_child(Label::new(move |data: &ApplicationState, _env: &Env| {
+                format!("{:.1}", lens.get(data))
+            })),
+    )
+}
+
+/* Impaginazione del libro cartaceo usata per stimare le pagine */
+fn render_layout_settings(id: usize) -> impl Widget<ApplicationState> {
+    let layout_lens = lens!(ApplicationState, bookcase)
+        .then(lens!(BookCase, library))
+        .index(id)
+        .then(lens!(BookInfo, ocr))
+        .then(lens!(OcrData, layout));
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Layout").with_text_size(20.))
+        .with_spacer(10.)
+        .with_child(layout_slider(
+            id,
+            "Space between paragraphs (lines)",
+            2.,
+            lens!(LayoutSettings, paragraph_spacing),
+        ))
+        .with_spacer(5.)
+        .with_child(layout_slider(
+            id,
+            "Space around headings (lines)",
+            4.,
+            lens!(LayoutSettings, heading_spacing),
+        ))
+        .with_spacer(5.)
+        .with_child(layout_slider(
+            id,
+            "Heading size (lines)",
+            4.,
+            lens!(LayoutSettings, heading_scale),
+        ))
+        .with_spacer(5.)
+        .with_child(layout_slider(
+            id,
+            "Space at chapter start (lines)",
+            20.,
+            lens!(LayoutSettings, chapter_start),
+        ))
+        .with_spacer(5.)
+        .with_child(
+            Checkbox::new("Measure heading size and chapter start from the photos")
+                .lens(layout_lens.clone().then(lens!(LayoutSettings, calibrate))),
+        )
+        .with_spacer(5.)
+        .with_child(
+            Checkbox::new("Avoid single lines of a paragraph at the top or bottom of a page")
+                .lens(layout_lens.then(lens!(LayoutSettings, widow_control))),
+        )
+}
+
+/* Prima pagina cartacea di ogni capitolo, correggibile a mano */
+fn render_page_mapping(id: usize, chapters_len: usize) -> impl Widget<ApplicationState> {
+    let mut chapters = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+    for ch in 0..chapters_len {
+        chapters.add_child(metadata_row(
+            &format!("Chapter {}", ch + 1),
+            TextBox::new()
+                .with_placeholder("No page")
+                .lens(lens!(ApplicationState, pages_input).index(ch)),
+        ));
+        chapters.add_spacer(5.);
+    }
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(move |data: &ApplicationState, _env: &Env| {
+                format!("Paper pages - {}", data.bookcase.library[id].edition)
+            })
+            .with_text_size(25.),
+        )
+        .with_spacer(10.)
+        .with_child(
+            Label::new("The page of the paper book where each chapter starts. \
+                        Corrected pages are kept when the alignment is refined, \
+                        a different layout recomputes the other pages on save.")
+                .with_text_size(18.)
+                .with_text_color(Color::grey(0.9))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(render_layout_settings(id))
+        .with_spacer(20.)
+        .with_child(chapters)
+        .with_spacer(5.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| {
+                data.pages_edit_error.clone().unwrap_or_default()
+            })
+            .with_text_color(Color::rgb(0.9, 0.05, 0.05))
+            .with_text_size(14.),
+        )
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .must_fill_main_axis(true)
+                .with_flex_spacer(1.)
+                .with_child(Buttons::btn_discard_page_mapping())
+                .with_spacer(5.)
+                .with_child(Buttons::btn_save_page_mapping())
+                .with_flex_spacer(1.),
+        )
+        .padding(20.)
+        .scroll()
+        .vertical()
+}
+
+fn render_duplicate_dialog() -> impl Widget<ApplicationState> {
+    Flex::column()
+        .with_child(Label::new("This book is already in your library").with_text_size(25.))
+        .with_spacer(20.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| match &data.duplicate {
+                Some(duplicate) => duplicate.describe(&data.bookcase.library),
+                None => String::new(),
+            })
+            .with_text_size(18.)
+            .with_text_color(Color::grey(0.9))
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(10.)
+        .with_child(
+            Label::new("Press 'MERGE' to keep the current entry and remember the new file as another copy, \
+                        'REPLACE' to read the new file keeping your position and alignment, \
+                        'KEEP BOTH' to add it as a separate book or 'CANCEL' to abort the import.")
+                .with_text_size(18.)
+                .with_text_color(Color::grey(0.9))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .must_fill_main_axis(true)
+                .with_flex_spacer(1.)
+                .with_child(Buttons::btn_resolve_duplicate(None))
+                .with_spacer(5.)
+                .with_child(Buttons::btn_resolve_duplicate(Some(DuplicateChoice::Merge)))
+                .with_spacer(5.)
+                .with_child(Buttons::btn_resolve_duplicate(Some(DuplicateChoice::Replace)))
+                .with_spacer(5.)
+                .with_child(Buttons::btn_resolve_duplicate(Some(DuplicateChoice::KeepBoth)))
+                .with_flex_spacer(1.),
+        )
+        .padding(20.)
+}
+
+fn render_ocr_choices() -> impl Widget<ApplicationState> {
+    ViewSwitcher::new(
+        |data: &ApplicationState, _| data.ocr_choices.clone(),
+        |choices, _data: &ApplicationState, _| -> Box<dyn Widget<ApplicationState>> {
+            let mut col = Flex::column()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(Label::new("Which passage did you photograph?").with_text_size(25.))
+                .with_spacer(10.)
+                .with_child(
+                    Label::new("The page matches more than one position of the book, choose the right one.")
+                        .with_text_size(18.)
+                        .with_text_color(Color::grey(0.9))
+                        .with_line_break_mode(LineBreaking::WordWrap),
+                )
+                .with_spacer(20.);
+            for (i, choice) in choices.iter().enumerate() {
+                col.add_child(
+                    Flex::row()
+                        .must_fill_main_axis(true)
+                        .cross_axis_alignment(CrossAxisAlignment::Start)
+                        .with_flex_child(
+                            Flex::column()
+                                .cross_axis_alignment(CrossAxisAlignment::Start)
                                 .with_child(
-                                    Label::new("5").with_text_size(25.).with_text_color(Color::grey(0.5)))
+                                    Label::new(format!(
+                                        "Chapter {} - similarity {:.0}%",
+                                        choice.ch,
+                                        choice.score * 100.
+                                    ))
+                                    .with_text_size(18.),
+                                )
+                                .with_spacer(4.)
                                 .with_child(
-                                    Label::new("6").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                .with_flex_spacer(1.)
+                                    Label::new(choice.snippet.clone())
+                                        .with_text_color(Color::grey(0.9))
+                                        .with_line_break_mode(LineBreaking::WordWrap),
+                                ),
+                            1.,
                         )
-                        .with_spacer(20.)
-                        .with_child(
-                            Label::new("Here you need to upload a picture of the first page of the first chapter.\
-                                                \nFrom this page will start the alignment being the first for which there is a match between ebook and paper book.\
-                                                \nPress 'LOAD PAGE' to load the page, 'GO BACK' to return to '2' or 'LIBRARY' to return to the home page.")
-                                .with_text_size(18.)
-                                .with_text_color(Color::grey(0.9))
-                                .with_line_break_mode(LineBreaking::WordWrap)
-                        )
-                        .with_spacer(20.)
+                        .with_spacer(10.)
+                        .with_child(Buttons::btn_choose_ocr_match(Some(i))),
+                );
+                col.add_spacer(15.);
+            }
+            col.add_child(
+                Flex::row()
+                    .must_fill_main_axis(true)
+                    .with_flex_spacer(1.)
+                    .with_child(Buttons::btn_choose_ocr_match(None)),
+            );
+            Box::new(col.padding(20.).scroll().vertical())
+        },
+    )
+}
+
+fn render_settings() -> impl Widget<ApplicationState> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Settings").with_text_size(25.))
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "OCR match threshold",
+            Flex::row()
+                .with_flex_child(
+                    Slider::new()
+                        .with_range(0.1, 1.)
+                        .lens(lens!(ApplicationState, settings).then(lens!(Settings, match_threshold)))
+                        .expand_width(),
+                    1.,
+                )
+                .with_spacer(10.)
+                .with_child(Label::new(|data: &ApplicationState, _env: &Env| {
+                    format!("{:.0}%", data.settings.match_threshold * 100.)
+                })),
+        ))
+        .with_spacer(10.)
+        .with_child(
+            Label::new("Minimum similarity between a photographed page and the book text. \
+                        Lower it for poor quality photos, raise it to avoid wrong jumps.")
+                .with_text_color(Color::grey(0.9))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "OCR comparison",
+            RadioGroup::column(vec![
+                ("Word by word", MatchStrategy::Linear),
+                ("Tolerant to missing or extra words", MatchStrategy::Alignment),
+            ])
+            .lens(lens!(ApplicationState, settings).then(lens!(Settings, match_strategy))),
+        ))
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "Photo preparation",
+            Flex::column()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(
+                    Checkbox::new("Black and white (uneven lighting)").lens(
+                        lens!(ApplicationState, settings).then(lens!(Settings, preprocess_binarize)),
+                    ),
+                )
+                .with_child(
+                    Checkbox::new("Straighten tilted photos").lens(
+                        lens!(ApplicationState, settings).then(lens!(Settings, preprocess_deskew)),
+                    ),
+                )
+                .with_child(
+                    Checkbox::new("Crop to the main text block").lens(
+                        lens!(ApplicationState, settings).then(lens!(Settings, preprocess_crop)),
+                    ),
+                )
+                .with_child(
+                    Checkbox::new("Detect rotated photos (slower)").lens(
+                        lens!(ApplicationState, settings).then(lens!(Settings, preprocess_rotation)),
+                    ),
+                ),
+        ))
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "OCR engine",
+            RadioGroup::column(vec![
+                ("Tesseract", OcrEngine::Tesseract),
+                ("Text file next to the photo", OcrEngine::TextFile),
+                ("External command", OcrEngine::Command),
+            ])
+            .lens(lens!(ApplicationState, settings).then(lens!(Settings, ocr_engine))),
+        ))
+        .with_spacer(10.)
+        .with_child(metadata_row(
+            "OCR command",
+            TextBox::new()
+                .with_placeholder("tesseract {image} - -l {lang}")
+                .lens(lens!(ApplicationState, settings).then(lens!(Settings, ocr_command))),
+        ))
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "OCR languages",
+            TextBox::new()
+                .with_placeholder("Book language, e.g. ita+eng")
+                .lens(lens!(ApplicationState, settings).then(lens!(Settings, ocr_languages))),
+        ))
+        .with_spacer(10.)
+        .with_child(
+            Label::new(|data: &ApplicationState, _env: &Env| {
+                let langs = data
+                    .installed_langs
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<String>>();
+                if langs.is_empty() {
+                    "No Tesseract language data found".to_string()
+                } else {
+                    format!("Installed: {}", langs.join(", "))
+                }
+            })
+            .with_text_color(Color::grey(0.9))
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(10.)
+        .with_child(metadata_row(
+            "Tessdata folder",
+            TextBox::new()
+                .with_placeholder("Tesseract default")
+                .lens(lens!(ApplicationState, settings).then(lens!(Settings, tessdata_path)))
+                .controller(Update::new(|_ctx, data: &mut ApplicationState, _| {
+                    data.refresh_languages(false)
+                })),
+        ))
+        .with_spacer(10.)
+        .with_child(metadata_row(
+            "Page layout",
+            RadioGroup::column(vec![
+                ("Detect columns and blocks", PageLayout::Auto),
+                ("Single block of text", PageLayout::SingleBlock),
+            ])
+            .lens(lens!(ApplicationState, settings).then(lens!(Settings, page_layout))),
+        ))
+        .with_spacer(20.)
+        .with_child(metadata_row(
+            "Photo source",
+            RadioGroup::column(vec![
+                ("Choose a file", ImageSource::Dialog),
+                ("Newest photo in the inbox folder", ImageSource::Inbox),
+                ("Clipboard", ImageSource::Clipboard),
+            ])
+            .lens(lens!(ApplicationState, settings).then(lens!(Settings, image_source))),
+        ))
+        .with_spacer(10.)
+        .with_child(metadata_row(
+            "Inbox folder",
+            TextBox::new()
+                .with_placeholder("Folder where the phone syncs the photos")
+                .lens(lens!(ApplicationState, settings).then(lens!(Settings, inbox_folder))),
+        ))
+        .with_spacer(10.)
+        .with_child(
+            Label::new(
+                "With the inbox folder, a photo arriving while a book is open jumps \
+                straight to its page, and the alignment wizard uses it as the next page.",
+            )
+            .with_text_color(Color::grey(0.9))
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(20.)
+        .with_child(
+            Flex::row()
+                .must_fill_main_axis(true)
+                .with_flex_spacer(1.)
+                .with_child(Buttons::btn_close_settings()),
+        )
+        .padding(20.)
+}
+
+fn render_cover(book_info: &BookInfo) -> Box<dyn Widget<ApplicationState>> {
+    /* Le copertine SVG non sono supportate da ImageBuf */
+    if book_info.cover_path.ends_with(".svg") {
+        if let Some(svg) = fs::read_to_string(&book_info.cover_path)
+            .ok()
+            .and_then(|s| s.parse::<SvgData>().ok())
+        {
+            return Box::new(Svg::new(svg).fix_width(300.0).fix_height(200.0));
+        }
+    }
+    Box::new(
+        Image::new(book_info.cover_buf.clone())
+            .fix_width(300.0)
+            .fix_height(200.0),
+    )
+}
+
+fn metadata_row(
+    label: &str,
+    field: impl Widget<ApplicationState> + 'static,
+) -> impl Widget<ApplicationState> {
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_flex_child(Label::new(String::from(label.to_owned() + ":")).with_text_color(Color::grey(0.9)), 1.)
+        .with_flex_child(field.expand_width(), 4.)
+        .must_fill_main_axis(true)
+}
+
+
+/*
+Wizard di allineamento con il libro cartaceo: la vista viene ricostruita solo quando cambia il
+passo, non mentre l'utente corregge pagina e righe
+*/
+fn render_ocr_wizard() -> impl Widget<ApplicationState> {
+    ViewSwitcher::new(
+        |data: &ApplicationState, _| {
+            let wizard = &data.wizard;
+            (
+                wizard.step,
+                wizard.loading,
+                wizard.error.clone(),
+                wizard.preview.is_some(),
+                wizard.pages.len(),
+            )
+        },
+        |_, data: &ApplicationState, _env| -> Box<dyn Widget<ApplicationState>> {
+            let wizard = &data.wizard;
+            let mut column = Flex::column()
+                .with_child(render_wizard_progress(wizard))
+                .with_spacer(20.)
+                .with_child(
+                    Label::new(wizard_description(wizard.step))
+                        .with_text_size(18.)
+                        .with_text_color(Color::grey(0.9))
+                        .with_line_break_mode(LineBreaking::WordWrap),
+                )
+                .with_spacer(20.);
+
+            if let Some(error) = &wizard.error {
+                column.add_child(
+                    Label::new(error.clone())
+                        .with_text_color(Color::rgb(0.9, 0.45, 0.4))
+                        .with_line_break_mode(LineBreaking::WordWrap),
+                );
+                column.add_spacer(20.);
+            }
+
+            if wizard.loading {
+                /* Tesseract sta leggendo: la foto preparata si vede già */
+                if let Some(preview) = &wizard.preview {
+                    column.add_child(wizard_image(preview, 300.));
+                    column.add_spacer(10.);
+                }
+                column.add_child(
+                    Flex::row()
+                        .with_child(Spinner::new())
+                        .with_spacer(10.)
                         .with_child(
-                            Flex::row()
-                                .must_fill_main_axis(true)
-                                .with_flex_spacer(1.)
-                                .with_child(Buttons::btn_ocr_form_close(true))
-                                .with_spacer(5.)
-                                .with_child(Buttons::btn_ocr_form_prev())
-                                .with_spacer(5.)
-                                .with_child(Buttons::btn_add_first_page())
-                                .with_flex_spacer(1.)
-                        )
-                        .padding(20.))
+                            Label::new("Reading the page...").with_text_color(Color::grey(0.9)),
+                        ),
+                );
+                return Box::new(Scroll::new(column.padding(20.)).vertical());
+            }
+
+            if let Some(page) = wizard.current_page() {
+                column.add_child(render_wizard_check(page, wizard.step));
+                column.add_spacer(20.);
+            }
+
+            let pages = data.get_current_book_info().ocr.mappings.len();
+            match wizard.step {
+                WizardStep::Samples => {
+                    column.add_child(render_wizard_samples(wizard));
+                    column.add_spacer(10.);
+                    column.add_child(
+                        Label::new(format!("Pages used for the alignment: {}", pages))
+                            .with_text_color(Color::grey(0.9)),
+                    );
+                    column.add_spacer(20.);
                 }
-                3 => {
-                    Box::new(
-                        Flex::column()
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(
-                                        Label::new("1").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("2").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("3").with_text_size(25.))
-                                    .with_child(
-                                        Label::new("4").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("5").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("6").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Label::new("Perfect! We recognized the page you uploaded.\
-                                                      \nNow you would need to check if the number of total rows on the page (counting also the title, but not the page number and any headers) and the page number we calculated are correct, if not, correct them.\
-                                                      \nPress 'CONFIRM' to confirm, 'GO BACK' to return to '2' or 'LIBRARY' to return to the home page.")
-                                    .with_text_size(18.)
-                                    .with_text_color(Color::grey(0.9))
-                                    .with_line_break_mode(LineBreaking::WordWrap)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(Label::new(String::from("PAGE:")).with_text_color(Color::grey(0.9)))
-                                    .with_spacer(5.)
-                                    .with_child(ocr_form(data.get_current_book_info().ocr.first.unwrap(), data, OcrFormFields::PageNum))
-                                    .with_spacer(20.)
-                                    .with_child(Label::new(String::from("LINES:")).with_text_color(Color::grey(0.9)))
-                                    .with_spacer(5.)
-                                    .with_child(ocr_form(data.get_current_book_info().ocr.first.unwrap(), data, OcrFormFields::NumLines))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .must_fill_main_axis(true)
-                                    .with_flex_spacer(1.)
-                                    .with_child(Buttons::btn_ocr_form_close(true))
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_submit_ocr_form0())
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_remove_first_page())
-                                    .with_flex_spacer(1.)
-                            )
-                            .padding(20.)
-                    )
+                WizardStep::Done => {
+                    column.add_child(
+                        Label::new(format!("Pages used for the alignment: {}", pages))
+                            .with_text_color(Color::grey(0.9)),
+                    );
+                    column.add_spacer(20.);
                 }
-                4 =>{
-                    Box::new(
-                        Flex::column()
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(
-                                        Label::new("1").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("2").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("3").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("4").with_text_size(25.))
-                                    .with_child(
-                                        Label::new("5").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("6").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Label::new("Here you need to upload an image of a page from the book.\
-                                                      \nYou need to upload a picture of a page with little dialogue (possibly long periods).\
-                                                      \nThe page must not be truncated for some reason.\
-                                                      \nUsually any page taken in the middle of a chapter will meet the requirements.\
-                                                      \nPress 'LOAD PAGE' to load the page, 'GO BACK' to return to '3' or 'LIBRARY' to return to the home page.")
-                                    .with_text_size(18.)
-                                    .with_text_color(Color::grey(0.9))
-                                    .with_line_break_mode(LineBreaking::WordWrap)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .must_fill_main_axis(true)
-                                    .with_flex_spacer(1.)
-                                    .with_child(Buttons::btn_ocr_form_close(true))
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_ocr_form_prev())
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_add_other_page())
-                                    .with_flex_spacer(1.)
-                            )
-                            .padding(20.))
+                _ => {}
+            }
+
+            let mut buttons = Flex::row()
+                .must_fill_main_axis(true)
+                .with_flex_spacer(1.)
+                .with_child(Buttons::btn_wizard_close());
+            match wizard.step {
+                WizardStep::Intro => {
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_next("NEXT"));
                 }
-                5 =>{
-                    Box::new(
-                        Flex::column()
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(
-                                        Label::new("1").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("2").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("3").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("4").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("5").with_text_size(25.))
-                                    .with_child(
-                                        Label::new("6").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Label::new("Perfect! We recognized the page you uploaded.\
-                                                      \nNow you would need to check if the number of total rows on the page (counting also the title, but not the page number and any headers) we calculated is correct, if not, correct them.\
-                                                      \nPress 'CONFIRM' to confirm, 'GO BACK' to return to '4' or 'LIBRARY' to return to the home page.")
-                                    .with_text_size(18.)
-                                    .with_text_color(Color::grey(0.9))
-                                    .with_line_break_mode(LineBreaking::WordWrap)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(Label::new(String::from("LINES:")).with_text_color(Color::grey(0.9)))
-                                    .with_spacer(5.)
-                                    .with_child(ocr_form(data.get_current_book_info().ocr.other.unwrap(), data, OcrFormFields::NumLines))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .must_fill_main_axis(true)
-                                    .with_flex_spacer(1.)
-                                    .with_child(Buttons::btn_ocr_form_close(true))
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_submit_ocr_form1())
-                                    .with_spacer(5.)
-                                    .with_child(Buttons::btn_remove_other_page())
-                                    .with_flex_spacer(1.)
-                            )
-                            .padding(20.)
-                    )
+                WizardStep::FirstPage | WizardStep::OtherPage => {
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_back());
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_load_page());
                 }
-                6 =>{
-                    Box::new(
-                        Flex::column()
-                            .with_child(
-                                Flex::row()
-                                    .with_flex_spacer(1.)
-                                    .with_child(
-                                        Label::new("1").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("2").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("3").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("4").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("5").with_text_size(25.).with_text_color(Color::grey(0.5)))
-                                    .with_child(
-                                        Label::new("6").with_text_size(25.))
-                                    .with_flex_spacer(1.)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Label::new("Perfect!\
-                                                      \nEverything went well and now your book is aligned with the paper version.\
-                                                      \nNow clicking on a paragraph (only if it is after the first page you uploaded) will show you the page number on the physical book!\
-                                                      \nPress 'LIBRARY' to return to the home page. ")
-                                    .with_text_size(18.)
-                                    .with_text_color(Color::grey(0.9))
-                                    .with_line_break_mode(LineBreaking::WordWrap)
-                            )
-                            .with_spacer(20.)
-                            .with_child(
-                                Flex::row()
-                                    .must_fill_main_axis(true)
-                                    .with_flex_spacer(1.)
-                                    .with_child(Buttons::btn_ocr_form_close(false))
-                                    .with_flex_spacer(1.)
-                            )
-                            .padding(20.)
-                    )
+                WizardStep::CheckFirst | WizardStep::CheckOther => {
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_back());
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_confirm());
                 }
-                _ => {
-                    unreachable!()
+                WizardStep::Samples => {
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_add_pages());
+                    buttons.add_spacer(5.);
+                    buttons.add_child(Buttons::btn_wizard_next("FINISH"));
                 }
+                WizardStep::Done => {}
             }
+            buttons.add_flex_spacer(1.);
+            column.add_child(buttons);
+
+            Box::new(Scroll::new(column.padding(20.)).vertical())
         },
     )
 }
 
-enum OcrFormFields {
-    NumLines,
-    PageNum,
+/* I passi del wizard in fila, con quello corrente evidenziato */
+fn render_wizard_progress(wizard: &OcrWizard) -> impl Widget<ApplicationState> {
+    let mut steps = Flex::row().with_flex_spacer(1.);
+    for (i, step) in WIZARD_STEPS.iter().enumerate() {
+        let color = if *step == wizard.step {
+            Color::grey(0.9)
+        } else {
+            Color::grey(0.5)
+        };
+        if i > 0 {
+            steps.add_child(
+                Label::new("›")
+                    .with_text_size(18.)
+                    .with_text_color(Color::grey(0.5)),
+            );
+        }
+        steps.add_child(
+            Label::new(format!("{}. {}", i + 1, step.title()))
+                .with_text_size(18.)
+                .with_text_color(color)
+                .padding((8., 0.)),
+        );
+    }
+    steps.add_flex_spacer(1.);
+
+    let count = format!("Step {} of {}", wizard.number(), WIZARD_STEPS.len());
+    Flex::column()
+        .with_child(steps)
+        .with_spacer(5.)
+        .with_child(Label::new(count).with_text_color(Color::grey(0.5)))
 }
 
-fn ocr_form(
-    id: usize,
-    data: &ApplicationState,
-    field: OcrFormFields,
-) -> impl Widget<ApplicationState> {
-    macro_rules! mapping_lens {
-        ($field: tt) => {
-            lens!(ApplicationState, bookcase)
-                .then(lens!(BookCase, library))
-                .index(
-                    data.bookcase
-                        .library
-                        .iter()
-                        .position(|b| *b.path == data.book_to_align.get_path())
-                        .unwrap(),
-                )
-                .then(lens!(BookInfo, ocr))
-                .then(lens!(OcrData, mappings))
-                .index(id)
-                .then(lens!(Mapping, $field))
-        };
+fn wizard_description(step: WizardStep) -> &'static str {
+    match step {
+        WizardStep::Intro => "Thanks to this function while reading the selected epub you can know the corresponding page of the paper book at any time.\
+            \nIn order to be able to do this you will need to upload photos of a couple of pages of the paper book and you will have to verify the correctness of some information from the photos.\
+            \nPress 'NEXT' to proceed or 'LIBRARY' to return to the home page.",
+        WizardStep::FirstPage => "Here you need to upload a picture of the first page of the first chapter.\
+            \nFrom this page will start the alignment being the first for which there is a match between ebook and paper book.\
+            \nPress 'LOAD PAGE' to load the page, 'GO BACK' to return to the previous step or 'LIBRARY' to return to the home page.",
+        WizardStep::CheckFirst => "Perfect! We recognized the page you uploaded.\
+            \nCheck the text we read, then check if the number of total rows on the page (counting also the title, but not the page number and any headers) and the page number are correct, if not, correct them.\
+            \nPress 'CONFIRM' to confirm or 'GO BACK' to upload another photo.",
+        WizardStep::OtherPage => "Here you need to upload an image of a page from the book.\
+            \nYou need to upload a picture of a page with little dialogue (possibly long periods).\
+            \nThe page must not be truncated for some reason.\
+            \nUsually any page taken in the middle of a chapter will meet the requirements.\
+            \nPress 'LOAD PAGE' to load the page or 'GO BACK' to return to the first page.",
+        WizardStep::CheckOther => "Perfect! We recognized the page you uploaded.\
+            \nCheck the text we read, then check if the number of total rows on the page (counting also the title, but not the page number and any headers) is correct, if not, correct it.\
+            \nPress 'CONFIRM' to confirm or 'GO BACK' to upload another photo.",
+        WizardStep::Samples => "Your book is aligned with the paper version!\
+            \nYou can improve the estimate by adding photos of more pages, selecting several at once.\
+            \nPress 'ADD PAGES' to upload them or 'FINISH' when you are done.",
+        WizardStep::Done => "Everything went well and now your book is aligned with the paper version.\
+            \nNow clicking on a paragraph (only if it is after the first page you uploaded) will show you the page number on the physical book!\
+            \nPress 'LIBRARY' to return to the home page.",
     }
+}
 
-    match field {
-        OcrFormFields::NumLines => Container::new(
-            TextBox::new()
-                .with_formatter(CustomFormatter::new())
-                .update_data_while_editing(true)
-                .lens(mapping_lens!(page_lines)),
-        ),
-        OcrFormFields::PageNum => Container::new(
+/* La foto come l'ha letta Tesseract accanto al testo riconosciuto, con i valori da confermare */
+fn render_wizard_check(page: &WizardPage, step: WizardStep) -> impl Widget<ApplicationState> {
+    let mut row = Flex::row().cross_axis_alignment(CrossAxisAlignment::Start);
+    if let Some(preview) = &page.preview {
+        row.add_flex_child(wizard_image(preview, 400.), 1.);
+        row.add_spacer(20.);
+    }
+    row.add_flex_child(
+        Scroll::new(
+            Label::new(page.text.clone())
+                .with_text_color(Color::grey(0.9))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .vertical()
+        .fix_height(400.),
+        1.,
+    );
+
+    let mut inputs = Flex::row().with_flex_spacer(1.);
+    if step == WizardStep::CheckFirst {
+        inputs.add_child(Label::new("PAGE:").with_text_color(Color::grey(0.9)));
+        inputs.add_spacer(5.);
+        inputs.add_child(
             TextBox::new()
-                .with_formatter(CustomFormatter::new())
                 .update_data_while_editing(true)
-                .lens(mapping_lens!(page)),
-        ),
+                .lens(lens!(ApplicationState, wizard).then(lens!(OcrWizard, page_input))),
+        );
+        inputs.add_spacer(20.);
+    }
+    inputs.add_child(Label::new("LINES:").with_text_color(Color::grey(0.9)));
+    inputs.add_spacer(5.);
+    inputs.add_child(
+        TextBox::new()
+            .update_data_while_editing(true)
+            .lens(lens!(ApplicationState, wizard).then(lens!(OcrWizard, lines_input))),
+    );
+    inputs.add_flex_spacer(1.);
+
+    Flex::column()
+        .with_child(row)
+        .with_spacer(20.)
+        .with_child(inputs)
+}
+
+/* Le pagine già caricate, in miniatura */
+fn render_wizard_samples(wizard: &OcrWizard) -> impl Widget<ApplicationState> {
+    let mut row = Flex::row();
+    for page in wizard.pages.iter() {
+        if let Some(preview) = &page.preview {
+            row.add_child(wizard_image(preview, 150.));
+            row.add_spacer(10.);
+        }
     }
+    Scroll::new(row).horizontal()
+}
 
+fn wizard_image(preview: &ImageBuf, height: f64) -> impl Widget<ApplicationState> {
+    Image::new(preview.clone())
+        .fill_mode(FillStrat::Contain)
+        .fix_height(height)
 }
@@ -1,8 +1,8 @@
-use crate::book::page_element::ImageState::Present;
+use crate::algorithms::OcrAlgorithms;
 use crate::book::page_element::PageElement;
+use crate::layout::{Block, LayoutModel};
 use crate::{ApplicationState, ContentType};
 use druid::{im::Vector, Data, Lens, LocalizedString};
-use unicode_segmentation::UnicodeSegmentation;
 
 pub const WINDOW_TITLE: LocalizedString<ApplicationState> =
     LocalizedString::new("Ebook Reader");
@@ -17,7 +17,8 @@ pub struct View {
     window_size_home: (f64, f64),
     pub current_view: Vector<PageElement>,
     pub scroll_height: f64,
-    pub ocr_form_stage: usize,
+    pub visible: (usize, usize), // Primo e ultimo elemento visibili nella finestra
+    pub selected: Option<usize>, // Paragrafo cliccato dall'utente
 }
 
 impl View {
@@ -28,12 +29,14 @@ impl View {
             window_size_home: HOME_SIZE,
             current_view: Vector::new(),
             scroll_height: 0.0,
-            ocr_form_stage: 1,
+            visible: (0, 0),
+            selected: None,
         }
     }
 
     pub fn update_view(&mut self, vec: Vector<PageElement>) {
         self.current_view = vec;
+        self.selected = None;
     }
 
     pub fn get_window_size_view(&self) -> (f64, f64) {
@@ -85,17 +88,57 @@ impl View {
         element_number
     }
 
+    /*
+    Pagine cartacee (prima, ultima) su cui stanno gli elementi da first a last compresi,
+    None se il capitolo non è allineato
+    */
+    pub fn page_range(&self, first: usize, last: usize) -> Option<(usize, usize)> {
+        self.current_view
+            .iter()
+            .skip(first)
+            .take(last.saturating_sub(first) + 1)
+            .filter(|el| el.pg_offset.0 != 0)
+            .fold(None, |range, el| {
+                let (start, end) = Self::element_pages(el);
+                Some(match range {
+                    None => (start, end),
+                    Some((first, last)) => (first.min(start), last.max(end)),
+                })
+            })
+    }
+
+    /* Primo elemento che sta, anche in parte, sulla pagina cartacea page */
+    pub fn element_of_page(&self, page: usize) -> Option<usize> {
+        self.current_view.iter().position(|el| {
+            let (start, end) = Self::element_pages(el);
+            el.pg_offset.0 != 0 && start <= page && page <= end
+        })
+    }
+
+    /* Ultima pagina cartacea del capitolo, None se non è allineato */
+    pub fn last_page(&self) -> Option<usize> {
+        self.current_view
+            .iter()
+            .map(|el| el.pg_offset.0)
+            .max()
+            .filter(|page| *page != 0)
+    }
+
+    /* Un elemento con pg_offset.1 comincia nella pagina precedente */
+    pub fn element_pages(el: &PageElement) -> (usize, usize) {
+        if el.pg_offset.1 {
+            (el.pg_offset.0 - 1, el.pg_offset.0)
+        } else {
+            (el.pg_offset.0, el.pg_offset.0)
+        }
+    }
+
     pub fn ocr_offset_to_element(&self, mut offset: usize) -> usize {
         // A partire da un offset di words>5 trova il page element
         let mut page_element_number = 0;
         for page_element in &self.current_view {
             if let ContentType::Text(text) = page_element.content.clone() {
-                let long_words = text
-                    .text
-                    .split(" ")
-                    .map(|el| el.chars().filter(|c| c.is_alphabetic()).collect::<String>())
-                    .filter(|w| w.len() >= 5)
-                    .count();
+                let long_words = OcrAlgorithms::long_words(&text.text).len();
                 if (offset as i32 - long_words as i32) < 0 {
                     break;
                 } else {
@@ -107,57 +150,24 @@ impl View {
         page_element_number
     }
 
-    pub fn guess_lines(
-        &mut self,
-        max_chars: f64,
-        first: usize,
-        second: usize,
-        starting_page: usize,
-    ) -> Result<usize, ()> {
-        let mut guessed_lines = 0;
-        let mut curr_page = 1;
-
-        for el in self.current_view.iter_mut() {
-            if el.not_in_html {
-                continue;
-            }
-            if let ContentType::Text(text) = el.clone().content {
-                let mut element_lines =
-                    (text.text.trim().graphemes(true).count() as f64 / max_chars).ceil() as usize;
-                if element_lines == 0 {
-                    element_lines = 1;
-                } else if text.text.trim().graphemes(true).count() % (max_chars as usize) <= 3 {
-                    element_lines -= 1;
-                }
-
-                let max_lines = if curr_page == 1 { first } else { second };
-                guessed_lines = if (guessed_lines + element_lines) <= max_lines {
-                    guessed_lines + element_lines
-                } else {
-                    curr_page += 1;
-                    el.pg_offset.1 = true;
-                    guessed_lines + element_lines - max_lines
-                };
-            } else if let ContentType::Image(img_buf) = el.clone().content {
-                if let Present(img_buf) = img_buf {
-                    let element_lines = img_buf.height() / 20;
-                    let max_lines = if curr_page == 1 { first } else { second };
-                    guessed_lines = if (guessed_lines + element_lines) <= max_lines {
-                        guessed_lines + element_lines
-                    } else {
-                        curr_page += 1;
-                        element_lines
-                    };
-                }
-            }
-
-            el.pg_offset.0 = if starting_page == 0 {
-                0
+    /*
+    Assegna a ogni elemento la pagina cartacea in cui finisce secondo il modello di impaginazione,
+    restituisce il numero di pagine del capitolo
+    */
+    pub fn guess_lines(&mut self, model: &LayoutModel, starting_page: usize) -> Result<usize, ()> {
+        let blocks = self
+            .current_view
+            .iter()
+            .map(|el| model.block(el))
+            .collect::<Vec<Block>>();
+        let (pages, count) = model.paginate(&blocks);
+        for (el, (page, split)) in self.current_view.iter_mut().zip(pages) {
+            el.pg_offset = if starting_page == 0 {
+                (0, false)
             } else {
-                curr_page + starting_page - 1
-            }
+                (page + starting_page - 1, split)
+            };
         }
-
-        Ok(curr_page)
+        Ok(count)
     }
 }
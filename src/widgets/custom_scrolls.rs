@@ -1,4 +1,5 @@
-use crate::app::{ApplicationState, SCROLL_REQUEST, TRIGGER_OFF, TRIGGER_ON};
+use crate::app::{ApplicationState, ReaderWindow, SCROLL_REQUEST, TRIGGER_OFF, TRIGGER_ON};
+use crate::errors::{log, Severity};
 use crate::widgets::custom_label::UPDATE_SIZE;
 use druid::widget::{Axis, Scroll};
 use druid::{
@@ -21,10 +22,22 @@ impl<W: Widget<ApplicationState>> BetterScroll<W> {
 impl<W: Widget<ApplicationState>> Widget<ApplicationState> for BetterScroll<W> {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut ApplicationState, env: &Env) {
         self.child.event(ctx, event, data, env);
+        /* Elementi visibili, per la barra di stato */
+        let offset = self.child.offset_for_axis(Axis::Vertical);
+        let visible = (
+            data.view.get_element_from_offset(offset),
+            data.view.get_element_from_offset(offset + ctx.size().height),
+        );
+        if data.view.visible != visible {
+            data.view.visible = visible;
+        }
         match event {
+            Event::MouseUp(e) if e.button.is_left() => {
+                data.view.selected = Some(data.view.get_element_from_offset(offset + e.pos.y));
+            }
             Event::WindowCloseRequested => {
                 if data.modified.len() > 0 || data.edit {
-                    println!("Window close not implemented for unsaved edits/edit mode")
+                    log(Severity::Warning, "Unsaved edits discarded on close")
                 } else {
                     data.book_to_view.get_mut_nav().set_element_number(
                         data.view
@@ -176,3 +189,79 @@ impl<W: Widget<ApplicationState>> Widget<ApplicationState> for SyncScroll<W> {
         self.child.paint(ctx, data, env);
     }
 }
+
+/*
+Scroll delle finestre secondarie: salva la posizione nel ReaderWindow invece che in book_to_view
+*/
+pub struct ReaderScroll<W: Widget<ReaderWindow>> {
+    child: Scroll<ReaderWindow, W>,
+}
+
+impl<W: Widget<ReaderWindow>> ReaderScroll<W> {
+    pub fn new(widget: W) -> Self {
+        ReaderScroll {
+            child: Scroll::new(widget).vertical(),
+        }
+    }
+}
+
+impl<W: Widget<ReaderWindow>> Widget<ReaderWindow> for ReaderScroll<W> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut ReaderWindow, env: &Env) {
+        self.child.event(ctx, event, data, env);
+        match event {
+            Event::WindowCloseRequested => {
+                data.book.get_mut_nav().set_element_number(
+                    data.view
+                        .get_element_from_offset(self.child.offset_for_axis(Axis::Vertical)),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &ReaderWindow,
+        env: &Env,
+    ) {
+        self.child.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &ReaderWindow,
+        data: &ReaderWindow,
+        env: &Env,
+    ) {
+        if data.view.current_view.iter().any(|a| a.size.is_some())
+            && !old_data.view.current_view.iter().any(|a| a.size.is_some())
+        {
+            self.child.scroll_to_on_axis(
+                Axis::Vertical,
+                data.view
+                    .get_element_offset(data.book.get_nav().get_element_numer()),
+            );
+            ctx.request_paint();
+        }
+        self.child.update(ctx, old_data, data, env)
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &ReaderWindow,
+        env: &Env,
+    ) -> Size {
+        let size = self.child.layout(ctx, bc, data, env);
+        ctx.submit_command(UPDATE_SIZE);
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &ReaderWindow, env: &Env) {
+        self.child.paint(ctx, data, env);
+    }
+}
@@ -0,0 +1,213 @@
+use druid::{im::Vector, Data, ImageBuf, Lens};
+
+const MIN_PAGE_LINES: usize = 3; // Meno righe non bastano per riconoscere le pagine simili
+
+/*
+Passi del wizard di allineamento con il libro cartaceo, nell'ordine in cui vengono mostrati
+*/
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq)]
+pub enum WizardStep {
+    Intro,
+    FirstPage,  // Foto della prima pagina del primo capitolo
+    CheckFirst, // Testo letto, numero di pagina e righe della prima pagina
+    OtherPage,  // Foto di una pagina piena in mezzo a un capitolo
+    CheckOther, // Testo letto e righe della pagina piena
+    Samples,    // Altre pagine facoltative per migliorare la stima
+    Done,
+}
+
+pub const WIZARD_STEPS: [WizardStep; 7] = [
+    WizardStep::Intro,
+    WizardStep::FirstPage,
+    WizardStep::CheckFirst,
+    WizardStep::OtherPage,
+    WizardStep::CheckOther,
+    WizardStep::Samples,
+    WizardStep::Done,
+];
+
+impl WizardStep {
+    pub fn title(&self) -> &'static str {
+        match self {
+            WizardStep::Intro => "Start",
+            WizardStep::FirstPage => "First page",
+            WizardStep::CheckFirst => "Check",
+            WizardStep::OtherPage => "Full page",
+            WizardStep::CheckOther => "Check",
+            WizardStep::Samples => "More pages",
+            WizardStep::Done => "Done",
+        }
+    }
+
+    /* Il passo successivo, per quelli che non aspettano una foto */
+    fn next(&self) -> Option<WizardStep> {
+        match self {
+            WizardStep::Intro => Some(WizardStep::FirstPage),
+            WizardStep::CheckFirst => Some(WizardStep::OtherPage),
+            WizardStep::CheckOther => Some(WizardStep::Samples),
+            WizardStep::Samples => Some(WizardStep::Done),
+            _ => None,
+        }
+    }
+
+    /* Dopo le pagine di prova l'allineamento è fatto e non si torna indietro */
+    fn back(&self) -> Option<WizardStep> {
+        match self {
+            WizardStep::FirstPage => Some(WizardStep::Intro),
+            WizardStep::CheckFirst => Some(WizardStep::FirstPage),
+            WizardStep::OtherPage => Some(WizardStep::CheckFirst),
+            WizardStep::CheckOther => Some(WizardStep::OtherPage),
+            _ => None,
+        }
+    }
+
+    /* Il passo aspetta la foto di una pagina */
+    pub fn needs_photo(&self) -> bool {
+        matches!(self, WizardStep::FirstPage | WizardStep::OtherPage)
+    }
+}
+
+/* Una pagina fotografata e accettata: come l'ha vista Tesseract e il Mapping che ne è nato */
+#[derive(Clone, Data)]
+pub struct WizardPage {
+    pub preview: Option<ImageBuf>,
+    pub text: String,
+    pub mapping: usize, // Indice in OcrData::mappings
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct OcrWizard {
+    pub step: WizardStep,
+    pub loading: bool,             // Tesseract sta leggendo le foto
+    pub error: Option<String>,     // Errore del passo corrente, mostrato nel passo stesso
+    pub preview: Option<ImageBuf>, // Anteprima dell'ultima foto, in attesa del testo
+    pub pages: Vector<WizardPage>,
+    pub page_input: String,
+    pub lines_input: String,
+}
+
+impl Default for OcrWizard {
+    fn default() -> Self {
+        OcrWizard {
+            step: WizardStep::Intro,
+            loading: false,
+            error: None,
+            preview: None,
+            pages: Vector::new(),
+            page_input: String::new(),
+            lines_input: String::new(),
+        }
+    }
+}
+
+impl OcrWizard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Numero del passo corrente, da 1 */
+    pub fn number(&self) -> usize {
+        WIZARD_STEPS
+            .iter()
+            .position(|step| *step == self.step)
+            .unwrap_or(0)
+            + 1
+    }
+
+    pub fn start_loading(&mut self) {
+        self.loading = true;
+        self.error = None;
+    }
+
+    pub fn fail(&mut self, error: String) {
+        self.loading = false;
+        self.preview = None;
+        self.error = Some(error);
+    }
+
+    /*
+    Una foto letta e riconosciuta nel libro: il testo va confermato insieme al numero di pagina
+    e alle righe contate dall'OCR, che l'utente può correggere
+    */
+    pub fn page_read(&mut self, text: &str, mapping: usize, page: usize, lines: usize) {
+        self.pages.push_back(WizardPage {
+            preview: self.preview.take(),
+            text: text.to_string(),
+            mapping,
+        });
+        self.page_input = match page {
+            0 => String::new(),
+            page => page.to_string(),
+        };
+        self.lines_input = lines.to_string();
+        self.loading = false;
+        self.error = None;
+        self.step = match self.step {
+            WizardStep::FirstPage => WizardStep::CheckFirst,
+            WizardStep::OtherPage => WizardStep::CheckOther,
+            step => step,
+        };
+    }
+
+    /* Pagine in più lette al passo Samples */
+    pub fn sample_read(&mut self, text: &str, mapping: usize, preview: Option<ImageBuf>) {
+        self.pages.push_back(WizardPage {
+            preview,
+            text: text.to_string(),
+            mapping,
+        });
+    }
+
+    /* La pagina da confermare nei passi di controllo */
+    pub fn current_page(&self) -> Option<&WizardPage> {
+        match self.step {
+            WizardStep::CheckFirst | WizardStep::CheckOther => self.pages.last(),
+            _ => None,
+        }
+    }
+
+    /* Numero di pagina (0 se non richiesto) e righe inseriti nel passo di controllo */
+    pub fn check_inputs(&self) -> Result<(usize, usize), String> {
+        let lines = self.lines_input.trim();
+        let lines = match lines.parse::<usize>() {
+            Ok(lines) if lines >= MIN_PAGE_LINES => lines,
+            _ => {
+                return Err(format!(
+                    "'{}' is not a valid number of lines, a page has at least {}",
+                    lines, MIN_PAGE_LINES
+                ))
+            }
+        };
+        if self.step != WizardStep::CheckFirst {
+            return Ok((0, lines));
+        }
+        match self.page_input.trim().parse::<usize>() {
+            Ok(page) if page > 0 => Ok((page, lines)),
+            _ => Err("Type the page number printed on the photo".to_string()),
+        }
+    }
+
+    pub fn next(&mut self) {
+        if let Some(step) = self.step.next() {
+            self.step = step;
+            self.error = None;
+        }
+    }
+
+    /*
+    Torna al passo precedente. Tornando da un controllo la foto viene scartata: restituisce il
+    Mapping da togliere
+    */
+    pub fn back(&mut self) -> Option<usize> {
+        let step = self.step.back()?;
+        let discarded = match self.step {
+            WizardStep::CheckFirst | WizardStep::CheckOther => {
+                self.pages.pop_back().map(|page| page.mapping)
+            }
+            _ => None,
+        };
+        self.step = step;
+        self.error = None;
+        discarded
+    }
+}